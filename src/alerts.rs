@@ -0,0 +1,203 @@
+use crate::collectors::Sample;
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct AlertConfig {
+    // Percent
+    pub cpu_usage: Option<u8>,
+    pub ram_usage: Option<u8>,
+    pub gpu_usage: Option<u8>,
+    // Degrees Celsius
+    pub gpu_temp: Option<u8>,
+    // How far a value has to drop below its threshold before the alert clears
+    pub hysteresis: u8,
+}
+
+impl Default for AlertConfig {
+    fn default() -> Self {
+        AlertConfig {
+            cpu_usage: None,
+            ram_usage: None,
+            gpu_usage: None,
+            gpu_temp: None,
+            hysteresis: 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertKind {
+    Cpu = 0,
+    Ram = 1,
+    Gpu = 2,
+    GpuTemp = 3,
+}
+
+impl AlertKind {
+    // Same as the threshold's key in [alerts]
+    pub fn name(self) -> &'static str {
+        match self {
+            AlertKind::Cpu => "cpu_usage",
+            AlertKind::Ram => "ram_usage",
+            AlertKind::Gpu => "gpu_usage",
+            AlertKind::GpuTemp => "gpu_temp",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            AlertKind::Cpu => "CPU usage",
+            AlertKind::Ram => "RAM usage",
+            AlertKind::Gpu => "GPU usage",
+            AlertKind::GpuTemp => "GPU temperature",
+        }
+    }
+
+    // Thresholds are always Celsius, see Encoder::encode
+    pub fn unit(self) -> &'static str {
+        match self {
+            AlertKind::GpuTemp => "°C",
+            _ => "%",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Alert {
+    pub kind: AlertKind,
+    // false once the value is back below threshold - hysteresis
+    pub active: bool,
+    pub value: u8,
+    pub threshold: u8,
+}
+
+// Only reports changes, so a value hovering around its threshold doesn't spam the Flipper
+pub struct Alerts {
+    config: AlertConfig,
+    active: [bool; 4],
+}
+
+impl Alerts {
+    pub fn new(config: AlertConfig) -> Self {
+        Alerts {
+            config,
+            active: [false; 4],
+        }
+    }
+
+    pub fn check(&mut self, sample: &Sample) -> Vec<Alert> {
+        let Some(system) = &sample.system else {
+            return Vec::new();
+        };
+
+        let checks = [
+            (AlertKind::Cpu, self.config.cpu_usage, system.cpu_usage),
+            (AlertKind::Ram, self.config.ram_usage, system.ram_usage),
+            (AlertKind::Gpu, self.config.gpu_usage, system.gpu_usage),
+            (AlertKind::GpuTemp, self.config.gpu_temp, system.gpu_temp),
+        ];
+
+        let mut alerts = Vec::new();
+        for (kind, threshold, value) in checks {
+            // u8::MAX marks values the collectors couldn't read
+            let Some(threshold) = threshold.filter(|_| value != u8::MAX) else {
+                continue;
+            };
+
+            let active = &mut self.active[kind as usize];
+            let now_active = match *active {
+                false => value > threshold,
+                true => value > threshold.saturating_sub(self.config.hysteresis),
+            };
+
+            if now_active != *active {
+                *active = now_active;
+                alerts.push(Alert {
+                    kind,
+                    active: now_active,
+                    value,
+                    threshold,
+                });
+            }
+        }
+        alerts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collectors::system_info::SystemInfo;
+
+    fn sample(cpu_usage: u8, gpu_temp: u8) -> Sample {
+        Sample {
+            system: Some(SystemInfo {
+                cpu_usage,
+                gpu_temp,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn alerts() -> Alerts {
+        Alerts::new(AlertConfig {
+            cpu_usage: Some(90),
+            gpu_temp: Some(80),
+            ..Default::default()
+        })
+    }
+
+    fn changes(alerts: &mut Alerts, cpu_usage: u8) -> Vec<bool> {
+        alerts
+            .check(&sample(cpu_usage, 50))
+            .iter()
+            .map(|alert| alert.active)
+            .collect()
+    }
+
+    #[test]
+    fn raises_above_the_threshold() {
+        let mut alerts = alerts();
+        assert!(alerts.check(&sample(90, 50)).is_empty());
+
+        let raised = alerts.check(&sample(91, 81));
+        assert_eq!(raised.len(), 2);
+        assert_eq!(raised[0].kind, AlertKind::Cpu);
+        assert!(raised[0].active);
+        assert_eq!((raised[0].value, raised[0].threshold), (91, 90));
+        assert_eq!(raised[1].kind, AlertKind::GpuTemp);
+    }
+
+    #[test]
+    fn clears_below_threshold_minus_hysteresis() {
+        let mut alerts = alerts();
+        assert_eq!(changes(&mut alerts, 95), [true]);
+        assert_eq!(changes(&mut alerts, 86), Vec::<bool>::new());
+        assert_eq!(changes(&mut alerts, 85), [false]);
+        assert_eq!(changes(&mut alerts, 85), Vec::<bool>::new());
+    }
+
+    #[test]
+    fn hovering_around_the_threshold_doesnt_flap() {
+        let mut alerts = alerts();
+        let changed: Vec<Vec<bool>> = [91, 89, 92, 88, 90, 87]
+            .into_iter()
+            .map(|cpu_usage| changes(&mut alerts, cpu_usage))
+            .collect();
+        assert_eq!(changed.concat(), [true]);
+    }
+
+    #[test]
+    fn unknown_values_and_missing_thresholds_are_skipped() {
+        let mut alerts = alerts();
+        assert!(alerts.check(&sample(u8::MAX, u8::MAX)).is_empty());
+        assert!(alerts.check(&Sample::default()).is_empty());
+
+        // RAM has no threshold configured
+        let mut sample = sample(0, 0);
+        sample.system.as_mut().unwrap().ram_usage = 100;
+        assert!(alerts.check(&sample).is_empty());
+    }
+}
@@ -0,0 +1,213 @@
+use crate::config::Config;
+use crate::protocol;
+use crate::transport;
+use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[derive(Parser, Debug)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Path to the config file [default: <config dir>/flipper-pc-monitor/config.toml]
+    #[arg(short, long, global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Log filter, e.g. `info` or `btleplug=debug` (overrides -v and RUST_LOG)
+    #[arg(long, global = true)]
+    pub log_level: Option<String>,
+
+    /// More logging, -vv for everything including dependencies
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    pub verbose: u8,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Stream system info to the Flipper (default)
+    Run(RunArgs),
+    /// List nearby Flippers running PC Monitor
+    Scan {
+        /// How long to scan for, in seconds
+        #[arg(long, default_value_t = 10)]
+        timeout: u64,
+    },
+    /// Pair with a Flipper (from `devices`, or the first found) and keep the bond, so writes
+    /// aren't rejected and it reconnects after a reboot
+    Pair {
+        /// How long to scan for, in seconds
+        #[arg(long, default_value_t = 10)]
+        timeout: u64,
+    },
+    /// Remove the bond `pair` created, for every paired Flipper unless one is given
+    ForgetDevice {
+        /// Address or name of the Flipper
+        device: Option<String>,
+    },
+    /// Collect without a Flipper and forward the samples to a hub, see `[agent]`
+    Agent {
+        /// host:port of the hub, overrides `agent.hub`. Without either, hubs find the agent
+        /// over mDNS
+        #[arg(long)]
+        hub: Option<String>,
+    },
+    /// Collect a single sample and print it along with the encoded packet
+    Test,
+    /// List detected GPUs and their indices for `gpu_index`
+    Gpus,
+    /// Summarize samples recorded by the `[sqlite]` sink
+    History {
+        /// Part of the metric name, e.g. `cpu_usage` or `disk[/home]`
+        #[arg(long)]
+        metric: String,
+        /// How far back to look, e.g. `90m`, `24h` or `7d`
+        #[arg(long, default_value = "24h", value_parser = parse_duration)]
+        last: Duration,
+        /// Print every recorded value as CSV instead of min/avg/max
+        #[arg(long)]
+        raw: bool,
+    },
+    /// Start `run` automatically: a systemd user unit on Linux, a service on Windows,
+    /// a LaunchAgent on macOS
+    #[cfg(any(target_os = "linux", target_os = "macos", windows))]
+    #[command(alias = "install-agent")]
+    InstallService,
+    /// Remove what `install-service` set up
+    #[cfg(any(target_os = "linux", target_os = "macos", windows))]
+    #[command(alias = "uninstall-agent")]
+    UninstallService,
+    /// Entry point used by the Windows service manager
+    #[cfg(windows)]
+    #[command(hide = true)]
+    Service,
+}
+
+// A number with an s, m, h or d suffix
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let (number, unit) = value.split_at(value.trim_end_matches(char::is_alphabetic).len());
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("expected e.g. 30m or 24h, got {value}"))?;
+    let multiplier = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(format!("unknown unit in {value}, use s, m, h or d")),
+    };
+    let seconds = number
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("{value} is too long"))?;
+    Ok(Duration::from_secs(seconds))
+}
+
+#[derive(Args, Debug, Default)]
+pub struct RunArgs {
+    /// How to reach the Flipper
+    #[arg(long, value_enum)]
+    pub transport: Option<transport::Kind>,
+
+    /// Print encoded packets instead of sending them, same as `--transport dry-run`
+    #[arg(long, conflicts_with = "transport")]
+    pub dry_run: bool,
+
+    /// Only connect to this device (BLE name/address/id, serial port or host:port), can be repeated
+    #[arg(long = "address", value_name = "ADDRESS")]
+    pub addresses: Vec<String>,
+
+    /// Wire format, v2 needs a PC Monitor app with TLV support, older apps that say so get theirs
+    #[arg(long, value_enum)]
+    pub protocol: Option<protocol::Version>,
+
+    /// Wrap packets in frames with a sequence number and CRC16
+    #[arg(long)]
+    pub framing: bool,
+
+    /// Only send fields that changed since the previous packet (protocol v2)
+    #[arg(long)]
+    pub delta: bool,
+
+    /// Time between updates, in milliseconds
+    #[arg(long)]
+    pub interval: Option<u64>,
+
+    /// Also send per-core usage for up to this many cores (0 disables)
+    #[arg(long)]
+    pub cores: Option<u8>,
+
+    /// Index of the GPU to report, see the `gpus` subcommand
+    #[arg(long)]
+    pub gpu_index: Option<usize>,
+
+    /// Mount point to report disk usage for, can be repeated [default: / or C:]
+    #[arg(long = "disk", value_name = "MOUNT_POINT")]
+    pub disks: Vec<String>,
+
+    /// Append every sample to this CSV file, rotated once it reaches `csv.max_size`
+    #[arg(long, value_name = "PATH")]
+    pub log_csv: Option<PathBuf>,
+
+    /// Run under a service manager: report readiness, feed the watchdog and publish status
+    #[arg(long)]
+    pub daemon: bool,
+
+    /// Collect a single sample, print it with the detected GPUs as JSON and exit
+    #[arg(long)]
+    pub once: bool,
+
+    /// Show a tray/menu bar icon with the connection state and pause/quit controls
+    #[cfg(feature = "tray")]
+    #[arg(long)]
+    pub tray: bool,
+}
+
+impl RunArgs {
+    pub fn apply(self, config: &mut Config) {
+        if let Some(transport) = self.transport {
+            config.transport = transport;
+        }
+
+        if self.dry_run {
+            config.transport = transport::Kind::DryRun;
+        }
+
+        if !self.addresses.is_empty() {
+            config.address = None;
+            config.devices = self.addresses;
+        }
+
+        if let Some(protocol) = self.protocol {
+            config.protocol = protocol;
+        }
+
+        if self.framing {
+            config.framing = true;
+        }
+
+        if self.delta {
+            config.delta = true;
+        }
+
+        if let Some(interval) = self.interval {
+            config.interval = interval;
+        }
+
+        if let Some(cores) = self.cores {
+            config.cores = cores;
+        }
+
+        if let Some(gpu_index) = self.gpu_index {
+            config.gpu_index = gpu_index;
+        }
+
+        if !self.disks.is_empty() {
+            config.disks = self.disks;
+        }
+
+        if let Some(path) = self.log_csv {
+            config.csv.get_or_insert_with(Default::default).path = path;
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use super::{Collector, Metric};
+use async_trait::async_trait;
+use battery::units::{ratio::percent, time::minute};
+use battery::State;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BatteryInfo {
+    pub level: u8,
+    pub charging: u8,
+    // Minutes until empty (or full while charging), u16::MAX if unknown
+    pub time_remaining: u16,
+}
+
+impl BatteryInfo {
+    pub fn get_battery_info() -> Option<Self> {
+        let manager = battery::Manager::new()
+            .map_err(|e| tracing::debug!("Failed to open battery manager: {e}"))
+            .ok()?;
+        let battery = manager
+            .batteries()
+            .map_err(|e| tracing::debug!("Failed to list batteries: {e}"))
+            .ok()?
+            .flatten()
+            .next()?;
+
+        let charging = matches!(battery.state(), State::Charging | State::Full);
+        let time_remaining = match charging {
+            true => battery.time_to_full(),
+            false => battery.time_to_empty(),
+        };
+
+        Some(BatteryInfo {
+            level: crate::helpers::percent(
+                "battery_level",
+                battery.state_of_charge().get::<percent>() as f64,
+            ),
+            charging: charging as u8,
+            time_remaining: match time_remaining {
+                Some(time) => (time.get::<minute>() as u64).min(u16::MAX as u64 - 1) as u16,
+                None => u16::MAX,
+            },
+        })
+    }
+}
+
+pub struct BatteryCollector;
+
+#[async_trait]
+impl Collector for BatteryCollector {
+    fn name(&self) -> &'static str {
+        "battery"
+    }
+
+    async fn collect(&mut self) -> Metric {
+        Metric::Battery(BatteryInfo::get_battery_info())
+    }
+}
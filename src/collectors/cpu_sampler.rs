@@ -0,0 +1,76 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+use sysinfo::{CpuRefreshKind, RefreshKind, MINIMUM_CPU_UPDATE_INTERVAL};
+use tokio::sync::watch;
+
+#[derive(Debug, Clone, Default)]
+pub struct CpuSnapshot {
+    // Percent per core, averaged over the sampling window
+    pub core_usage: Vec<f32>,
+    // MHz per core, latest reading
+    pub frequencies: Vec<u64>,
+}
+
+// sysinfo only gets CPU usage right as the difference of two refreshes at least
+// MINIMUM_CPU_UPDATE_INTERVAL apart, so a task keeps refreshing on its own and
+// averages the readings over roughly one collection interval
+pub struct CpuSampler {
+    latest: watch::Receiver<Option<CpuSnapshot>>,
+}
+
+impl CpuSampler {
+    pub fn spawn(window: Duration) -> Self {
+        let (sender, latest) = watch::channel(None);
+        let readings =
+            (window.as_millis() / MINIMUM_CPU_UPDATE_INTERVAL.as_millis()).max(1) as usize;
+
+        tokio::spawn(async move {
+            let refresh = CpuRefreshKind::new().with_cpu_usage().with_frequency();
+            let mut system =
+                sysinfo::System::new_with_specifics(RefreshKind::new().with_cpu(refresh));
+            let mut history: VecDeque<Vec<f32>> = VecDeque::with_capacity(readings);
+
+            let mut ticker = tokio::time::interval(MINIMUM_CPU_UPDATE_INTERVAL);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            // The first tick completes right away, new_with_specifics was the baseline refresh
+            ticker.tick().await;
+
+            loop {
+                ticker.tick().await;
+                system.refresh_cpu_specifics(refresh);
+
+                if history.len() == readings {
+                    history.pop_front();
+                }
+                history.push_back(system.cpus().iter().map(|c| c.cpu_usage()).collect());
+
+                let cores = system.cpus().len();
+                let core_usage = (0..cores)
+                    .map(|core| {
+                        history.iter().filter_map(|r| r.get(core)).sum::<f32>()
+                            / history.len() as f32
+                    })
+                    .collect();
+                let snapshot = CpuSnapshot {
+                    core_usage,
+                    frequencies: system.cpus().iter().map(|c| c.frequency()).collect(),
+                };
+
+                // The collector is gone
+                if sender.send(Some(snapshot)).is_err() {
+                    return;
+                }
+            }
+        });
+
+        CpuSampler { latest }
+    }
+
+    // Only waits on the very first call, until the first measurement is in
+    pub async fn latest(&mut self) -> CpuSnapshot {
+        if let Err(e) = self.latest.wait_for(Option::is_some).await {
+            tracing::error!("CPU sampler stopped: {e}");
+        }
+        self.latest.borrow().clone().unwrap_or_default()
+    }
+}
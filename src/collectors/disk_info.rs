@@ -0,0 +1,99 @@
+use super::{Collector, Metric};
+use crate::helpers::{percent, pop_4u8, pop_8u8};
+use crate::units::Units;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DiskInfo {
+    pub label: [u8; 8],
+    pub disk_max: u16,
+    pub disk_usage: u8,
+    pub disk_unit: [u8; 4],
+    // Exact size for the v2 wide fields, left out of the v1 DiskStruct
+    #[serde(skip)]
+    pub disk_bytes: u64,
+}
+
+impl DiskInfo {
+    pub fn default_mount_points() -> Vec<String> {
+        #[cfg(target_os = "windows")]
+        {
+            vec!["C:".to_owned()]
+        }
+
+        #[cfg(not(target_os = "windows"))]
+        {
+            vec!["/".to_owned()]
+        }
+    }
+
+    fn normalize(mount_point: &str) -> &str {
+        mount_point.trim_end_matches(['/', '\\'])
+    }
+
+    pub fn get_disk_info(
+        disks: &mut sysinfo::Disks,
+        mount_points: &[String],
+        units: Units,
+    ) -> Vec<Self> {
+        // Picks up drives that were mounted after startup
+        disks.refresh_list();
+
+        mount_points
+            .iter()
+            .filter_map(|mount_point| {
+                let disk = disks.iter().find(|d| {
+                    Self::normalize(&d.mount_point().to_string_lossy())
+                        == Self::normalize(mount_point)
+                })?;
+
+                let disk_max = disk.total_space();
+                let disk_used = disk_max.saturating_sub(disk.available_space());
+                let disk_scaled = units.scale(disk_max);
+
+                Some(DiskInfo {
+                    label: pop_8u8(mount_point.as_bytes()),
+                    disk_max: disk_scaled.tenths,
+                    disk_usage: match disk_max {
+                        0 => u8::MAX,
+                        _ => percent("disk_usage", disk_used as f64 / disk_max as f64 * 100.0),
+                    },
+                    disk_unit: pop_4u8(disk_scaled.unit.as_bytes()),
+                    disk_bytes: disk_max,
+                })
+            })
+            .collect()
+    }
+}
+
+pub struct DiskCollector {
+    disks: sysinfo::Disks,
+    mount_points: Vec<String>,
+    units: Units,
+}
+
+impl DiskCollector {
+    pub fn new(mount_points: Vec<String>, units: Units) -> Self {
+        DiskCollector {
+            disks: sysinfo::Disks::new_with_refreshed_list(),
+            mount_points,
+            units,
+        }
+    }
+}
+
+#[async_trait]
+impl Collector for DiskCollector {
+    fn name(&self) -> &'static str {
+        "disks"
+    }
+
+    async fn collect(&mut self) -> Metric {
+        Metric::Disks(DiskInfo::get_disk_info(
+            &mut self.disks,
+            &self.mount_points,
+            self.units,
+        ))
+    }
+}
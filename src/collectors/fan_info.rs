@@ -0,0 +1,118 @@
+use super::{Collector, Metric};
+use crate::helpers::pop_8u8;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FanInfo {
+    pub label: [u8; 8],
+    pub rpm: u16,
+}
+
+#[cfg(target_os = "windows")]
+#[derive(serde::Deserialize, Debug)]
+#[serde(rename = "Sensor", rename_all = "PascalCase")]
+struct LhmSensor {
+    name: String,
+    value: f32,
+}
+
+impl FanInfo {
+    fn new(label: &str, rpm: f64) -> Self {
+        FanInfo {
+            label: pop_8u8(label.as_bytes()),
+            rpm: rpm.round().clamp(0.0, u16::MAX as f64) as u16,
+        }
+    }
+
+    pub async fn get_fan_info() -> Vec<Self> {
+        #[cfg(target_os = "linux")]
+        {
+            Self::get_hwmon_fan_info()
+        }
+
+        #[cfg(target_os = "windows")]
+        {
+            Self::get_lhm_fan_info().await
+        }
+
+        #[cfg(target_os = "macos")]
+        {
+            Self::get_smc_fan_info()
+        }
+
+        #[cfg(not(any(target_os = "linux", target_os = "windows", target_os = "macos")))]
+        {
+            Vec::new()
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn get_hwmon_fan_info() -> Vec<Self> {
+        crate::helpers::read_hwmon_sensors("fan")
+            .into_iter()
+            .map(|sensor| {
+                FanInfo::new(
+                    sensor.label.as_deref().unwrap_or(&sensor.id),
+                    sensor.value as f64,
+                )
+            })
+            .collect()
+    }
+
+    // Windows doesn't expose fans itself, LibreHardwareMonitor publishes them over WMI while running
+    #[cfg(target_os = "windows")]
+    async fn get_lhm_fan_info() -> Vec<Self> {
+        let query = || -> Result<Vec<LhmSensor>, wmi::WMIError> {
+            let wmi = wmi::WMIConnection::with_namespace_path(
+                "root\\LibreHardwareMonitor",
+                wmi::COMLibrary::new()?,
+            )?;
+            wmi.raw_query("SELECT Name, Value FROM Sensor WHERE SensorType = 'Fan'")
+        };
+
+        // COM objects aren't Send, so the query runs on a blocking thread
+        match tokio::task::spawn_blocking(query).await {
+            Ok(Ok(sensors)) => sensors
+                .into_iter()
+                .map(|sensor| FanInfo::new(&sensor.name, sensor.value as f64))
+                .collect(),
+            Ok(Err(e)) => {
+                tracing::debug!("LibreHardwareMonitor not available: {e}");
+                Vec::new()
+            }
+            Err(e) => {
+                tracing::warn!("Failed to query fans: {e}");
+                Vec::new()
+            }
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    fn get_smc_fan_info() -> Vec<Self> {
+        let fans = macsmc::Smc::connect().and_then(|mut smc| {
+            smc.fans()?
+                .enumerate()
+                .map(|(index, fan)| Ok(FanInfo::new(&format!("fan{index}"), fan?.actual.0 as f64)))
+                .collect()
+        });
+
+        fans.unwrap_or_else(|e| {
+            tracing::debug!("Failed to read fans from the SMC: {e}");
+            Vec::new()
+        })
+    }
+}
+
+pub struct FanCollector;
+
+#[async_trait]
+impl Collector for FanCollector {
+    fn name(&self) -> &'static str {
+        "fans"
+    }
+
+    async fn collect(&mut self) -> Metric {
+        Metric::Fans(FanInfo::get_fan_info().await)
+    }
+}
@@ -0,0 +1,797 @@
+use super::nvidia_smi::{SmiGpu, SmiStream};
+#[cfg(target_os = "linux")]
+use crate::helpers::read_sysfs_u64;
+use nvml_wrapper::enum_wrappers::device::{Clock, TemperatureSensor};
+use nvml_wrapper::Nvml;
+use serde::Serialize;
+use std::sync::{Mutex, Once, OnceLock};
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+
+const MIB_TO_BYTES: u64 = 1024 * 1024;
+
+// APUs and iGPUs only get a small BIOS carve-out as "VRAM", dedicated cards have far more
+#[cfg(not(target_os = "macos"))]
+const APU_VRAM_MAX_MIB: u64 = 2048;
+
+// Initialized on first use and kept for the lifetime of the process,
+// None if the NVIDIA driver library isn't available
+static NVML: OnceLock<Option<Nvml>> = OnceLock::new();
+
+pub(crate) fn nvml() -> Option<&'static Nvml> {
+    NVML.get_or_init(|| {
+        Nvml::init()
+            .map_err(|e| tracing::debug!("NVML not available, falling back to nvidia-smi: {e}"))
+            .ok()
+    })
+    .as_ref()
+}
+
+// Runs every tick, so a missing nvidia-smi is only reported once
+static NVIDIA_SMI_MISSING: Once = Once::new();
+
+// Started on the first refresh without NVML and again after it stopped for lack of readers,
+// Some(None) if nvidia-smi couldn't be started
+static NVIDIA_SMI_STREAM: Mutex<Option<Option<SmiStream>>> = Mutex::new(None);
+
+#[cfg(target_os = "windows")]
+#[derive(serde::Deserialize, Debug)]
+#[serde(rename = "Win32_VideoController", rename_all = "PascalCase")]
+struct VideoController {
+    name: String,
+    // Reported as uint32, so it saturates at 4 GiB
+    #[serde(rename = "AdapterRAM")]
+    adapter_ram: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum GpuSource {
+    #[default]
+    Nvidia,
+    #[cfg(not(target_os = "macos"))]
+    Amd,
+    Intel,
+    #[cfg(target_os = "macos")]
+    Apple,
+}
+
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum GpuPreference {
+    // Whatever `gpu_index` points at
+    #[default]
+    Index,
+    Discrete,
+    Integrated,
+    // Follow whichever GPU is busiest, for hybrid laptops that hand work between them
+    Active,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct GpuInfo {
+    pub name: String,
+    pub gpu_usage: u64,
+    // MiB, vram_max stays 0 when the usage can't be read so VRAM shows up as unknown instead
+    // of a made up 0%
+    pub vram_max: u64,
+    pub vram_used: u64,
+    // Degrees Celsius, 0 when the source doesn't report it
+    pub gpu_temp: u64,
+    // Board power in milliwatts, 0 when the source doesn't report it
+    // (Apple Silicon GPU power comes from the `power` collector instead)
+    pub gpu_power: u64,
+    // Current core and memory clocks in MHz, 0 when the source doesn't report them
+    pub gpu_clock: u64,
+    pub mem_clock: u64,
+    // Percent, None when unknown since 0 is a perfectly normal idle speed
+    pub gpu_fan: Option<u64>,
+    // Video encoder/decoder (NVENC/NVDEC, QuickSync) load in percent, None when unknown
+    pub encoder_usage: Option<u64>,
+    pub decoder_usage: Option<u64>,
+    // MiB the card has to itself even when its usage is unknown, tells Intel Arc cards from
+    // iGPUs
+    #[serde(skip)]
+    local_memory: u64,
+    // gpu_usage couldn't be read and is a placeholder 0
+    #[serde(skip)]
+    usage_unknown: bool,
+    // Which backend reported this GPU and where, so it can be refreshed on its own
+    #[serde(skip)]
+    source: GpuSource,
+    #[serde(skip)]
+    source_index: usize,
+}
+
+impl GpuInfo {
+    // Every GPU we can see, in the order they are preferred by default
+    pub async fn get_all_gpu_info() -> Vec<Self> {
+        #[cfg(target_os = "macos")]
+        {
+            Self::get_macos_gpu_info().await
+        }
+
+        #[cfg(not(target_os = "macos"))]
+        {
+            Self::get_generic_gpu_info().await
+        }
+    }
+
+    // The GPUs to report, only more than one when following the active GPU.
+    // Falls back to `index` when there is no GPU of the preferred kind
+    pub async fn select(index: usize, prefer: GpuPreference) -> Vec<Self> {
+        let gpus = Self::get_all_gpu_info().await;
+        let preferred = match prefer {
+            GpuPreference::Index => None,
+            GpuPreference::Discrete => gpus.iter().find(|gpu| !gpu.is_integrated()),
+            GpuPreference::Integrated => gpus.iter().find(|gpu| gpu.is_integrated()),
+            GpuPreference::Active => return gpus,
+        };
+
+        match preferred {
+            Some(gpu) => vec![gpu.clone()],
+            None => gpus.into_iter().nth(index).into_iter().collect(),
+        }
+    }
+
+    pub fn usage_known(&self) -> bool {
+        !self.usage_unknown
+    }
+
+    pub fn is_integrated(&self) -> bool {
+        match self.source {
+            GpuSource::Nvidia => false,
+            #[cfg(not(target_os = "macos"))]
+            GpuSource::Amd => self.vram_max <= APU_VRAM_MAX_MIB,
+            #[cfg(not(target_os = "macos"))]
+            GpuSource::Intel => self.local_memory <= APU_VRAM_MAX_MIB,
+            // Macs never shipped with Intel's dedicated cards
+            #[cfg(target_os = "macos")]
+            GpuSource::Intel | GpuSource::Apple => true,
+        }
+    }
+
+    // Re-queries only the backend this GPU came from instead of probing all of them,
+    // `interval` is how often this gets called
+    pub async fn refresh(&self, interval: Duration) -> Option<Self> {
+        let gpus = match self.source {
+            GpuSource::Nvidia => match nvml() {
+                Some(nvml) => Self::get_nvml_gpu_info(nvml),
+                None => Self::get_nvidia_smi_stream_info(interval).await,
+            },
+            #[cfg(not(target_os = "macos"))]
+            GpuSource::Amd => Self::get_amd_gpu_info().await,
+            #[cfg(not(target_os = "macos"))]
+            GpuSource::Intel => Self::get_intel_gpu_info().await,
+            #[cfg(target_os = "macos")]
+            GpuSource::Intel => Self::get_macos_intel_gpu_info().await.into_iter().collect(),
+            #[cfg(target_os = "macos")]
+            GpuSource::Apple => Self::get_apple_silicon_gpu_info()
+                .await
+                .into_iter()
+                .collect(),
+        };
+
+        Self::tagged(gpus, self.source).nth(self.source_index)
+    }
+
+    fn tagged(
+        gpus: impl IntoIterator<Item = Self>,
+        source: GpuSource,
+    ) -> impl Iterator<Item = Self> {
+        gpus.into_iter()
+            .enumerate()
+            .map(move |(index, gpu)| GpuInfo {
+                source,
+                source_index: index,
+                ..gpu
+            })
+    }
+
+    async fn get_nvidia_gpu_info() -> Vec<Self> {
+        match nvml() {
+            Some(nvml) => Self::get_nvml_gpu_info(nvml),
+            None => Self::get_nvidia_smi_gpu_info().await,
+        }
+    }
+
+    fn get_nvml_gpu_info(nvml: &Nvml) -> Vec<Self> {
+        (0..nvml.device_count().unwrap_or(0))
+            .filter_map(|index| {
+                let device = nvml.device_by_index(index).ok()?;
+                let utilization = device.utilization_rates().ok()?;
+                let memory = device.memory_info().ok()?;
+                let temp = device.temperature(TemperatureSensor::Gpu).unwrap_or(0);
+                let power = device.power_usage().unwrap_or(0);
+                let gpu_clock = device.clock_info(Clock::Graphics).unwrap_or(0);
+                let mem_clock = device.clock_info(Clock::Memory).unwrap_or(0);
+                // Passively cooled cards have no fan at all
+                let fan = device.fan_speed(0).ok();
+                let encoder = device.encoder_utilization().ok();
+                let decoder = device.decoder_utilization().ok();
+
+                Some(GpuInfo {
+                    name: device.name().unwrap_or_default(),
+                    gpu_usage: utilization.gpu as u64,
+                    vram_max: memory.total / MIB_TO_BYTES,
+                    vram_used: memory.used / MIB_TO_BYTES,
+                    gpu_temp: temp as u64,
+                    gpu_power: power as u64,
+                    gpu_clock: gpu_clock as u64,
+                    mem_clock: mem_clock as u64,
+                    gpu_fan: fan.map(|fan| fan as u64),
+                    encoder_usage: encoder.map(|e| e.utilization as u64),
+                    decoder_usage: decoder.map(|d| d.utilization as u64),
+                    ..Default::default()
+                })
+            })
+            .collect()
+    }
+
+    // Falls back to a one-off query until the stream has its first lines, or if it died
+    async fn get_nvidia_smi_stream_info(interval: Duration) -> Vec<Self> {
+        let latest = {
+            let mut stream = NVIDIA_SMI_STREAM.lock().unwrap();
+            if stream
+                .as_ref()
+                .map_or(true, |s| s.as_ref().is_some_and(SmiStream::is_idle))
+            {
+                *stream = Some(SmiStream::spawn(interval));
+            }
+            stream.as_ref().and_then(|s| s.as_ref()?.latest())
+        };
+        match latest {
+            Some(gpus) => gpus.iter().filter_map(Self::from_smi).collect(),
+            None => Self::get_nvidia_smi_gpu_info().await,
+        }
+    }
+
+    async fn get_nvidia_smi_gpu_info() -> Vec<Self> {
+        let mut cmd = match tokio::process::Command::new("nvidia-smi")
+            .arg("-q")
+            .arg("-x")
+            .stdout(std::process::Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+        {
+            Ok(cmd) => cmd,
+            Err(e) => {
+                NVIDIA_SMI_MISSING.call_once(|| tracing::debug!("nvidia-smi not available: {e}"));
+                return Vec::new();
+            }
+        };
+
+        let Some(stdout) = cmd.stdout.take() else {
+            return Vec::new();
+        };
+        let mut stdout_reader = tokio::io::BufReader::new(stdout);
+        let mut output = String::new();
+        if let Err(e) = stdout_reader.read_to_string(&mut output).await {
+            tracing::warn!("Failed to read nvidia-smi output: {e}");
+            return Vec::new();
+        }
+
+        let gpus = match super::nvidia_smi::parse(&output) {
+            Ok(gpus) => gpus,
+            Err(e) => {
+                tracing::warn!("Failed to parse nvidia-smi output: {e}");
+                return Vec::new();
+            }
+        };
+
+        gpus.iter().filter_map(Self::from_smi).collect()
+    }
+
+    fn from_smi(g: &SmiGpu) -> Option<Self> {
+        let gpu_usage = g.number("utilization/gpu_util")?;
+        let vram_max = g.number("fb_memory_usage/total")?;
+        let vram_used = g.number("fb_memory_usage/used")?;
+        let gpu_temp = g.number("temperature/gpu_temp").unwrap_or(0);
+        let gpu_power = g
+            .number::<f64>("gpu_power_readings/power_draw")
+            .or_else(|| g.number("power_readings/power_draw"))
+            .map_or(0, |watts| (watts * 1000.0) as u64);
+        let gpu_clock = g.number("clocks/graphics_clock").unwrap_or(0);
+        let mem_clock = g.number("clocks/mem_clock").unwrap_or(0);
+        // "N/A" on passively cooled cards
+        let gpu_fan = g.number("fan_speed");
+        let encoder_usage = g.number("utilization/encoder_util");
+        let decoder_usage = g.number("utilization/decoder_util");
+
+        Some(GpuInfo {
+            name: g.text("product_name").unwrap_or_default().to_owned(),
+            gpu_usage,
+            vram_max,
+            vram_used,
+            gpu_temp,
+            gpu_power,
+            gpu_clock,
+            mem_clock,
+            gpu_fan,
+            encoder_usage,
+            decoder_usage,
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(target_os = "macos")]
+impl GpuInfo {
+    async fn get_macos_gpu_info() -> Vec<Self> {
+        let mut gpus = Vec::new();
+        gpus.extend(Self::tagged(
+            Self::get_apple_silicon_gpu_info().await,
+            GpuSource::Apple,
+        ));
+        gpus.extend(Self::tagged(
+            Self::get_macos_intel_gpu_info().await,
+            GpuSource::Intel,
+        ));
+        gpus.extend(Self::tagged(
+            Self::get_nvidia_gpu_info().await,
+            GpuSource::Nvidia,
+        ));
+        gpus
+    }
+
+    async fn get_apple_silicon_gpu_info() -> Option<Self> {
+        // Asks the OS rather than checking our own arch so a Rosetta build still finds the GPU
+        static APPLE_SILICON: tokio::sync::OnceCell<bool> = tokio::sync::OnceCell::const_new();
+        let apple_silicon = APPLE_SILICON
+            .get_or_init(|| async {
+                match tokio::process::Command::new("uname")
+                    .arg("-m")
+                    .output()
+                    .await
+                {
+                    Ok(output) => String::from_utf8_lossy(&output.stdout).trim() == "arm64",
+                    Err(_) => false,
+                }
+            })
+            .await;
+        if !apple_silicon {
+            return None;
+        }
+
+        let Ok(output) = tokio::process::Command::new("ioreg")
+            .arg("-r")
+            .arg("-c")
+            .arg("IOAccelerator")
+            .kill_on_drop(true)
+            .output()
+            .await
+        else {
+            return None;
+        };
+
+        if !output.status.success() {
+            return None;
+        }
+
+        let output_str = String::from_utf8_lossy(&output.stdout);
+        let mut gpu_usage = 0u64;
+        let mut vram_used = 0u64;
+        let mut vram_max = 0u64;
+        let mut gpu_temp = 0u64;
+        let mut is_apple_gpu = false;
+
+        for line in output_str.lines() {
+            if line.contains("AGXAccelerator") || line.contains("\"model\" = \"Apple") {
+                is_apple_gpu = true;
+            }
+
+            if line.contains("\"PerformanceStatistics\"") {
+                if let Some(usage) = Self::parse_ioreg_number(line, "\"Device Utilization %\"=", 23)
+                {
+                    gpu_usage = usage;
+                }
+
+                if let Some(mem) = Self::parse_ioreg_number(line, "\"In use system memory\"=", 23) {
+                    vram_used = mem / MIB_TO_BYTES;
+                }
+
+                if let Some(mem) = Self::parse_ioreg_number(line, "\"Alloc system memory\"=", 22) {
+                    vram_max = mem / MIB_TO_BYTES;
+                }
+
+                if let Some(temp) = Self::parse_ioreg_number(line, "\"Temperature(C)\"=", 17) {
+                    gpu_temp = temp;
+                }
+            }
+        }
+
+        if !is_apple_gpu {
+            return None;
+        }
+
+        Some(GpuInfo {
+            name: "Apple GPU".to_owned(),
+            gpu_usage,
+            vram_max,
+            vram_used,
+            gpu_temp,
+            ..Default::default()
+        })
+    }
+
+    async fn get_macos_intel_gpu_info() -> Option<Self> {
+        let Ok(output) = tokio::process::Command::new("ioreg")
+            .arg("-r")
+            .arg("-c")
+            .arg("IOAccelerator")
+            .kill_on_drop(true)
+            .output()
+            .await
+        else {
+            return None;
+        };
+
+        if !output.status.success() {
+            return None;
+        }
+
+        let output_str = String::from_utf8_lossy(&output.stdout);
+        let mut local_memory = 0u64;
+        let mut gpu_temp = 0u64;
+        let mut is_intel = false;
+
+        for line in output_str.lines() {
+            if line.contains("\"IOClass\" = \"IntelAccelerator\"")
+                || line.contains("\"model\" = <\"Intel")
+            {
+                is_intel = true;
+            }
+
+            if line.contains("\"VRAM,totalMB\"") {
+                if let Some(equals_pos) = line.find('=') {
+                    let after_equals = line[equals_pos + 1..].trim();
+                    let number_str = after_equals
+                        .trim_start_matches('<')
+                        .trim_end_matches('>')
+                        .trim();
+                    if let Ok(mb_val) = number_str.parse::<u64>() {
+                        local_memory = mb_val;
+                    }
+                }
+            }
+
+            if line.contains("\"PerformanceStatistics\"") {
+                if let Some(temp) = Self::parse_ioreg_number(line, "\"Temperature(C)\"=", 17) {
+                    gpu_temp = temp;
+                }
+            }
+        }
+
+        if !is_intel || local_memory == 0 {
+            return None;
+        }
+
+        // ioreg has the VRAM size but neither what's used nor the load, so both are left unknown
+        Some(GpuInfo {
+            name: "Intel GPU".to_owned(),
+            gpu_temp,
+            local_memory,
+            usage_unknown: true,
+            ..Default::default()
+        })
+    }
+
+    fn parse_ioreg_number(line: &str, key: &str, offset: usize) -> Option<u64> {
+        line.find(key).and_then(|start| {
+            let after_equals = &line[start + offset..];
+            let end = after_equals.find(|c: char| !c.is_ascii_digit())?;
+            after_equals[..end].parse::<u64>().ok()
+        })
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+impl GpuInfo {
+    async fn get_generic_gpu_info() -> Vec<Self> {
+        let mut gpus = Vec::new();
+        gpus.extend(Self::tagged(
+            Self::get_nvidia_gpu_info().await,
+            GpuSource::Nvidia,
+        ));
+        gpus.extend(Self::tagged(Self::get_amd_gpu_info().await, GpuSource::Amd));
+        gpus.extend(Self::tagged(
+            Self::get_intel_gpu_info().await,
+            GpuSource::Intel,
+        ));
+        gpus
+    }
+
+    async fn get_amd_gpu_info() -> Vec<Self> {
+        #[cfg(target_os = "linux")]
+        {
+            Self::get_linux_amd_gpu_info().await
+        }
+
+        #[cfg(target_os = "freebsd")]
+        {
+            Self::get_freebsd_gpu_info("0x1002", "AMD").await
+        }
+
+        #[cfg(not(any(target_os = "linux", target_os = "freebsd")))]
+        {
+            Vec::new()
+        }
+    }
+
+    async fn get_intel_gpu_info() -> Vec<Self> {
+        #[cfg(target_os = "windows")]
+        {
+            Self::get_windows_intel_gpu_info().await
+        }
+
+        #[cfg(target_os = "linux")]
+        {
+            Self::get_linux_intel_gpu_info().await
+        }
+
+        #[cfg(target_os = "freebsd")]
+        {
+            Self::get_freebsd_gpu_info("0x8086", "Intel").await
+        }
+
+        #[cfg(not(any(target_os = "windows", target_os = "linux", target_os = "freebsd")))]
+        {
+            Vec::new()
+        }
+    }
+
+    #[cfg(target_os = "windows")]
+    async fn get_windows_intel_gpu_info() -> Vec<Self> {
+        // WMI only knows the VRAM size and not what's used, so VRAM is left unknown. The load
+        // comes from performance counters
+        let gpu_usage = super::pdh::get_gpu_usage();
+        let encoder_usage = super::pdh::get_encoder_usage();
+        let decoder_usage = super::pdh::get_decoder_usage();
+
+        // COM objects aren't Send, so the query runs on a blocking thread
+        let controllers = match tokio::task::spawn_blocking(Self::query_video_controllers).await {
+            Ok(Ok(controllers)) => controllers,
+            Ok(Err(e)) => {
+                tracing::warn!("Failed to query video controllers: {e}");
+                return Vec::new();
+            }
+            Err(e) => {
+                tracing::warn!("Failed to query video controllers: {e}");
+                return Vec::new();
+            }
+        };
+
+        controllers
+            .into_iter()
+            .filter(|c| c.name.to_lowercase().contains("intel"))
+            .filter_map(|c| match c.adapter_ram {
+                Some(ram_bytes) if ram_bytes > 0 => Some(GpuInfo {
+                    name: c.name,
+                    gpu_usage: gpu_usage.unwrap_or(0),
+                    usage_unknown: gpu_usage.is_none(),
+                    local_memory: ram_bytes as u64 / MIB_TO_BYTES,
+                    encoder_usage,
+                    decoder_usage,
+                    ..Default::default()
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[cfg(target_os = "windows")]
+    fn query_video_controllers() -> Result<Vec<VideoController>, wmi::WMIError> {
+        let wmi = wmi::WMIConnection::new(wmi::COMLibrary::new()?)?;
+        wmi.query()
+    }
+
+    // drm-kmod lists cards under hw.dri and LinuxKPI mirrors the amdgpu/i915 sysfs
+    // attributes under sys.class.drm, so this reads the same files as on Linux
+    #[cfg(target_os = "freebsd")]
+    async fn get_freebsd_gpu_info(vendor_id: &str, vendor_name: &str) -> Vec<Self> {
+        use crate::helpers::sysctl;
+
+        let mut gpus = Vec::new();
+        for card in 0.. {
+            if sysctl(&format!("hw.dri.{card}.name")).await.is_none() {
+                break;
+            }
+
+            let device = format!("sys.class.drm.card{card}.device");
+            let read = |attribute: &str| {
+                let name = format!("{device}.{attribute}");
+                async move { sysctl(&name).await }
+            };
+            if read("vendor").await.as_deref() != Some(vendor_id) {
+                continue;
+            }
+
+            let read_u64 = |value: Option<String>| value.and_then(|v| v.parse::<u64>().ok());
+            let Some(vram_total) = read_u64(read("mem_info_vram_total").await) else {
+                continue;
+            };
+
+            let gpu_usage = read_u64(read("gpu_busy_percent").await);
+            gpus.push(GpuInfo {
+                name: format!("{vendor_name} GPU (card{card})"),
+                gpu_usage: gpu_usage.unwrap_or(0),
+                usage_unknown: gpu_usage.is_none(),
+                vram_max: vram_total / MIB_TO_BYTES,
+                vram_used: read_u64(read("mem_info_vram_used").await).unwrap_or(0) / MIB_TO_BYTES,
+                local_memory: vram_total / MIB_TO_BYTES,
+                ..Default::default()
+            });
+        }
+
+        gpus
+    }
+
+    #[cfg(target_os = "linux")]
+    fn find_hwmon(device_path: &std::path::Path) -> Option<std::path::PathBuf> {
+        std::fs::read_dir(device_path.join("hwmon"))
+            .ok()?
+            .flatten()
+            .map(|entry| entry.path())
+            .next()
+    }
+
+    #[cfg(target_os = "linux")]
+    async fn get_linux_amd_gpu_info() -> Vec<Self> {
+        let Ok(entries) = std::fs::read_dir("/sys/class/drm") else {
+            return Vec::new();
+        };
+        let mut gpus = Vec::new();
+
+        for entry in entries.flatten() {
+            // Skip connector entries like card0-DP-1
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if !name.starts_with("card") || name.contains('-') {
+                continue;
+            }
+
+            let device_path = entry.path().join("device");
+            let Ok(vendor) = std::fs::read_to_string(device_path.join("vendor")) else {
+                continue;
+            };
+
+            if vendor.trim() != "0x1002" {
+                continue;
+            }
+
+            let Some(vram_total) = read_sysfs_u64(&device_path.join("mem_info_vram_total")) else {
+                continue;
+            };
+
+            let hwmon_path = Self::find_hwmon(&device_path);
+            let gpu_temp = hwmon_path
+                .as_ref()
+                .and_then(|hwmon| read_sysfs_u64(&hwmon.join("temp1_input")))
+                .map(|millidegrees| millidegrees / 1000)
+                .unwrap_or(0);
+            // Microwatts, newer kernels only have power1_input on some cards
+            let gpu_power = hwmon_path
+                .as_ref()
+                .and_then(|hwmon| {
+                    read_sysfs_u64(&hwmon.join("power1_average"))
+                        .or_else(|| read_sysfs_u64(&hwmon.join("power1_input")))
+                })
+                .map(|microwatts| microwatts / 1000)
+                .unwrap_or(0);
+            // PWM duty cycle from 0 to 255
+            let gpu_fan = hwmon_path
+                .as_ref()
+                .and_then(|hwmon| read_sysfs_u64(&hwmon.join("pwm1")))
+                .map(|pwm| pwm * 100 / 255);
+
+            let gpu_usage = read_sysfs_u64(&device_path.join("gpu_busy_percent"));
+            gpus.push(GpuInfo {
+                name: format!("AMD GPU ({name})"),
+                gpu_usage: gpu_usage.unwrap_or(0),
+                usage_unknown: gpu_usage.is_none(),
+                vram_max: vram_total / MIB_TO_BYTES,
+                vram_used: read_sysfs_u64(&device_path.join("mem_info_vram_used")).unwrap_or(0)
+                    / MIB_TO_BYTES,
+                gpu_temp,
+                gpu_power,
+                gpu_clock: Self::read_dpm_clock(&device_path.join("pp_dpm_sclk")).unwrap_or(0),
+                mem_clock: Self::read_dpm_clock(&device_path.join("pp_dpm_mclk")).unwrap_or(0),
+                gpu_fan,
+                ..Default::default()
+            });
+        }
+
+        gpus
+    }
+
+    // One DPM level per line, e.g. "1: 1800Mhz *", the active one is marked with a star
+    #[cfg(target_os = "linux")]
+    fn read_dpm_clock(path: &std::path::Path) -> Option<u64> {
+        let levels = std::fs::read_to_string(path).ok()?;
+        let active = levels.lines().find(|line| line.trim_end().ends_with('*'))?;
+        let (_, clock) = active.split_once(':')?;
+        clock
+            .trim()
+            .trim_end_matches('*')
+            .trim()
+            .to_lowercase()
+            .strip_suffix("mhz")?
+            .parse()
+            .ok()
+    }
+
+    // i915_gem_objects starts with "<n> shrinkable [<n> free] objects, <used> bytes" followed
+    // by one "<region>: total:0x..., available:0x... bytes" line per memory region.
+    // debugfs needs root, so this is only a bonus when running as root
+    #[cfg(target_os = "linux")]
+    fn read_i915_shared_memory(card: &str) -> Option<(u64, u64)> {
+        let minor = card.strip_prefix("card")?;
+        let objects =
+            std::fs::read_to_string(format!("/sys/kernel/debug/dri/{minor}/i915_gem_objects"))
+                .ok()?;
+        let mut lines = objects.lines();
+
+        let used = lines
+            .next()?
+            .split(", ")
+            .nth(1)?
+            .trim_end_matches(" bytes")
+            .parse()
+            .ok()?;
+        let total = lines.find_map(|line| {
+            let hex = line.strip_prefix("system: total:0x")?;
+            let end = hex
+                .find(|c: char| !c.is_ascii_hexdigit())
+                .unwrap_or(hex.len());
+            u64::from_str_radix(&hex[..end], 16).ok()
+        })?;
+
+        Some((total, used))
+    }
+
+    #[cfg(target_os = "linux")]
+    async fn get_linux_intel_gpu_info() -> Vec<Self> {
+        let Ok(entries) = std::fs::read_dir("/sys/class/drm") else {
+            return Vec::new();
+        };
+        let mut gpus = Vec::new();
+
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if !name.starts_with("card") || name.contains('-') {
+                continue;
+            }
+
+            let device_path = entry.path().join("device");
+            let vendor_path = device_path.join("vendor");
+
+            let Ok(vendor) = std::fs::read_to_string(&vendor_path) else {
+                continue;
+            };
+            if vendor.trim() != "0x8086" {
+                continue;
+            }
+
+            // Only discrete cards have VRAM, i915 iGPUs share system memory. Without a way to
+            // read what's used VRAM is left unknown, and sysfs has no load for Intel at all
+            let local_memory = read_sysfs_u64(&device_path.join("mem_info_vram_total"));
+            let vram = match local_memory {
+                Some(total) => read_sysfs_u64(&device_path.join("mem_info_vram_used"))
+                    .map(|used| (total, used)),
+                None => Self::read_i915_shared_memory(&name),
+            };
+            let (vram_max, vram_used) = vram.unwrap_or((0, 0));
+
+            gpus.push(GpuInfo {
+                name: format!("Intel GPU ({name})"),
+                vram_max: vram_max / MIB_TO_BYTES,
+                vram_used: vram_used / MIB_TO_BYTES,
+                local_memory: local_memory.unwrap_or(0) / MIB_TO_BYTES,
+                usage_unknown: true,
+                ..Default::default()
+            });
+        }
+
+        gpus
+    }
+}
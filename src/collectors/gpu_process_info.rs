@@ -0,0 +1,103 @@
+use super::gpu_info::nvml;
+use super::{Collector, Metric};
+use crate::helpers::pop_16u8;
+use async_trait::async_trait;
+use nvml_wrapper::enums::device::UsedGpuMemory;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use sysinfo::{Pid, ProcessRefreshKind};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GpuProcessInfo {
+    pub name: [u8; 16],
+    // MB of VRAM over all NVIDIA GPUs
+    pub vram_used: u16,
+}
+
+impl GpuProcessInfo {
+    // NVIDIA only, other vendors don't expose per-process VRAM
+    pub fn get_top_gpu_processes(system: &mut sysinfo::System, count: usize) -> Vec<Self> {
+        let Some(nvml) = nvml() else {
+            return Vec::new();
+        };
+
+        // A process doing both compute and graphics shows up in both lists with the same usage
+        let mut by_pid: HashMap<u32, u64> = HashMap::new();
+        for index in 0..nvml.device_count().unwrap_or(0) {
+            let Ok(device) = nvml.device_by_index(index) else {
+                continue;
+            };
+            let processes = device
+                .running_graphics_processes()
+                .into_iter()
+                .chain(device.running_compute_processes())
+                .flatten();
+
+            let mut device_usage: HashMap<u32, u64> = HashMap::new();
+            for process in processes {
+                // Unavailable on Windows WDDM, where the driver doesn't track it
+                if let UsedGpuMemory::Used(bytes) = process.used_gpu_memory {
+                    let usage = device_usage.entry(process.pid).or_default();
+                    *usage = (*usage).max(bytes);
+                }
+            }
+            for (pid, bytes) in device_usage {
+                *by_pid.entry(pid).or_default() += bytes;
+            }
+        }
+
+        if by_pid.is_empty() {
+            return Vec::new();
+        }
+
+        system.refresh_processes_specifics(ProcessRefreshKind::new());
+        let mut by_name: HashMap<String, u64> = HashMap::new();
+        for (pid, bytes) in by_pid {
+            let name = match system.process(Pid::from_u32(pid)) {
+                Some(process) => process.name().to_owned(),
+                None => pid.to_string(),
+            };
+            *by_name.entry(name).or_default() += bytes;
+        }
+
+        let mut processes: Vec<_> = by_name.into_iter().collect();
+        processes.sort_by(|(_, a), (_, b)| b.cmp(a));
+
+        processes
+            .into_iter()
+            .take(count)
+            .map(|(name, bytes)| GpuProcessInfo {
+                name: pop_16u8(name.as_bytes()),
+                vram_used: (bytes / (1024 * 1024)).min(u16::MAX as u64) as u16,
+            })
+            .collect()
+    }
+}
+
+pub struct GpuProcessCollector {
+    system: sysinfo::System,
+    count: usize,
+}
+
+impl GpuProcessCollector {
+    pub fn new(count: usize) -> Self {
+        GpuProcessCollector {
+            system: sysinfo::System::new(),
+            count,
+        }
+    }
+}
+
+#[async_trait]
+impl Collector for GpuProcessCollector {
+    fn name(&self) -> &'static str {
+        "gpu_processes"
+    }
+
+    async fn collect(&mut self) -> Metric {
+        Metric::GpuProcesses(GpuProcessInfo::get_top_gpu_processes(
+            &mut self.system,
+            self.count,
+        ))
+    }
+}
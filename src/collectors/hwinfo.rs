@@ -0,0 +1,193 @@
+use super::sensor_info::{SensorReading, MAX_READINGS};
+use super::{Collector, Metric};
+use crate::helpers::{c_str, pop_16u8, pop_4u8};
+use async_trait::async_trait;
+use std::ops::Range;
+use windows_sys::Win32::Foundation::CloseHandle;
+use windows_sys::Win32::System::Memory::{
+    MapViewOfFile, OpenFileMappingW, UnmapViewOfFile, VirtualQuery, FILE_MAP_READ,
+    MEMORY_BASIC_INFORMATION, MEMORY_MAPPED_VIEW_ADDRESS,
+};
+
+// Published by HWiNFO64 while "Shared Memory Support" is enabled in its settings
+const SHARED_MEMORY_NAME: &str = "Global\\HWiNFO_SENS_SM2";
+const SIGNATURE: &[u8; 4] = b"SiWH";
+
+// Offsets into the packed HWiNFO_SENSORS_SHARED_MEM2 header
+const HEADER_SIZE: usize = 44;
+const OFFSET_OF_READING_SECTION: usize = 32;
+const SIZE_OF_READING_ELEMENT: usize = 36;
+const NUM_READING_ELEMENTS: usize = 40;
+
+// Offsets into each packed HWiNFO_SENSORS_READING_ELEMENT
+const READING_LABEL_USER: usize = 140;
+const READING_UNIT: usize = 268;
+const READING_VALUE: usize = 284;
+const READING_MIN_SIZE: usize = READING_VALUE + 8;
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        bytes.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+struct MappedView {
+    handle: isize,
+    view: MEMORY_MAPPED_VIEW_ADDRESS,
+    // Bytes mapped, nothing the header says is trusted past this
+    len: usize,
+}
+
+impl MappedView {
+    fn open() -> Option<Self> {
+        let name: Vec<u16> = SHARED_MEMORY_NAME
+            .encode_utf16()
+            .chain(std::iter::once(0))
+            .collect();
+
+        unsafe {
+            let handle = OpenFileMappingW(FILE_MAP_READ, 0, name.as_ptr());
+            if handle == 0 {
+                return None;
+            }
+
+            let view = MapViewOfFile(handle, FILE_MAP_READ, 0, 0, 0);
+            if view.Value.is_null() {
+                CloseHandle(handle);
+                return None;
+            }
+
+            let mut info: MEMORY_BASIC_INFORMATION = std::mem::zeroed();
+            let queried = VirtualQuery(
+                view.Value,
+                &mut info,
+                std::mem::size_of::<MEMORY_BASIC_INFORMATION>(),
+            );
+            if queried == 0 {
+                UnmapViewOfFile(view);
+                CloseHandle(handle);
+                return None;
+            }
+
+            Some(MappedView {
+                handle,
+                view,
+                len: info.RegionSize,
+            })
+        }
+    }
+
+    // None for anything reaching past the mapping
+    fn bytes(&self, range: Range<usize>) -> Option<&[u8]> {
+        if range.start > range.end || range.end > self.len {
+            return None;
+        }
+        let all = unsafe { std::slice::from_raw_parts(self.view.Value as *const u8, self.len) };
+        Some(&all[range])
+    }
+}
+
+impl Drop for MappedView {
+    fn drop(&mut self) {
+        unsafe {
+            UnmapViewOfFile(self.view);
+            CloseHandle(self.handle);
+        }
+    }
+}
+
+impl SensorReading {
+    // Readings whose label contains any of the filters, all of them without filters
+    pub fn get_hwinfo_readings(filters: &[String]) -> Vec<Self> {
+        let Some(view) = MappedView::open() else {
+            tracing::debug!("HWiNFO shared memory not available");
+            return Vec::new();
+        };
+
+        let Some(header) = view.bytes(0..HEADER_SIZE) else {
+            tracing::warn!("HWiNFO shared memory is smaller than its header");
+            return Vec::new();
+        };
+        if &header[..4] != SIGNATURE {
+            tracing::warn!("HWiNFO shared memory has an unexpected signature");
+            return Vec::new();
+        }
+
+        let (Some(offset), Some(size), Some(count)) = (
+            read_u32(header, OFFSET_OF_READING_SECTION),
+            read_u32(header, SIZE_OF_READING_ELEMENT),
+            read_u32(header, NUM_READING_ELEMENTS),
+        ) else {
+            return Vec::new();
+        };
+        let (offset, size, count) = (offset as usize, size as usize, count as usize);
+        if size < READING_MIN_SIZE {
+            tracing::warn!(size, "HWiNFO reading elements are smaller than expected");
+            return Vec::new();
+        }
+
+        let section = size
+            .checked_mul(count)
+            .and_then(|len| offset.checked_add(len))
+            .and_then(|end| view.bytes(offset..end));
+        let Some(section) = section else {
+            tracing::warn!(
+                offset,
+                size,
+                count,
+                "HWiNFO readings reach past the shared memory"
+            );
+            return Vec::new();
+        };
+        let readings: Vec<Self> = section
+            .chunks_exact(size)
+            .filter_map(|reading| {
+                let label = c_str(&reading[READING_LABEL_USER..READING_UNIT]);
+                let matches = filters.is_empty()
+                    || filters
+                        .iter()
+                        .any(|f| label.to_lowercase().contains(&f.to_lowercase()));
+                if !matches {
+                    return None;
+                }
+
+                let value =
+                    f64::from_le_bytes(reading[READING_VALUE..READING_VALUE + 8].try_into().ok()?);
+                Some(SensorReading {
+                    label: pop_16u8(label.as_bytes()),
+                    value: value as f32,
+                    unit: pop_4u8(c_str(&reading[READING_UNIT..READING_VALUE]).as_bytes()),
+                })
+            })
+            .collect();
+
+        if readings.len() > MAX_READINGS {
+            tracing::warn!(
+                matched = readings.len(),
+                "Too many HWiNFO readings, narrow down hwinfo_sensors. Sending the first {MAX_READINGS}"
+            );
+        }
+        readings.into_iter().take(MAX_READINGS).collect()
+    }
+}
+
+pub struct HwinfoCollector {
+    filters: Vec<String>,
+}
+
+impl HwinfoCollector {
+    pub fn new(filters: Vec<String>) -> Self {
+        HwinfoCollector { filters }
+    }
+}
+
+#[async_trait]
+impl Collector for HwinfoCollector {
+    fn name(&self) -> &'static str {
+        "hwinfo"
+    }
+
+    async fn collect(&mut self) -> Metric {
+        Metric::Sensors(SensorReading::get_hwinfo_readings(&self.filters))
+    }
+}
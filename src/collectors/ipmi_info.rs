@@ -0,0 +1,211 @@
+use super::sensor_info::{SensorReading, MAX_READINGS};
+use super::{Collector, Metric};
+use crate::helpers::{pop_16u8, pop_4u8};
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct IpmiConfig {
+    // BMC to query over the network, the local one (/dev/ipmi0) when unset
+    pub host: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    // ipmitool -I, "lanplus" for IPMI 2.0 and "lan" for older BMCs
+    pub interface: String,
+    // Substrings of the sensor names to send, e.g. "Inlet Temp", "FAN", "PS". Empty sends all
+    pub sensors: Vec<String>,
+    // Seconds between queries, BMCs are slow to answer
+    pub interval: u64,
+}
+
+impl Default for IpmiConfig {
+    fn default() -> Self {
+        IpmiConfig {
+            host: None,
+            username: None,
+            password: None,
+            interface: "lanplus".to_owned(),
+            sensors: Vec::new(),
+            interval: 30,
+        }
+    }
+}
+
+// ipmitool's unit names, shortened to fit the 4 byte unit
+fn short_unit(unit: &str) -> &str {
+    match unit {
+        "degrees C" => "C",
+        "degrees F" => "F",
+        "Watts" => "W",
+        "Volts" => "V",
+        "Amps" => "A",
+        "percent" => "%",
+        unit => unit,
+    }
+}
+
+// One `ipmitool -c sdr list` line, e.g. "Inlet Temp,24,degrees C,ok" or, for discrete
+// sensors like PSU status, "PS1 Status,0x01,discrete,ok"
+fn parse_line(line: &str, filters: &[String]) -> Option<SensorReading> {
+    let columns: Vec<&str> = line.split(',').map(str::trim).collect();
+    let [name, value, unit, status] = columns[..] else {
+        return None;
+    };
+    if !filters.is_empty() && !filters.iter().any(|filter| name.contains(filter.as_str())) {
+        return None;
+    }
+
+    let (value, unit) = match unit {
+        // "ns" is no reading, e.g. an empty PSU bay
+        _ if status == "ns" => return None,
+        // 1 while the BMC says ok, 0 for critical or non-critical states
+        "discrete" => ((status == "ok") as u8 as f32, ""),
+        unit => (value.parse().ok()?, short_unit(unit)),
+    };
+    Some(SensorReading {
+        label: pop_16u8(name.as_bytes()),
+        value,
+        unit: pop_4u8(unit.as_bytes()),
+    })
+}
+
+async fn get_ipmi_readings(config: &IpmiConfig) -> Vec<SensorReading> {
+    let mut command = tokio::process::Command::new("ipmitool");
+    if let Some(host) = &config.host {
+        command.args(["-I", &config.interface, "-H", host]);
+        if let Some(username) = &config.username {
+            command.args(["-U", username]);
+        }
+        // -E keeps the password off the command line where other users could see it
+        if let Some(password) = &config.password {
+            command.arg("-E").env("IPMI_PASSWORD", password);
+        }
+    }
+
+    let output = match command
+        .args(["-c", "sdr", "list"])
+        .kill_on_drop(true)
+        .output()
+        .await
+    {
+        Ok(output) if output.status.success() => output,
+        Ok(output) => {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            tracing::warn!("ipmitool failed: {}", stderr.trim());
+            return Vec::new();
+        }
+        Err(e) => {
+            tracing::debug!("ipmitool not available: {e}");
+            return Vec::new();
+        }
+    };
+
+    let readings: Vec<SensorReading> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| parse_line(line, &config.sensors))
+        .collect();
+    if readings.len() > MAX_READINGS {
+        tracing::warn!(
+            matched = readings.len(),
+            "Too many IPMI sensors, narrow down ipmi.sensors. Sending the first {MAX_READINGS}"
+        );
+    }
+    readings.into_iter().take(MAX_READINGS).collect()
+}
+
+pub struct IpmiCollector {
+    config: IpmiConfig,
+    last: Option<(Instant, Vec<SensorReading>)>,
+}
+
+impl IpmiCollector {
+    pub fn new(config: IpmiConfig) -> Self {
+        IpmiCollector { config, last: None }
+    }
+}
+
+#[async_trait]
+impl Collector for IpmiCollector {
+    fn name(&self) -> &'static str {
+        "ipmi"
+    }
+
+    async fn collect(&mut self) -> Metric {
+        let interval = Duration::from_secs(self.config.interval);
+        match &self.last {
+            Some((updated, readings)) if updated.elapsed() < interval => {
+                Metric::Sensors(readings.clone())
+            }
+            _ => {
+                let readings = get_ipmi_readings(&self.config).await;
+                self.last = Some((Instant::now(), readings.clone()));
+                Metric::Sensors(readings)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::c_str;
+
+    fn reading(line: &str) -> Option<(String, f32, String)> {
+        let reading = parse_line(line, &[])?;
+        Some((c_str(&reading.label), reading.value, c_str(&reading.unit)))
+    }
+
+    #[test]
+    fn analog_readings_get_short_units() {
+        assert_eq!(
+            reading("Inlet Temp,24,degrees C,ok"),
+            Some(("Inlet Temp".to_owned(), 24.0, "C".to_owned()))
+        );
+        assert_eq!(
+            reading("PS1 Input Power, 182.50 , Watts , ok"),
+            Some(("PS1 Input Power".to_owned(), 182.5, "W".to_owned()))
+        );
+        assert_eq!(
+            reading("Fan1,4800,RPM,ok"),
+            Some(("Fan1".to_owned(), 4800.0, "RPM".to_owned()))
+        );
+    }
+
+    #[test]
+    fn unit_less_readings() {
+        assert_eq!(
+            reading("Fan Redundancy,2,,ok"),
+            Some(("Fan Redundancy".to_owned(), 2.0, String::new()))
+        );
+    }
+
+    #[test]
+    fn discrete_sensors_report_their_state() {
+        assert_eq!(
+            reading("PS1 Status,0x01,discrete,ok"),
+            Some(("PS1 Status".to_owned(), 1.0, String::new()))
+        );
+        assert_eq!(
+            reading("PS2 Status,0x0b,discrete,cr"),
+            Some(("PS2 Status".to_owned(), 0.0, String::new()))
+        );
+    }
+
+    #[test]
+    fn missing_readings_are_skipped() {
+        assert_eq!(reading("CPU2 Temp,na,degrees C,na"), None);
+        assert_eq!(reading("PS3 Status,0x00,discrete,ns"), None);
+        assert_eq!(reading("Inlet Temp,no reading,degrees C,ns"), None);
+        assert_eq!(reading("Inlet Temp,24,degrees C"), None);
+        assert_eq!(reading(""), None);
+    }
+
+    #[test]
+    fn filters_match_names() {
+        let filters = ["Temp".to_owned()];
+        assert!(parse_line("Inlet Temp,24,degrees C,ok", &filters).is_some());
+        assert!(parse_line("Fan1,4800,RPM,ok", &filters).is_none());
+    }
+}
@@ -0,0 +1,308 @@
+use crate::config::Config;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+pub mod battery_info;
+pub mod cpu_sampler;
+pub mod disk_info;
+pub mod fan_info;
+pub mod gpu_info;
+pub mod gpu_process_info;
+#[cfg(target_os = "windows")]
+pub mod hwinfo;
+pub mod ipmi_info;
+pub mod network_info;
+pub mod nvidia_smi;
+#[cfg(target_os = "windows")]
+pub mod pdh;
+pub mod power_info;
+pub mod process_info;
+#[cfg(target_os = "linux")]
+pub mod rpi_info;
+pub mod sensor_info;
+pub mod smart_info;
+pub mod smoothing;
+pub mod snmp_info;
+pub mod supervisor;
+pub mod system_info;
+pub mod temp_info;
+pub mod ups_info;
+
+use battery_info::BatteryInfo;
+use disk_info::DiskInfo;
+use fan_info::FanInfo;
+use gpu_process_info::GpuProcessInfo;
+use network_info::NetworkInfo;
+use power_info::PowerInfo;
+use process_info::ProcessInfo;
+use smart_info::SmartInfo;
+use supervisor::{Outcome, Supervised};
+use system_info::SystemInfo;
+use temp_info::TempInfo;
+use ups_info::UpsInfo;
+
+#[derive(Debug, Clone)]
+pub enum Metric {
+    System(SystemInfo),
+    Disks(Vec<DiskInfo>),
+    Network(NetworkInfo),
+    Battery(Option<BatteryInfo>),
+    Processes(Vec<ProcessInfo>),
+    GpuProcesses(Vec<GpuProcessInfo>),
+    Fans(Vec<FanInfo>),
+    Temps(Vec<TempInfo>),
+    Smart(Vec<SmartInfo>),
+    Ups(Option<UpsInfo>),
+    Power(Option<PowerInfo>),
+    #[cfg(target_os = "linux")]
+    Rpi(Option<rpi_info::RpiInfo>),
+    Sensors(Vec<sensor_info::SensorReading>),
+}
+
+#[async_trait]
+pub trait Collector: Send {
+    fn name(&self) -> &'static str;
+
+    async fn collect(&mut self) -> Metric;
+
+    // On battery, collectors with an expensive part (usually a subprocess) can leave it out
+    fn set_low_power(&mut self, _low_power: bool) {}
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct LowPowerConfig {
+    // Switches over while the `battery` collector reports discharging
+    pub enabled: bool,
+    // Milliseconds between updates on battery, never faster than `interval`
+    pub interval: u64,
+    // Collectors that don't run at all on battery
+    pub skip: Vec<String>,
+}
+
+impl Default for LowPowerConfig {
+    fn default() -> Self {
+        LowPowerConfig {
+            enabled: true,
+            interval: 5000,
+            skip: vec![
+                "processes".to_owned(),
+                "gpu_processes".to_owned(),
+                "smart".to_owned(),
+            ],
+        }
+    }
+}
+
+// Everything collected in one tick, handed to the encoder and to sinks
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Sample {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system: Option<SystemInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disks: Option<Vec<DiskInfo>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network: Option<NetworkInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub battery: Option<BatteryInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub processes: Option<Vec<ProcessInfo>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gpu_processes: Option<Vec<GpuProcessInfo>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fans: Option<Vec<FanInfo>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temps: Option<Vec<TempInfo>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub smart: Option<Vec<SmartInfo>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ups: Option<UpsInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub power: Option<PowerInfo>,
+    #[cfg(target_os = "linux")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rpi: Option<rpi_info::RpiInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sensors: Option<Vec<sensor_info::SensorReading>>,
+}
+
+impl Sample {
+    pub fn is_empty(&self) -> bool {
+        #[cfg(target_os = "linux")]
+        if self.rpi.is_some() {
+            return false;
+        }
+        self.system.is_none()
+            && self.disks.is_none()
+            && self.network.is_none()
+            && self.battery.is_none()
+            && self.processes.is_none()
+            && self.gpu_processes.is_none()
+            && self.fans.is_none()
+            && self.temps.is_none()
+            && self.smart.is_none()
+            && self.ups.is_none()
+            && self.power.is_none()
+            && self.sensors.is_none()
+    }
+
+    pub fn push(&mut self, metric: Metric) {
+        match metric {
+            Metric::System(system) => self.system = Some(system),
+            Metric::Disks(disks) => self.disks = Some(disks),
+            Metric::Network(network) => self.network = Some(network),
+            Metric::Battery(battery) => self.battery = battery,
+            Metric::Processes(processes) => self.processes = Some(processes),
+            Metric::GpuProcesses(processes) => self.gpu_processes = Some(processes),
+            Metric::Fans(fans) => self.fans = Some(fans),
+            Metric::Temps(temps) => self.temps = Some(temps),
+            Metric::Smart(smart) => self.smart = Some(smart),
+            Metric::Ups(ups) => self.ups = ups,
+            Metric::Power(power) => self.power = power,
+            #[cfg(target_os = "linux")]
+            Metric::Rpi(rpi) => self.rpi = rpi,
+            // HWiNFO, SNMP and IPMI share the one list
+            Metric::Sensors(sensors) => {
+                let all = self.sensors.get_or_insert_with(Vec::new);
+                all.extend(sensors);
+                all.truncate(sensor_info::MAX_READINGS);
+            }
+        }
+    }
+}
+
+pub struct Registry {
+    workers: Vec<Supervised>,
+    // For rebuilding collectors that had to be restarted
+    config: Config,
+    timeout: Duration,
+    restart_after: Duration,
+    // Latest metric per collector, reused when one misses the timeout
+    last: Vec<Option<Metric>>,
+    smoothing: Option<smoothing::Smoothing>,
+    low_power: bool,
+}
+
+impl Registry {
+    pub const DEFAULT_COLLECTORS: [&'static str; 4] = ["system", "disks", "network", "battery"];
+
+    pub fn new(config: &Config) -> Self {
+        let mut workers = Vec::new();
+        for name in &config.collectors {
+            match Self::create(name, config) {
+                Some(collector) => workers.push(Supervised::spawn(collector, false)),
+                None => tracing::warn!(%name, "Unknown collector, skipping"),
+            }
+        }
+
+        Registry {
+            last: vec![None; workers.len()],
+            workers,
+            config: config.clone(),
+            timeout: Duration::from_millis(config.collector_timeout),
+            restart_after: Duration::from_secs(config.collector_restart_after),
+            smoothing: smoothing::Smoothing::new(config.smoothing),
+            low_power: false,
+        }
+    }
+
+    fn create(name: &str, config: &Config) -> Option<Box<dyn Collector>> {
+        let collector: Box<dyn Collector> = match name {
+            "system" => Box::new(system_info::SystemCollector::new(
+                config.gpu_index,
+                config.gpu_prefer,
+                config.interval,
+                config.units,
+            )),
+            "disks" => Box::new(disk_info::DiskCollector::new(
+                config.disks.clone(),
+                config.units,
+            )),
+            "network" => Box::new(network_info::NetworkCollector::new(config.units)),
+            "battery" => Box::new(battery_info::BatteryCollector),
+            "processes" => Box::new(process_info::ProcessCollector::new(config.process_count)),
+            "gpu_processes" => Box::new(gpu_process_info::GpuProcessCollector::new(
+                config.process_count,
+            )),
+            "fans" => Box::new(fan_info::FanCollector),
+            "temps" => Box::new(temp_info::TempCollector::new(config.temp_sensors.clone())),
+            "smart" => Box::new(smart_info::SmartCollector::default()),
+            "ups" => Box::new(ups_info::UpsCollector::new(config.ups.clone())),
+            "power" => Box::new(power_info::PowerCollector::new(config.interval)),
+            #[cfg(target_os = "linux")]
+            "rpi" => Box::new(rpi_info::RpiCollector::default()),
+            #[cfg(target_os = "windows")]
+            "hwinfo" => Box::new(hwinfo::HwinfoCollector::new(config.hwinfo_sensors.clone())),
+            "ipmi" => Box::new(ipmi_info::IpmiCollector::new(config.ipmi.clone())),
+            "snmp" => Box::new(snmp_info::SnmpCollector::new(config.snmp.clone())),
+            _ => return None,
+        };
+        Some(collector)
+    }
+
+    pub fn set_low_power(&mut self, low_power: bool) {
+        self.low_power = low_power;
+        for worker in &self.workers {
+            worker.set_low_power(low_power);
+        }
+    }
+
+    fn is_skipped(&self, name: &str) -> bool {
+        self.low_power && self.config.low_power.skip.iter().any(|skip| skip == name)
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.workers.iter().map(|w| w.name()).collect()
+    }
+
+    // All collectors run at once in their own tasks, a slow one only costs its own freshness
+    pub async fn collect(&mut self) -> Sample {
+        let (timeout, restart_after) = (self.timeout, self.restart_after);
+        let skipped: Vec<bool> = self
+            .workers
+            .iter()
+            .map(|w| self.is_skipped(w.name()))
+            .collect();
+        let outcomes = futures::future::join_all(self.workers.iter_mut().zip(&skipped).map(
+            |(worker, skipped)| async move {
+                match skipped {
+                    true => None,
+                    false => Some(worker.collect(timeout, restart_after).await),
+                }
+            },
+        ))
+        .await;
+
+        // Skipped collectors send nothing rather than a stale value
+        let mut sample = Sample::default();
+        for (index, outcome) in outcomes.into_iter().enumerate() {
+            let Some(outcome) = outcome else {
+                continue;
+            };
+
+            let name = self.workers[index].name();
+            match outcome {
+                Outcome::Collected(metric) => self.last[index] = Some(metric),
+                Outcome::Slow => {
+                    tracing::warn!(name, "Timed out after {timeout:?}, reusing the last value")
+                }
+                Outcome::Stuck => {
+                    tracing::warn!(name, "Collector stuck or crashed, restarting it");
+                    if let Some(collector) = Self::create(name, &self.config) {
+                        self.workers[index] = Supervised::spawn(collector, self.low_power);
+                    }
+                }
+            }
+
+            if let Some(metric) = &self.last[index] {
+                sample.push(metric.clone());
+            }
+        }
+
+        if let Some(smoothing) = &mut self.smoothing {
+            smoothing.apply(&mut sample);
+        }
+        sample
+    }
+}
@@ -0,0 +1,107 @@
+use super::{Collector, Metric};
+use crate::helpers::pop_4u8;
+use crate::units::Units;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NetworkInfo {
+    pub rx_rate: u16,
+    pub rx_unit: [u8; 4],
+    pub tx_rate: u16,
+    pub tx_unit: [u8; 4],
+    // Unscaled bytes per second for sinks and the JSON outputs, left out of the packets.
+    // Agents from before these existed send none
+    #[serde(default)]
+    pub rx_bytes: u64,
+    #[serde(default)]
+    pub tx_bytes: u64,
+}
+
+impl NetworkInfo {
+    // Layout of the v1 NetworkPacket and the v2 TAG_NETWORK field
+    pub fn wire(&self) -> (u16, [u8; 4], u16, [u8; 4]) {
+        (self.rx_rate, self.rx_unit, self.tx_rate, self.tx_unit)
+    }
+
+    fn encode_rate(bytes_per_sec: u64, units: Units) -> (u16, [u8; 4]) {
+        let rate = units.scale(bytes_per_sec);
+        (rate.tenths, pop_4u8(rate.unit.as_bytes()))
+    }
+
+    pub fn from_rates(rx_bytes: u64, tx_bytes: u64, units: Units) -> Self {
+        let (rx_rate, rx_unit) = Self::encode_rate(rx_bytes, units);
+        let (tx_rate, tx_unit) = Self::encode_rate(tx_bytes, units);
+
+        NetworkInfo {
+            rx_rate,
+            rx_unit,
+            tx_rate,
+            tx_unit,
+            rx_bytes,
+            tx_bytes,
+        }
+    }
+
+    pub fn get_network_info(
+        networks: &mut sysinfo::Networks,
+        last_refresh: &mut Instant,
+        units: Units,
+    ) -> Self {
+        // received()/transmitted() are counted since the previous refresh
+        networks.refresh();
+        let elapsed = last_refresh.elapsed().as_secs_f64().max(f64::EPSILON);
+        *last_refresh = Instant::now();
+
+        let (rx, tx) = networks
+            .iter()
+            .filter(|(name, _)| !name.starts_with("lo"))
+            .fold((0, 0), |(rx, tx), (_, data)| {
+                (rx + data.received(), tx + data.transmitted())
+            });
+
+        Self::from_rates(
+            (rx as f64 / elapsed) as u64,
+            (tx as f64 / elapsed) as u64,
+            units,
+        )
+    }
+}
+
+pub struct NetworkCollector {
+    networks: sysinfo::Networks,
+    last_refresh: Instant,
+    units: Units,
+}
+
+impl Default for NetworkCollector {
+    fn default() -> Self {
+        Self::new(Units::default())
+    }
+}
+
+impl NetworkCollector {
+    pub fn new(units: Units) -> Self {
+        NetworkCollector {
+            networks: sysinfo::Networks::new_with_refreshed_list(),
+            last_refresh: Instant::now(),
+            units,
+        }
+    }
+}
+
+#[async_trait]
+impl Collector for NetworkCollector {
+    fn name(&self) -> &'static str {
+        "network"
+    }
+
+    async fn collect(&mut self) -> Metric {
+        Metric::Network(NetworkInfo::get_network_info(
+            &mut self.networks,
+            &mut self.last_refresh,
+            self.units,
+        ))
+    }
+}
@@ -0,0 +1,228 @@
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::AsyncBufReadExt;
+
+// Paths below <gpu> that are kept, everything else in the report is skipped while streaming
+const FIELDS: [&str; 12] = [
+    "product_name",
+    "utilization/gpu_util",
+    "utilization/encoder_util",
+    "utilization/decoder_util",
+    "fb_memory_usage/total",
+    "fb_memory_usage/used",
+    "temperature/gpu_temp",
+    // Renamed to gpu_power_readings in newer drivers
+    "power_readings/power_draw",
+    "gpu_power_readings/power_draw",
+    "clocks/graphics_clock",
+    "clocks/mem_clock",
+    "fan_speed",
+];
+
+// --query-gpu fields for the streaming mode and the XML paths they are stored under.
+// Encoder/decoder load is left out, older drivers refuse to start with unknown fields. The
+// name goes last, it's free text and may contain commas itself
+const STREAM_FIELDS: [(&str, &str); 9] = [
+    ("utilization.gpu", "utilization/gpu_util"),
+    ("memory.total", "fb_memory_usage/total"),
+    ("memory.used", "fb_memory_usage/used"),
+    ("temperature.gpu", "temperature/gpu_temp"),
+    ("power.draw", "power_readings/power_draw"),
+    ("clocks.gr", "clocks/graphics_clock"),
+    ("clocks.mem", "clocks/mem_clock"),
+    ("fan.speed", "fan_speed"),
+    ("name", "product_name"),
+];
+
+// One <gpu> node of `nvidia-smi -q -x`, or one line of the streaming mode
+#[derive(Debug, Clone, Default)]
+pub struct SmiGpu {
+    values: Vec<(&'static str, String)>,
+}
+
+impl SmiGpu {
+    pub fn text(&self, path: &str) -> Option<&str> {
+        self.values
+            .iter()
+            .find(|(field, _)| *field == path)
+            .map(|(_, value)| value.as_str())
+    }
+
+    // Leading number of values like "45 %", "8192 MiB" or "45.23 W", None for "N/A"
+    pub fn number<T: FromStr>(&self, path: &str) -> Option<T> {
+        self.text(path)?.split_whitespace().next()?.parse().ok()
+    }
+}
+
+// e.g. "0, 5, 10240, 512, 45, 30.12, 210, 405, 0, NVIDIA GeForce RTX 3080", unsupported
+// values read "[N/A]" and simply don't parse as numbers
+fn parse_line(line: &str) -> Option<(usize, SmiGpu)> {
+    // The index, then everything up to the name split off, the rest is the name
+    let mut columns = line.splitn(STREAM_FIELDS.len() + 1, ',').map(str::trim);
+    let index = columns.next()?.parse().ok()?;
+    let gpu = SmiGpu {
+        values: STREAM_FIELDS
+            .iter()
+            .map(|(_, path)| *path)
+            .zip(columns.map(str::to_owned))
+            .collect(),
+    };
+    Some((index, gpu))
+}
+
+pub fn parse(xml: &str) -> Result<Vec<SmiGpu>, quick_xml::Error> {
+    let mut reader = Reader::from_str(xml);
+    reader.trim_text(true);
+
+    let mut gpus = Vec::new();
+    let mut current: Option<SmiGpu> = None;
+    // Elements opened inside the current <gpu>
+    let mut path: Vec<String> = Vec::new();
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(element) => match &current {
+                Some(_) => path.push(String::from_utf8_lossy(element.name().as_ref()).into_owned()),
+                None if element.name().as_ref() == b"gpu" => current = Some(SmiGpu::default()),
+                None => {}
+            },
+            Event::End(_) => {
+                if current.is_some() && path.pop().is_none() {
+                    gpus.extend(current.take());
+                }
+            }
+            Event::Text(text) => {
+                let Some(gpu) = &mut current else {
+                    continue;
+                };
+                let Some(field) = FIELDS
+                    .iter()
+                    .find(|field| field.split('/').eq(path.iter().map(String::as_str)))
+                else {
+                    continue;
+                };
+                gpu.values.push((field, text.unescape()?.into_owned()));
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(gpus)
+}
+
+// A single `nvidia-smi --query-gpu ... --loop-ms <interval>` kept running in the background,
+// so refreshing doesn't fork a new process every tick
+pub struct SmiStream {
+    // Latest line per GPU index
+    gpus: Arc<Mutex<Vec<SmiGpu>>>,
+    running: Arc<AtomicBool>,
+    // Stops itself when nothing read from it for a few intervals, e.g. while collection is paused
+    last_read: Arc<Mutex<Instant>>,
+    idle: Arc<AtomicBool>,
+}
+
+impl SmiStream {
+    pub fn spawn(interval: Duration) -> Option<Self> {
+        let query = std::iter::once("index")
+            .chain(STREAM_FIELDS.iter().map(|(field, _)| *field))
+            .collect::<Vec<_>>()
+            .join(",");
+
+        let mut child = tokio::process::Command::new("nvidia-smi")
+            .arg(format!("--query-gpu={query}"))
+            .arg("--format=csv,noheader,nounits")
+            .arg(format!("--loop-ms={}", interval.as_millis().max(1)))
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| tracing::debug!("Failed to start nvidia-smi stream: {e}"))
+            .ok()?;
+        let stdout = child.stdout.take()?;
+
+        let gpus = Arc::new(Mutex::new(Vec::new()));
+        let running = Arc::new(AtomicBool::new(true));
+        let last_read = Arc::new(Mutex::new(Instant::now()));
+        let idle = Arc::new(AtomicBool::new(false));
+        let (shared, still_running) = (gpus.clone(), running.clone());
+        let (read, stopped_idle) = (last_read.clone(), idle.clone());
+        let idle_after = interval * 3 + Duration::from_secs(1);
+        tokio::spawn(async move {
+            let _child = child;
+            let mut lines = tokio::io::BufReader::new(stdout).lines();
+
+            while let Ok(Some(line)) = lines.next_line().await {
+                let Some((index, gpu)) = parse_line(&line) else {
+                    continue;
+                };
+
+                let mut gpus = shared.lock().unwrap();
+                if gpus.len() <= index {
+                    gpus.resize_with(index + 1, SmiGpu::default);
+                }
+                gpus[index] = gpu;
+                drop(gpus);
+
+                // Dropping the child kills nvidia-smi, the next read starts a new one
+                if read.lock().unwrap().elapsed() > idle_after {
+                    tracing::debug!("Nobody reads the nvidia-smi stream, stopping it");
+                    stopped_idle.store(true, Ordering::Relaxed);
+                    still_running.store(false, Ordering::Relaxed);
+                    return;
+                }
+            }
+
+            tracing::warn!("nvidia-smi stream exited, querying it every tick instead");
+            still_running.store(false, Ordering::Relaxed);
+        });
+
+        Some(SmiStream {
+            gpus,
+            running,
+            last_read,
+            idle,
+        })
+    }
+
+    // None until the first lines arrive and after nvidia-smi exited
+    pub fn latest(&self) -> Option<Vec<SmiGpu>> {
+        *self.last_read.lock().unwrap() = Instant::now();
+        if !self.running.load(Ordering::Relaxed) {
+            return None;
+        }
+        let gpus = self.gpus.lock().unwrap();
+        (!gpus.is_empty()).then(|| gpus.clone())
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.idle.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_with_commas_stay_whole() {
+        let (index, gpu) =
+            parse_line("1, 5, 10240, 512, 45, [N/A], 210, 405, 0, NVIDIA A100, 80GB, PCIe")
+                .unwrap();
+        assert_eq!(index, 1);
+        assert_eq!(gpu.text("product_name"), Some("NVIDIA A100, 80GB, PCIe"));
+        assert_eq!(gpu.number::<u32>("fb_memory_usage/total"), Some(10240));
+        assert_eq!(gpu.number::<f32>("power_readings/power_draw"), None);
+        assert_eq!(gpu.number::<u32>("fan_speed"), Some(0));
+    }
+
+    #[test]
+    fn lines_without_an_index_are_skipped() {
+        assert!(parse_line("").is_none());
+        assert!(parse_line("[N/A], 5, 10240").is_none());
+    }
+}
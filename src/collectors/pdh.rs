@@ -0,0 +1,116 @@
+use std::sync::Mutex;
+use windows_sys::Win32::System::Performance::{
+    PdhAddEnglishCounterW, PdhCloseQuery, PdhCollectQueryData, PdhGetFormattedCounterArrayW,
+    PdhOpenQueryW, PDH_FMT_COUNTERVALUE_ITEM_W,
+};
+
+const PDH_FMT_DOUBLE: u32 = 0x0000_0200;
+const PDH_MORE_DATA: u32 = 0x8000_07D2;
+const PDH_CSTATUS_NEW_DATA: u32 = 1;
+
+// Same counters Task Manager uses for the "3D", "Video Encode" and "Video Decode" graphs
+const GPU_ENGINE_3D: &str = "\\GPU Engine(*engtype_3D)\\Utilization Percentage";
+const GPU_ENGINE_ENCODE: &str = "\\GPU Engine(*engtype_VideoEncode)\\Utilization Percentage";
+const GPU_ENGINE_DECODE: &str = "\\GPU Engine(*engtype_VideoDecode)\\Utilization Percentage";
+
+// Kept open so every tick diffs against the previous one
+static GPU_ENGINE_QUERY: Mutex<Option<CounterQuery>> = Mutex::new(None);
+static GPU_ENCODE_QUERY: Mutex<Option<CounterQuery>> = Mutex::new(None);
+static GPU_DECODE_QUERY: Mutex<Option<CounterQuery>> = Mutex::new(None);
+
+struct CounterQuery {
+    query: isize,
+    counter: isize,
+}
+
+impl CounterQuery {
+    fn open(path: &str) -> Option<Self> {
+        let path: Vec<u16> = path.encode_utf16().chain(std::iter::once(0)).collect();
+        let mut query = 0;
+        let mut counter = 0;
+
+        unsafe {
+            if PdhOpenQueryW(std::ptr::null(), 0, &mut query) != 0 {
+                return None;
+            }
+
+            if PdhAddEnglishCounterW(query, path.as_ptr(), 0, &mut counter) != 0 {
+                PdhCloseQuery(query);
+                return None;
+            }
+
+            // Rate counters need a first sample to compare against
+            PdhCollectQueryData(query);
+        }
+
+        Some(CounterQuery { query, counter })
+    }
+
+    // Sum over all instances matched by the wildcard
+    fn sum(&self) -> Option<f64> {
+        unsafe {
+            if PdhCollectQueryData(self.query) != 0 {
+                return None;
+            }
+
+            let mut size = 0u32;
+            let mut count = 0u32;
+            let status = PdhGetFormattedCounterArrayW(
+                self.counter,
+                PDH_FMT_DOUBLE,
+                &mut size,
+                &mut count,
+                std::ptr::null_mut(),
+            );
+            if status != PDH_MORE_DATA {
+                return None;
+            }
+
+            // The buffer holds the items followed by their instance names
+            let item_size = std::mem::size_of::<PDH_FMT_COUNTERVALUE_ITEM_W>();
+            let mut items: Vec<PDH_FMT_COUNTERVALUE_ITEM_W> =
+                Vec::with_capacity(size as usize / item_size + 1);
+            let status = PdhGetFormattedCounterArrayW(
+                self.counter,
+                PDH_FMT_DOUBLE,
+                &mut size,
+                &mut count,
+                items.as_mut_ptr(),
+            );
+            if status != 0 {
+                return None;
+            }
+            items.set_len(count as usize);
+
+            Some(
+                items
+                    .iter()
+                    .filter(|item| item.FmtValue.CStatus <= PDH_CSTATUS_NEW_DATA)
+                    .map(|item| item.FmtValue.Anonymous.doubleValue)
+                    .sum(),
+            )
+        }
+    }
+}
+
+fn get_engine_usage(query: &Mutex<Option<CounterQuery>>, path: &str) -> Option<u64> {
+    let mut query = query.lock().unwrap();
+    if query.is_none() {
+        *query = CounterQuery::open(path);
+    }
+
+    let usage = query.as_ref()?.sum()?;
+    Some(usage.clamp(0.0, 100.0).round() as u64)
+}
+
+pub fn get_gpu_usage() -> Option<u64> {
+    get_engine_usage(&GPU_ENGINE_QUERY, GPU_ENGINE_3D)
+}
+
+pub fn get_encoder_usage() -> Option<u64> {
+    get_engine_usage(&GPU_ENCODE_QUERY, GPU_ENGINE_ENCODE)
+}
+
+pub fn get_decoder_usage() -> Option<u64> {
+    get_engine_usage(&GPU_DECODE_QUERY, GPU_ENGINE_DECODE)
+}
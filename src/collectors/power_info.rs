@@ -0,0 +1,274 @@
+use super::{Collector, Metric};
+#[cfg(target_os = "linux")]
+use crate::helpers::read_sysfs_u64;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+#[cfg(target_os = "linux")]
+use std::path::PathBuf;
+#[cfg(target_os = "macos")]
+use std::sync::{Arc, Mutex};
+#[cfg(target_os = "linux")]
+use std::time::Instant;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ClusterInfo {
+    // e.g. "E" or "P0"
+    pub name: [u8; 8],
+    // Active residency in percent
+    pub usage: u8,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PowerInfo {
+    // Watts x10, u16::MAX if unknown
+    pub cpu_power: u16,
+    pub gpu_power: u16,
+    // Apple Silicon only
+    pub clusters: Vec<ClusterInfo>,
+}
+
+impl Default for PowerInfo {
+    fn default() -> Self {
+        PowerInfo {
+            cpu_power: u16::MAX,
+            gpu_power: u16::MAX,
+            clusters: Vec::new(),
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+impl PowerInfo {
+    // Tenths of a watt from "CPU Power: 1234 mW"
+    fn parse_milliwatts(value: &str) -> Option<u16> {
+        let mw: f64 = value.trim().trim_end_matches("mW").trim().parse().ok()?;
+        Some((mw / 100.0).round().min(u16::MAX as f64 - 1.0) as u16)
+    }
+
+    fn parse_line(&mut self, line: &str) {
+        let Some((key, value)) = line.split_once(':') else {
+            return;
+        };
+
+        match key {
+            "CPU Power" => self.cpu_power = Self::parse_milliwatts(value).unwrap_or(u16::MAX),
+            "GPU Power" => self.gpu_power = Self::parse_milliwatts(value).unwrap_or(u16::MAX),
+            // "P0-Cluster HW active residency:  12.34% (600 MHz: ...)"
+            _ => {
+                let Some(name) = key.strip_suffix("-Cluster HW active residency") else {
+                    return;
+                };
+                let Some(usage) = value
+                    .trim()
+                    .split('%')
+                    .next()
+                    .and_then(|usage| usage.parse::<f32>().ok())
+                else {
+                    return;
+                };
+
+                self.clusters.push(ClusterInfo {
+                    name: crate::helpers::pop_8u8(name.as_bytes()),
+                    usage: crate::helpers::percent("cluster_usage", usage as f64),
+                });
+            }
+        }
+    }
+
+    // powermetrics needs root and prints one sample per interval, so it keeps running
+    // in the background and the collector hands out the latest complete sample
+    fn spawn_powermetrics(interval: u64) -> Option<Arc<Mutex<Option<Self>>>> {
+        use tokio::io::AsyncBufReadExt;
+
+        let mut child = tokio::process::Command::new("powermetrics")
+            .args(["--samplers", "cpu_power,gpu_power"])
+            .arg("-i")
+            .arg(interval.to_string())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| tracing::warn!("Failed to start powermetrics (needs root): {e}"))
+            .ok()?;
+        let stdout = child.stdout.take()?;
+
+        let latest = Arc::new(Mutex::new(None));
+        let shared = latest.clone();
+        tokio::spawn(async move {
+            let _child = child;
+            let mut lines = tokio::io::BufReader::new(stdout).lines();
+            let mut current: Option<PowerInfo> = None;
+
+            while let Ok(Some(line)) = lines.next_line().await {
+                // The collector is gone, dropping the child kills powermetrics
+                if Arc::strong_count(&shared) == 1 {
+                    return;
+                }
+
+                // A sample is complete once the next one starts
+                if line.starts_with("*** Sampled system activity") {
+                    if let Some(sample) = current.replace(PowerInfo::default()) {
+                        *shared.lock().unwrap() = Some(sample);
+                    }
+                    continue;
+                }
+                if let Some(sample) = &mut current {
+                    sample.parse_line(&line);
+                }
+            }
+
+            tracing::warn!("powermetrics exited");
+            *shared.lock().unwrap() = current;
+        });
+
+        Some(latest)
+    }
+}
+
+// One package domain of /sys/class/powercap, energy_uj only ever counts up (and wraps)
+#[cfg(target_os = "linux")]
+struct RaplDomain {
+    path: PathBuf,
+    max_energy: u64,
+    last: u64,
+}
+
+#[cfg(target_os = "linux")]
+struct Rapl {
+    domains: Vec<RaplDomain>,
+    last_read: Instant,
+}
+
+#[cfg(target_os = "linux")]
+impl Rapl {
+    // intel-rapl:0 is a whole package, intel-rapl:0:0 its cores, so only the former are summed
+    fn probe() -> Self {
+        let mut domains = Vec::new();
+        let entries = std::fs::read_dir("/sys/class/powercap")
+            .into_iter()
+            .flatten();
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if !name.starts_with("intel-rapl:") || name.matches(':').count() != 1 {
+                continue;
+            }
+
+            let path = entry.path();
+            // Only root can read energy_uj since the Platypus side channel fix
+            let Some(last) = read_sysfs_u64(&path.join("energy_uj")) else {
+                tracing::warn!(%name, "Failed to read RAPL energy, it usually needs root");
+                continue;
+            };
+            domains.push(RaplDomain {
+                max_energy: read_sysfs_u64(&path.join("max_energy_range_uj")).unwrap_or(u64::MAX),
+                path,
+                last,
+            });
+        }
+
+        if domains.is_empty() {
+            tracing::debug!("No readable RAPL domains");
+        }
+        Rapl {
+            domains,
+            last_read: Instant::now(),
+        }
+    }
+
+    // Package power in tenths of a watt since the previous read
+    fn read(&mut self) -> Option<u16> {
+        if self.domains.is_empty() {
+            return None;
+        }
+
+        let elapsed = self.last_read.elapsed().as_micros() as u64;
+        self.last_read = Instant::now();
+
+        let mut energy = 0;
+        for domain in &mut self.domains {
+            let current = read_sysfs_u64(&domain.path.join("energy_uj"))?;
+            energy += match current >= domain.last {
+                true => current - domain.last,
+                false => domain.max_energy - domain.last + current,
+            };
+            domain.last = current;
+        }
+
+        // Microjoules per microsecond are watts
+        (elapsed > 0).then(|| {
+            (energy as f64 / elapsed as f64 * 10.0)
+                .round()
+                .min(u16::MAX as f64 - 1.0) as u16
+        })
+    }
+}
+
+pub struct PowerCollector {
+    // Probed on the first tick
+    #[cfg(target_os = "linux")]
+    rapl: Option<Rapl>,
+    #[cfg(target_os = "macos")]
+    interval: u64,
+    // Started on the first tick, None inside if powermetrics couldn't be started
+    #[cfg(target_os = "macos")]
+    powermetrics: Option<Option<Arc<Mutex<Option<PowerInfo>>>>>,
+}
+
+impl PowerCollector {
+    #[cfg_attr(not(target_os = "macos"), allow(unused_variables))]
+    pub fn new(interval: u64) -> Self {
+        PowerCollector {
+            #[cfg(target_os = "linux")]
+            rapl: None,
+            #[cfg(target_os = "macos")]
+            interval,
+            #[cfg(target_os = "macos")]
+            powermetrics: None,
+        }
+    }
+}
+
+#[async_trait]
+impl Collector for PowerCollector {
+    fn name(&self) -> &'static str {
+        "power"
+    }
+
+    async fn collect(&mut self) -> Metric {
+        #[cfg(target_os = "macos")]
+        {
+            let interval = self.interval;
+            let powermetrics = self
+                .powermetrics
+                .get_or_insert_with(|| PowerInfo::spawn_powermetrics(interval));
+            Metric::Power(
+                powermetrics
+                    .as_ref()
+                    .and_then(|p| p.lock().unwrap().clone()),
+            )
+        }
+
+        #[cfg(target_os = "linux")]
+        {
+            // Power is an energy difference, so the first tick has nothing to report yet
+            let Some(rapl) = &mut self.rapl else {
+                self.rapl = Some(Rapl::probe());
+                return Metric::Power(None);
+            };
+            if rapl.domains.is_empty() {
+                return Metric::Power(None);
+            }
+
+            Metric::Power(Some(PowerInfo {
+                cpu_power: rapl.read().unwrap_or(u16::MAX),
+                ..Default::default()
+            }))
+        }
+
+        #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+        {
+            Metric::Power(None)
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use super::{Collector, Metric};
+use crate::helpers::{percent, pop_16u8};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use sysinfo::{MemoryRefreshKind, ProcessRefreshKind};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProcessInfo {
+    pub name: [u8; 16],
+    // Percent of the whole machine, not of a single core
+    pub cpu_usage: u8,
+    // Percent of total RAM
+    pub ram_usage: u8,
+}
+
+impl ProcessInfo {
+    // Processes sharing a name (browsers, electron apps) are summed up
+    pub fn get_top_processes(system: &mut sysinfo::System, count: usize) -> Vec<Self> {
+        system.refresh_memory_specifics(MemoryRefreshKind::new().with_ram());
+        system.refresh_processes_specifics(ProcessRefreshKind::new().with_cpu().with_memory());
+
+        let cores = system.cpus().len().max(1) as f32;
+        let total_memory = system.total_memory().max(1) as f64;
+
+        let mut by_name: HashMap<&str, (f32, u64)> = HashMap::new();
+        for process in system.processes().values() {
+            let entry = by_name.entry(process.name()).or_default();
+            entry.0 += process.cpu_usage();
+            entry.1 += process.memory();
+        }
+
+        let mut processes: Vec<_> = by_name.into_iter().collect();
+        processes.sort_by(|(_, (a, _)), (_, (b, _))| b.total_cmp(a));
+
+        processes
+            .into_iter()
+            .take(count)
+            .map(|(name, (cpu, memory))| ProcessInfo {
+                name: pop_16u8(name.as_bytes()),
+                cpu_usage: percent("process_cpu", (cpu / cores) as f64),
+                ram_usage: percent("process_ram", memory as f64 / total_memory * 100.0),
+            })
+            .collect()
+    }
+}
+
+pub struct ProcessCollector {
+    system: sysinfo::System,
+    count: usize,
+}
+
+impl ProcessCollector {
+    pub fn new(count: usize) -> Self {
+        ProcessCollector {
+            // CPU usage of a process needs the CPU list to scale by core count
+            system: sysinfo::System::new_with_specifics(
+                sysinfo::RefreshKind::new().with_cpu(sysinfo::CpuRefreshKind::new()),
+            ),
+            count,
+        }
+    }
+}
+
+#[async_trait]
+impl Collector for ProcessCollector {
+    fn name(&self) -> &'static str {
+        "processes"
+    }
+
+    async fn collect(&mut self) -> Metric {
+        Metric::Processes(ProcessInfo::get_top_processes(&mut self.system, self.count))
+    }
+}
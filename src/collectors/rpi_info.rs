@@ -0,0 +1,94 @@
+use super::{Collector, Metric};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RpiInfo {
+    // SoC temperature in degrees Celsius, u8::MAX if unknown
+    pub temp: u8,
+    // get_throttled bit field: 0 under-voltage, 1 ARM frequency capped, 2 throttled,
+    // 3 soft temperature limit, bits 16-19 the same flags since boot
+    pub throttled: u32,
+    // Core voltage in millivolts
+    pub core_voltage: u16,
+    // VideoCore memory split in MB
+    pub gpu_mem: u16,
+}
+
+// Every vcgencmd answer looks like "name=value", e.g. "temp=47.2'C" or "volt=0.8500V"
+async fn vcgencmd(args: &[&str]) -> Option<String> {
+    let output = tokio::process::Command::new("vcgencmd")
+        .args(args)
+        .kill_on_drop(true)
+        .output()
+        .await
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let (_, value) = stdout.trim().split_once('=')?;
+    Some(value.to_owned())
+}
+
+// Leading number of a value like "47.2'C"
+fn parse_number(value: &str) -> Option<f32> {
+    let end = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    value[..end].parse().ok()
+}
+
+impl RpiInfo {
+    pub async fn get_rpi_info(gpu_mem: u16) -> Option<Self> {
+        // Not a Pi (or the firmware tools aren't installed)
+        let temp = vcgencmd(&["measure_temp"]).await?;
+
+        let (throttled, volts) = tokio::join!(
+            vcgencmd(&["get_throttled"]),
+            vcgencmd(&["measure_volts", "core"])
+        );
+
+        Some(RpiInfo {
+            temp: parse_number(&temp).map_or(u8::MAX, |t| t.round().min(254.0) as u8),
+            throttled: throttled
+                .and_then(|t| u32::from_str_radix(t.trim_start_matches("0x"), 16).ok())
+                .unwrap_or(0),
+            core_voltage: volts
+                .as_deref()
+                .and_then(parse_number)
+                .map_or(0, |v| (v * 1000.0).round() as u16),
+            gpu_mem,
+        })
+    }
+
+    pub async fn get_gpu_mem() -> u16 {
+        vcgencmd(&["get_mem", "gpu"])
+            .await
+            .as_deref()
+            .and_then(parse_number)
+            .map_or(0, |mem| mem as u16)
+    }
+}
+
+#[derive(Default)]
+pub struct RpiCollector {
+    // The memory split is set at boot, so it's only asked for once
+    gpu_mem: Option<u16>,
+}
+
+#[async_trait]
+impl Collector for RpiCollector {
+    fn name(&self) -> &'static str {
+        "rpi"
+    }
+
+    async fn collect(&mut self) -> Metric {
+        let gpu_mem = match self.gpu_mem {
+            Some(gpu_mem) => gpu_mem,
+            None => *self.gpu_mem.insert(RpiInfo::get_gpu_mem().await),
+        };
+        Metric::Rpi(RpiInfo::get_rpi_info(gpu_mem).await)
+    }
+}
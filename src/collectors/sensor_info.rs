@@ -0,0 +1,12 @@
+use serde::{Deserialize, Serialize};
+
+// More readings don't fit in a packet the Flipper can take
+pub const MAX_READINGS: usize = 16;
+
+// A labelled value from outside the usual collectors (HWiNFO, SNMP, IPMI), sent as is
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SensorReading {
+    pub label: [u8; 16],
+    pub value: f32,
+    pub unit: [u8; 4],
+}
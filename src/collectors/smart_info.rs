@@ -0,0 +1,101 @@
+use super::{Collector, Metric};
+use crate::helpers::pop_8u8;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+// SMART data changes slowly and smartctl may wake sleeping disks, so it's polled rarely
+const REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SmartInfo {
+    // Device name without /dev/, e.g. "sda" or "nvme0"
+    pub label: [u8; 8],
+    // Degrees Celsius, u8::MAX if unknown
+    pub temp: u8,
+    // 0 OK, 1 WARN when the self-assessment failed or the drive reports a critical warning
+    pub status: u8,
+}
+
+async fn smartctl(args: &[&str]) -> Option<serde_json::Value> {
+    let output = match tokio::process::Command::new("smartctl")
+        .args(args)
+        .arg("-j")
+        .kill_on_drop(true)
+        .output()
+        .await
+    {
+        Ok(output) => output,
+        Err(e) => {
+            tracing::debug!("smartctl not available: {e}");
+            return None;
+        }
+    };
+
+    // smartctl uses its exit code as a bit mask of drive problems, so only the JSON counts
+    serde_json::from_slice(&output.stdout)
+        .map_err(|e| tracing::warn!("Failed to parse smartctl output: {e}"))
+        .ok()
+}
+
+impl SmartInfo {
+    pub async fn get_smart_info() -> Vec<Self> {
+        let Some(scan) = smartctl(&["--scan"]).await else {
+            return Vec::new();
+        };
+
+        let mut drives = Vec::new();
+        for device in scan["devices"].as_array().into_iter().flatten() {
+            let (Some(name), Some(kind)) = (device["name"].as_str(), device["type"].as_str())
+            else {
+                continue;
+            };
+            let Some(info) = smartctl(&["-a", "-d", kind, name]).await else {
+                continue;
+            };
+
+            let passed = info["smart_status"]["passed"].as_bool().unwrap_or(true);
+            let critical_warning = info["nvme_smart_health_information_log"]["critical_warning"]
+                .as_u64()
+                .unwrap_or(0);
+
+            drives.push(SmartInfo {
+                label: pop_8u8(name.trim_start_matches("/dev/").as_bytes()),
+                temp: info["temperature"]["current"]
+                    .as_u64()
+                    .map_or(u8::MAX, |temp| temp.min(u8::MAX as u64 - 1) as u8),
+                status: (!passed || critical_warning > 0) as u8,
+            });
+        }
+
+        if drives.iter().any(|drive| drive.status > 0) {
+            tracing::warn!("smartctl reports a drive with problems");
+        }
+        drives
+    }
+}
+
+#[derive(Default)]
+pub struct SmartCollector {
+    last: Option<(Instant, Vec<SmartInfo>)>,
+}
+
+#[async_trait]
+impl Collector for SmartCollector {
+    fn name(&self) -> &'static str {
+        "smart"
+    }
+
+    async fn collect(&mut self) -> Metric {
+        match &self.last {
+            Some((updated, drives)) if updated.elapsed() < REFRESH_INTERVAL => {
+                Metric::Smart(drives.clone())
+            }
+            _ => {
+                let drives = SmartInfo::get_smart_info().await;
+                self.last = Some((Instant::now(), drives.clone()));
+                Metric::Smart(drives)
+            }
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use super::Sample;
+
+// Exponential moving average over roughly `window` samples for the jumpy usage values
+pub struct Smoothing {
+    alpha: f32,
+    cpu: Option<f32>,
+    gpu: Option<f32>,
+    cores: Vec<Option<f32>>,
+}
+
+fn smooth(state: &mut Option<f32>, value: &mut u8, alpha: f32) {
+    // Unknown values pass through and restart the average once they're back
+    if *value == u8::MAX {
+        *state = None;
+        return;
+    }
+
+    let average = match *state {
+        Some(average) => average + alpha * (*value as f32 - average),
+        None => *value as f32,
+    };
+    *state = Some(average);
+    *value = average.round() as u8;
+}
+
+impl Smoothing {
+    // None when the window is too small to change anything
+    pub fn new(window: u32) -> Option<Self> {
+        (window > 1).then(|| Smoothing {
+            alpha: 2.0 / (window as f32 + 1.0),
+            cpu: None,
+            gpu: None,
+            cores: Vec::new(),
+        })
+    }
+
+    pub fn apply(&mut self, sample: &mut Sample) {
+        let Some(system) = &mut sample.system else {
+            return;
+        };
+
+        smooth(&mut self.cpu, &mut system.cpu_usage, self.alpha);
+        smooth(&mut self.gpu, &mut system.gpu_usage, self.alpha);
+
+        self.cores.resize(system.core_usage.len(), None);
+        for (state, value) in self.cores.iter_mut().zip(&mut system.core_usage) {
+            smooth(state, value, self.alpha);
+        }
+    }
+}
@@ -0,0 +1,474 @@
+use super::sensor_info::{SensorReading, MAX_READINGS};
+use super::{Collector, Metric};
+use crate::helpers::{pop_16u8, pop_4u8};
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+
+// Well under collector_timeout, LAN devices answer in a few milliseconds
+const TIMEOUT: Duration = Duration::from_millis(300);
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct SnmpConfig {
+    // host or host:port, 161 by default
+    pub host: String,
+    // SNMPv2c community
+    pub community: String,
+    pub oids: Vec<SnmpOid>,
+}
+
+impl Default for SnmpConfig {
+    fn default() -> Self {
+        SnmpConfig {
+            host: String::new(),
+            community: "public".to_owned(),
+            oids: Vec::new(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct SnmpOid {
+    // Numeric, e.g. "1.3.6.1.2.1.31.1.1.1.6.2"
+    pub oid: String,
+    pub label: String,
+    pub unit: String,
+    // Multiplied with the value (after `rate`), e.g. 0.1 for tenths of a degree
+    pub scale: f64,
+    // Per second change of a counter instead of the value, for ifHCInOctets and the like
+    pub rate: bool,
+}
+
+impl Default for SnmpOid {
+    fn default() -> Self {
+        SnmpOid {
+            oid: String::new(),
+            label: String::new(),
+            unit: String::new(),
+            scale: 1.0,
+            rate: false,
+        }
+    }
+}
+
+// Just enough BER for an SNMPv2c GetRequest and its Response
+mod ber {
+    pub const INTEGER: u8 = 0x02;
+    pub const OCTET_STRING: u8 = 0x04;
+    pub const NULL: u8 = 0x05;
+    pub const OID: u8 = 0x06;
+    pub const SEQUENCE: u8 = 0x30;
+    pub const COUNTER32: u8 = 0x41;
+    pub const GAUGE32: u8 = 0x42;
+    pub const TIMETICKS: u8 = 0x43;
+    pub const COUNTER64: u8 = 0x46;
+    pub const GET_REQUEST: u8 = 0xa0;
+    pub const RESPONSE: u8 = 0xa2;
+
+    pub fn tlv(tag: u8, value: &[u8]) -> Vec<u8> {
+        let mut out = vec![tag];
+        match value.len() {
+            len @ 0..=0x7f => out.push(len as u8),
+            len => {
+                let bytes: Vec<u8> = len
+                    .to_be_bytes()
+                    .into_iter()
+                    .skip_while(|&b| b == 0)
+                    .collect();
+                out.push(0x80 | bytes.len() as u8);
+                out.extend(bytes);
+            }
+        }
+        out.extend(value);
+        out
+    }
+
+    pub fn integer(value: i64) -> Vec<u8> {
+        let bytes = value.to_be_bytes();
+        // Shortest two's complement form
+        let mut start = 0;
+        while start < 7
+            && ((bytes[start] == 0 && bytes[start + 1] & 0x80 == 0)
+                || (bytes[start] == 0xff && bytes[start + 1] & 0x80 != 0))
+        {
+            start += 1;
+        }
+        tlv(INTEGER, &bytes[start..])
+    }
+
+    // None for anything that isn't a dotted numeric OID starting with 0, 1 or 2
+    pub fn oid(dotted: &str) -> Option<Vec<u8>> {
+        let arcs: Vec<u64> = dotted
+            .trim_start_matches('.')
+            .split('.')
+            .map(|arc| arc.parse().ok())
+            .collect::<Option<_>>()?;
+        if arcs.len() < 2 || arcs[0] > 2 {
+            return None;
+        }
+
+        let mut value = Vec::new();
+        for arc in std::iter::once(arcs[0] * 40 + arcs[1]).chain(arcs[2..].iter().copied()) {
+            // Base 128, high bit set on all but the last byte
+            let mut bytes = vec![(arc & 0x7f) as u8];
+            let mut rest = arc >> 7;
+            while rest > 0 {
+                bytes.push((rest & 0x7f) as u8 | 0x80);
+                rest >>= 7;
+            }
+            value.extend(bytes.iter().rev());
+        }
+        Some(tlv(OID, &value))
+    }
+
+    // (tag, value, rest)
+    pub fn read(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+        let (&tag, data) = data.split_first()?;
+        let (&first, mut data) = data.split_first()?;
+        let len = match first {
+            0..=0x7f => first as usize,
+            0x81..=0x84 => {
+                let count = (first & 0x7f) as usize;
+                let (bytes, rest) = (data.get(..count)?, &data[count..]);
+                data = rest;
+                bytes.iter().fold(0, |len, &b| len << 8 | b as usize)
+            }
+            _ => return None,
+        };
+        let value = data.get(..len)?;
+        Some((tag, value, &data[len..]))
+    }
+
+    pub fn unsigned(value: &[u8]) -> u64 {
+        value.iter().fold(0, |n, &b| n << 8 | b as u64)
+    }
+
+    pub fn signed(value: &[u8]) -> i64 {
+        let negative = value.first().is_some_and(|&b| b & 0x80 != 0);
+        let init = if negative { -1 } else { 0 };
+        value.iter().fold(init, |n, &b| n << 8 | b as i64)
+    }
+}
+
+fn get_request(community: &str, request_id: i32, oids: &[Vec<u8>]) -> Vec<u8> {
+    let bindings: Vec<u8> = oids
+        .iter()
+        .flat_map(|oid| ber::tlv(ber::SEQUENCE, &[oid.as_slice(), &[ber::NULL, 0]].concat()))
+        .collect();
+    let pdu = [
+        ber::integer(request_id as i64),
+        // error-status, error-index
+        ber::integer(0),
+        ber::integer(0),
+        ber::tlv(ber::SEQUENCE, &bindings),
+    ]
+    .concat();
+    let message = [
+        // version 1 is SNMPv2c
+        ber::integer(1),
+        ber::tlv(ber::OCTET_STRING, community.as_bytes()),
+        ber::tlv(ber::GET_REQUEST, &pdu),
+    ]
+    .concat();
+    ber::tlv(ber::SEQUENCE, &message)
+}
+
+// Values in request order, None for missing OIDs and non-numeric values
+fn parse_response(data: &[u8], request_id: i32) -> Option<Vec<Option<f64>>> {
+    let (ber::SEQUENCE, message, _) = ber::read(data)? else {
+        return None;
+    };
+    let (_, _version, rest) = ber::read(message)?;
+    let (_, _community, rest) = ber::read(rest)?;
+    let (ber::RESPONSE, pdu, _) = ber::read(rest)? else {
+        return None;
+    };
+    let (_, id, rest) = ber::read(pdu)?;
+    if ber::signed(id) != request_id as i64 {
+        return None;
+    }
+    let (_, status, rest) = ber::read(rest)?;
+    if ber::signed(status) != 0 {
+        tracing::debug!(status = ber::signed(status), "SNMP request failed");
+        return None;
+    }
+    let (_, _index, rest) = ber::read(rest)?;
+    let (_, mut bindings, _) = ber::read(rest)?;
+
+    let mut values = Vec::new();
+    while !bindings.is_empty() {
+        let (_, binding, rest) = ber::read(bindings)?;
+        bindings = rest;
+        let (_, _oid, value) = ber::read(binding)?;
+        let (tag, value, _) = ber::read(value)?;
+        values.push(match tag {
+            ber::INTEGER => Some(ber::signed(value) as f64),
+            ber::COUNTER32 | ber::GAUGE32 | ber::TIMETICKS | ber::COUNTER64 => {
+                Some(ber::unsigned(value) as f64)
+            }
+            // Some devices report temperatures as strings like "42.5"
+            ber::OCTET_STRING => std::str::from_utf8(value).ok()?.trim().parse().ok(),
+            // noSuchObject, noSuchInstance, endOfMibView, and anything else
+            _ => None,
+        });
+    }
+    Some(values)
+}
+
+struct Host {
+    address: String,
+    community: String,
+    // Each configured OID with its encoded form, unparseable ones are left out
+    oids: Vec<(SnmpOid, Vec<u8>)>,
+    request_id: i32,
+    // Values of the previous poll, for `rate` OIDs
+    previous: Option<(Vec<Option<f64>>, Instant)>,
+}
+
+impl Host {
+    fn new(config: SnmpConfig) -> Self {
+        let oids = config
+            .oids
+            .into_iter()
+            .filter_map(|oid| match ber::oid(&oid.oid) {
+                Some(encoded) => Some((oid, encoded)),
+                None => {
+                    tracing::warn!(oid = %oid.oid, "Not a numeric OID, skipping");
+                    None
+                }
+            })
+            .collect();
+
+        Host {
+            address: crate::helpers::with_default_port(&config.host, 161),
+            community: config.community,
+            oids,
+            request_id: 0,
+            previous: None,
+        }
+    }
+
+    async fn get(&mut self) -> Option<Vec<Option<f64>>> {
+        let socket = UdpSocket::bind("0.0.0.0:0").await.ok()?;
+        socket.connect(&self.address).await.ok()?;
+
+        self.request_id = self.request_id.wrapping_add(1) & 0x7fff_ffff;
+        let encoded: Vec<Vec<u8>> = self.oids.iter().map(|(_, oid)| oid.clone()).collect();
+        let request = get_request(&self.community, self.request_id, &encoded);
+        socket.send(&request).await.ok()?;
+
+        let mut buf = vec![0u8; 65535];
+        let received = tokio::time::timeout(TIMEOUT, socket.recv(&mut buf)).await;
+        let Ok(Ok(len)) = received else {
+            tracing::debug!(host = %self.address, "No SNMP response");
+            return None;
+        };
+        parse_response(&buf[..len], self.request_id)
+    }
+
+    async fn readings(&mut self) -> Vec<SensorReading> {
+        let Some(values) = self.get().await else {
+            return Vec::new();
+        };
+        let now = Instant::now();
+        let previous = self.previous.replace((values.clone(), now));
+
+        self.oids
+            .iter()
+            .zip(&values)
+            .enumerate()
+            .filter_map(|(index, ((oid, _), value))| {
+                let value = match oid.rate {
+                    false => (*value)?,
+                    // Nothing to compare the first poll with, or the counter wrapped
+                    true => {
+                        let (previous, at) = previous.as_ref()?;
+                        let delta = (*value)? - (*previous.get(index)?)?;
+                        let elapsed = now.duration_since(*at).as_secs_f64();
+                        (delta >= 0.0 && elapsed > 0.0).then(|| delta / elapsed)?
+                    }
+                };
+                Some(SensorReading {
+                    label: pop_16u8(oid.label.as_bytes()),
+                    value: (value * oid.scale) as f32,
+                    unit: pop_4u8(oid.unit.as_bytes()),
+                })
+            })
+            .collect()
+    }
+}
+
+pub struct SnmpCollector {
+    hosts: Vec<Host>,
+}
+
+impl SnmpCollector {
+    pub fn new(hosts: Vec<SnmpConfig>) -> Self {
+        SnmpCollector {
+            hosts: hosts.into_iter().map(Host::new).collect(),
+        }
+    }
+}
+
+#[async_trait]
+impl Collector for SnmpCollector {
+    fn name(&self) -> &'static str {
+        "snmp"
+    }
+
+    async fn collect(&mut self) -> Metric {
+        let readings = futures::future::join_all(self.hosts.iter_mut().map(Host::readings)).await;
+        let mut readings: Vec<SensorReading> = readings.into_iter().flatten().collect();
+        if readings.len() > MAX_READINGS {
+            tracing::warn!("Too many SNMP readings, sending the first {MAX_READINGS}");
+            readings.truncate(MAX_READINGS);
+        }
+        Metric::Sensors(readings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(request_id: i32, status: i64, values: &[Vec<u8>]) -> Vec<u8> {
+        let oid = ber::oid("1.3.6.1.2.1.1.3.0").unwrap();
+        let bindings: Vec<u8> = values
+            .iter()
+            .flat_map(|value| ber::tlv(ber::SEQUENCE, &[oid.as_slice(), value].concat()))
+            .collect();
+        let pdu = [
+            ber::integer(request_id as i64),
+            ber::integer(status),
+            ber::integer(0),
+            ber::tlv(ber::SEQUENCE, &bindings),
+        ]
+        .concat();
+        let message = [
+            ber::integer(1),
+            ber::tlv(ber::OCTET_STRING, b"public"),
+            ber::tlv(ber::RESPONSE, &pdu),
+        ]
+        .concat();
+        ber::tlv(ber::SEQUENCE, &message)
+    }
+
+    #[test]
+    fn integers_round_trip() {
+        assert_eq!(ber::integer(0), [ber::INTEGER, 1, 0]);
+        assert_eq!(ber::integer(127), [ber::INTEGER, 1, 0x7f]);
+        assert_eq!(ber::integer(128), [ber::INTEGER, 2, 0, 0x80]);
+        assert_eq!(ber::integer(-1), [ber::INTEGER, 1, 0xff]);
+        assert_eq!(ber::integer(-129), [ber::INTEGER, 2, 0xff, 0x7f]);
+        for value in [
+            0,
+            1,
+            -1,
+            255,
+            -256,
+            65_536,
+            i32::MAX as i64,
+            i64::MIN,
+            i64::MAX,
+        ] {
+            let encoded = ber::integer(value);
+            let (tag, decoded, rest) = ber::read(&encoded).unwrap();
+            assert_eq!(
+                (tag, ber::signed(decoded), rest),
+                (ber::INTEGER, value, &[][..])
+            );
+        }
+    }
+
+    #[test]
+    fn long_lengths_round_trip() {
+        for len in [0x7f, 0x80, 200, 300, 70_000] {
+            let value = vec![7; len];
+            let encoded = ber::tlv(ber::OCTET_STRING, &value);
+            let (tag, decoded, rest) = ber::read(&encoded).unwrap();
+            assert_eq!(
+                (tag, decoded, rest),
+                (ber::OCTET_STRING, &value[..], &[][..])
+            );
+        }
+        assert_eq!(
+            ber::tlv(ber::OCTET_STRING, &[0; 300])[..4],
+            [ber::OCTET_STRING, 0x82, 1, 44]
+        );
+    }
+
+    #[test]
+    fn oids() {
+        assert_eq!(
+            ber::oid("1.3.6.1.2.1.1.3.0").unwrap(),
+            [ber::OID, 8, 0x2b, 6, 1, 2, 1, 1, 3, 0]
+        );
+        // Arcs past 127 take more than one byte
+        assert_eq!(
+            ber::oid(".1.3.6.1.4.1.2021").unwrap(),
+            [ber::OID, 7, 0x2b, 6, 1, 4, 1, 0x8f, 0x65]
+        );
+        assert_eq!(ber::oid("1"), None);
+        assert_eq!(ber::oid("3.1"), None);
+        assert_eq!(ber::oid("1.3.six"), None);
+        assert_eq!(ber::oid(""), None);
+    }
+
+    #[test]
+    fn truncated_and_oversized_are_rejected() {
+        assert_eq!(ber::read(&[]), None);
+        assert_eq!(ber::read(&[ber::INTEGER]), None);
+        assert_eq!(ber::read(&[ber::OCTET_STRING, 5, 1, 2]), None);
+        assert_eq!(ber::read(&[ber::OCTET_STRING, 0x82, 1]), None);
+        assert_eq!(
+            ber::read(&[ber::OCTET_STRING, 0x84, 0xff, 0xff, 0xff, 0xff, 0]),
+            None
+        );
+        // Indefinite and more than four length bytes
+        assert_eq!(ber::read(&[ber::SEQUENCE, 0x80, 0, 0]), None);
+        assert_eq!(
+            ber::read(&[ber::OCTET_STRING, 0x85, 0, 0, 0, 0, 1, 0]),
+            None
+        );
+
+        let full = response(7, 0, &[ber::tlv(ber::GAUGE32, &[42])]);
+        for len in 0..full.len() {
+            assert_eq!(parse_response(&full[..len], 7), None);
+        }
+    }
+
+    #[test]
+    fn responses() {
+        let values = [
+            ber::tlv(ber::GAUGE32, &[0x01, 0x00]),
+            ber::integer(-5),
+            ber::tlv(ber::OCTET_STRING, b" 42.5 "),
+            ber::tlv(ber::OCTET_STRING, b"n/a"),
+            // noSuchObject
+            vec![0x80, 0],
+        ];
+        assert_eq!(
+            parse_response(&response(7, 0, &values), 7),
+            Some(vec![Some(256.0), Some(-5.0), Some(42.5), None, None])
+        );
+    }
+
+    #[test]
+    fn wrong_types_ids_and_errors_are_rejected() {
+        let values = [ber::tlv(ber::GAUGE32, &[42])];
+        assert_eq!(parse_response(&response(7, 0, &values), 8), None);
+        // noSuchName
+        assert_eq!(parse_response(&response(7, 2, &values), 7), None);
+
+        let mut not_a_sequence = response(7, 0, &values);
+        not_a_sequence[0] = ber::OCTET_STRING;
+        assert_eq!(parse_response(&not_a_sequence, 7), None);
+
+        // Our own request echoed back isn't a response
+        let request = get_request("public", 7, &[ber::oid("1.3.6.1.2.1.1.3.0").unwrap()]);
+        assert_eq!(parse_response(&request, 7), None);
+    }
+}
@@ -0,0 +1,97 @@
+use super::{Collector, Metric};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tracing::Instrument;
+
+enum Request {
+    Collect(oneshot::Sender<Metric>),
+    LowPower(bool),
+}
+
+pub enum Outcome {
+    Collected(Metric),
+    // Still working on it, the answer is picked up on a later tick
+    Slow,
+    // Hung past the restart limit or the task died (panicked)
+    Stuck,
+}
+
+// One collector in its own task, so a hung one (e.g. nvidia-smi wedged after a driver
+// crash) can be thrown away and rebuilt without stalling the others
+pub struct Supervised {
+    name: &'static str,
+    requests: mpsc::UnboundedSender<Request>,
+    task: JoinHandle<()>,
+    // Unanswered request and when it was sent, a slow collector isn't asked again meanwhile
+    pending: Option<(oneshot::Receiver<Metric>, Instant)>,
+}
+
+impl Supervised {
+    pub fn spawn(mut collector: Box<dyn Collector>, low_power: bool) -> Self {
+        collector.set_low_power(low_power);
+        let name = collector.name();
+        let (requests, mut receiver) = mpsc::unbounded_channel();
+
+        let task = tokio::spawn(
+            async move {
+                while let Some(request) = receiver.recv().await {
+                    match request {
+                        Request::Collect(reply) => {
+                            let _ = reply.send(collector.collect().await);
+                        }
+                        Request::LowPower(low_power) => collector.set_low_power(low_power),
+                    }
+                }
+            }
+            .instrument(tracing::debug_span!("collector", name)),
+        );
+
+        Supervised {
+            name,
+            requests,
+            task,
+            pending: None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn set_low_power(&self, low_power: bool) {
+        let _ = self.requests.send(Request::LowPower(low_power));
+    }
+
+    pub async fn collect(&mut self, timeout: Duration, restart_after: Duration) -> Outcome {
+        let (reply, sent) = match &mut self.pending {
+            Some(pending) => pending,
+            None => {
+                let (sender, reply) = oneshot::channel();
+                if self.requests.send(Request::Collect(sender)).is_err() {
+                    return Outcome::Stuck;
+                }
+                self.pending.insert((reply, Instant::now()))
+            }
+        };
+        let sent = *sent;
+
+        match tokio::time::timeout(timeout, reply).await {
+            Ok(Ok(metric)) => {
+                self.pending = None;
+                Outcome::Collected(metric)
+            }
+            Ok(Err(_)) => Outcome::Stuck,
+            Err(_) if sent.elapsed() >= restart_after => Outcome::Stuck,
+            Err(_) => Outcome::Slow,
+        }
+    }
+}
+
+// Aborting drops whatever the collector was awaiting, child processes are spawned with
+// kill_on_drop so they go with it
+impl Drop for Supervised {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
@@ -0,0 +1,368 @@
+use super::cpu_sampler::CpuSampler;
+use super::gpu_info::{GpuInfo, GpuPreference};
+use super::{Collector, Metric};
+use crate::helpers::{avg_vecu32, percent, pop_4u8};
+use crate::units::{Scaled, Units};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+use sysinfo::MemoryRefreshKind;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SystemInfo {
+    pub cpu_usage: u8,
+    pub ram_max: u16,
+    pub ram_usage: u8,
+    pub ram_unit: [u8; 4],
+    pub gpu_usage: u8,
+    pub vram_max: u16,
+    pub vram_usage: u8,
+    pub vram_unit: [u8; 4],
+    pub core_usage: Vec<u8>,
+    // Raw amounts in MiB so apps can format "12.3/32 GB" themselves, VRAM u32::MAX if unknown
+    pub ram_used_mib: u32,
+    pub ram_total_mib: u32,
+    pub vram_used_mib: u32,
+    pub vram_total_mib: u32,
+    pub gpu_temp: u8,
+    // Watts x10, u16::MAX if unknown
+    pub gpu_power: u16,
+    // MHz, u16::MAX if unknown
+    pub gpu_clock: u16,
+    pub vram_clock: u16,
+    // Percent, u8::MAX if unknown
+    pub gpu_fan: u8,
+    // Video encoder/decoder load in percent, u8::MAX if unknown
+    pub gpu_encoder: u8,
+    pub gpu_decoder: u8,
+    // 1, 5 and 15 minute load averages x100, Unix only
+    pub load_average: Option<[u16; 3]>,
+    // MHz, averaged over all cores and of the fastest core
+    pub cpu_freq_avg: u16,
+    pub cpu_freq_max: u16,
+    // Seconds since boot
+    pub uptime: u32,
+    // VALID_* bits of the values that were actually read, so 255 can be told apart from "N/A"
+    pub valid: u16,
+}
+
+impl SystemInfo {
+    pub const VALID_CPU: u16 = 1 << 0;
+    pub const VALID_RAM: u16 = 1 << 1;
+    pub const VALID_GPU: u16 = 1 << 2;
+    pub const VALID_VRAM: u16 = 1 << 3;
+    pub const VALID_GPU_TEMP: u16 = 1 << 4;
+    pub const VALID_GPU_POWER: u16 = 1 << 5;
+    pub const VALID_GPU_CLOCK: u16 = 1 << 6;
+    pub const VALID_GPU_FAN: u16 = 1 << 7;
+    pub const VALID_GPU_CODEC: u16 = 1 << 8;
+    pub const VALID_LOAD: u16 = 1 << 9;
+
+    pub const VALID_FLAGS: [(u16, &'static str); 10] = [
+        (Self::VALID_CPU, "cpu"),
+        (Self::VALID_RAM, "ram"),
+        (Self::VALID_GPU, "gpu"),
+        (Self::VALID_VRAM, "vram"),
+        (Self::VALID_GPU_TEMP, "gpu_temp"),
+        (Self::VALID_GPU_POWER, "gpu_power"),
+        (Self::VALID_GPU_CLOCK, "gpu_clock"),
+        (Self::VALID_GPU_FAN, "gpu_fan"),
+        (Self::VALID_GPU_CODEC, "gpu_codec"),
+        (Self::VALID_LOAD, "load_average"),
+    ];
+
+    // Windows has no load average, sysinfo just reports zeros there
+    fn get_load_average() -> Option<[u16; 3]> {
+        #[cfg(unix)]
+        {
+            let load = sysinfo::System::load_average();
+            Some([load.one, load.five, load.fifteen].map(|l| (l * 100.0).round() as u16))
+        }
+
+        #[cfg(not(unix))]
+        {
+            None
+        }
+    }
+
+    pub async fn get_system_info(
+        system_info: &mut sysinfo::System,
+        statics: &StaticInfo,
+        cpu: &mut CpuSampler,
+        units: Units,
+        interval: Duration,
+        refresh_gpus: bool,
+    ) -> Self {
+        let base = 1024;
+
+        // GPU probes may spawn processes, so they overlap with the RAM refresh
+        let (gpu_info, cpu, _) = tokio::join!(
+            async {
+                let gpus = statics.gpus.iter().filter(|_| refresh_gpus);
+                let gpus = futures::future::join_all(gpus.map(|gpu| gpu.refresh(interval)));
+                // Ties keep the earlier GPU, so an idle hybrid laptop sticks to one of them
+                gpus.await
+                    .into_iter()
+                    .flatten()
+                    .fold(None, |busiest: Option<GpuInfo>, gpu| match busiest {
+                        Some(busiest) if busiest.gpu_usage >= gpu.gpu_usage => Some(busiest),
+                        _ => Some(gpu),
+                    })
+            },
+            cpu.latest(),
+            async {
+                system_info.refresh_memory_specifics(MemoryRefreshKind::new().with_ram());
+            }
+        );
+        let vram_mult = u64::pow(base, 2);
+        let vram_max = gpu_info.as_ref().map_or(0, |gi| gi.vram_max * vram_mult);
+        let vram_scaled = units.scale(vram_max);
+
+        let vram_usage = match &gpu_info {
+            Some(gi) if vram_max > 0 => percent(
+                "vram_usage",
+                gi.vram_used as f64 * vram_mult as f64 / vram_max as f64 * 100.0,
+            ),
+            _ => u8::MAX,
+        };
+
+        let core_usage: Vec<u8> = cpu
+            .core_usage
+            .iter()
+            .map(|&u| percent("core_usage", u as f64))
+            .collect();
+        let frequencies: Vec<u32> = cpu.frequencies.iter().map(|&f| f as u32).collect();
+        let load_average = Self::get_load_average();
+
+        let gpu = gpu_info.as_ref();
+        let valid = [
+            (Self::VALID_CPU, !core_usage.is_empty()),
+            (Self::VALID_RAM, statics.ram_max > 0),
+            (Self::VALID_GPU, gpu.is_some_and(GpuInfo::usage_known)),
+            (Self::VALID_VRAM, gpu.is_some() && vram_max > 0),
+            (Self::VALID_GPU_TEMP, gpu.is_some_and(|gi| gi.gpu_temp > 0)),
+            (
+                Self::VALID_GPU_POWER,
+                gpu.is_some_and(|gi| gi.gpu_power > 0),
+            ),
+            (
+                Self::VALID_GPU_CLOCK,
+                gpu.is_some_and(|gi| gi.gpu_clock > 0 || gi.mem_clock > 0),
+            ),
+            (
+                Self::VALID_GPU_FAN,
+                gpu.is_some_and(|gi| gi.gpu_fan.is_some()),
+            ),
+            (
+                Self::VALID_GPU_CODEC,
+                gpu.is_some_and(|gi| gi.encoder_usage.is_some() || gi.decoder_usage.is_some()),
+            ),
+            (Self::VALID_LOAD, load_average.is_some()),
+        ]
+        .into_iter()
+        .filter(|(_, known)| *known)
+        .fold(0, |valid, (flag, _)| valid | flag);
+
+        SystemInfo {
+            // Averaged before rounding, rounded per-core values drift by up to half a percent
+            cpu_usage: match cpu.core_usage.len() {
+                0 => 0,
+                cores => percent(
+                    "cpu_usage",
+                    cpu.core_usage.iter().map(|&u| u as f64).sum::<f64>() / cores as f64,
+                ),
+            },
+            ram_max: statics.ram_scaled.tenths,
+            ram_usage: percent(
+                "ram_usage",
+                system_info.used_memory() as f64 / statics.ram_max as f64 * 100.0,
+            ),
+            ram_unit: pop_4u8(statics.ram_scaled.unit.as_bytes()),
+            gpu_usage: match &gpu_info {
+                Some(gi) if gi.usage_known() => percent("gpu_usage", gi.gpu_usage as f64),
+                _ => u8::MAX,
+            },
+            vram_max: vram_scaled.tenths,
+            vram_usage,
+            vram_unit: pop_4u8(vram_scaled.unit.as_bytes()),
+            core_usage,
+            ram_used_mib: (system_info.used_memory() / vram_mult).min(u32::MAX as u64) as u32,
+            ram_total_mib: (statics.ram_max / vram_mult).min(u32::MAX as u64) as u32,
+            vram_used_mib: match &gpu_info {
+                Some(gi) if vram_max > 0 => gi.vram_used.min(u32::MAX as u64 - 1) as u32,
+                _ => u32::MAX,
+            },
+            vram_total_mib: match &gpu_info {
+                Some(gi) if vram_max > 0 => gi.vram_max.min(u32::MAX as u64 - 1) as u32,
+                _ => u32::MAX,
+            },
+            gpu_temp: match &gpu_info {
+                Some(gi) if gi.gpu_temp > 0 => gi.gpu_temp as u8,
+                _ => u8::MAX,
+            },
+            gpu_power: match &gpu_info {
+                Some(gi) if gi.gpu_power > 0 => {
+                    (gi.gpu_power / 100).min(u16::MAX as u64 - 1) as u16
+                }
+                _ => u16::MAX,
+            },
+            gpu_clock: match &gpu_info {
+                Some(gi) if gi.gpu_clock > 0 => gi.gpu_clock.min(u16::MAX as u64 - 1) as u16,
+                _ => u16::MAX,
+            },
+            vram_clock: match &gpu_info {
+                Some(gi) if gi.mem_clock > 0 => gi.mem_clock.min(u16::MAX as u64 - 1) as u16,
+                _ => u16::MAX,
+            },
+            gpu_fan: match gpu_info.as_ref().and_then(|gi| gi.gpu_fan) {
+                Some(fan) => percent("gpu_fan", fan as f64),
+                None => u8::MAX,
+            },
+            gpu_encoder: match gpu_info.as_ref().and_then(|gi| gi.encoder_usage) {
+                Some(usage) => percent("gpu_encoder", usage as f64),
+                None => u8::MAX,
+            },
+            gpu_decoder: match gpu_info.as_ref().and_then(|gi| gi.decoder_usage) {
+                Some(usage) => percent("gpu_decoder", usage as f64),
+                None => u8::MAX,
+            },
+            load_average,
+            cpu_freq_avg: avg_vecu32(frequencies.clone()).min(u16::MAX as u32) as u16,
+            cpu_freq_max: frequencies
+                .iter()
+                .copied()
+                .max()
+                .unwrap_or(0)
+                .min(u16::MAX as u32) as u16,
+            uptime: sysinfo::System::uptime().min(u32::MAX as u64) as u32,
+            valid,
+        }
+    }
+}
+
+// How long to wait before probing every GPU backend again while none of them reports data
+const GPU_REPROBE_INTERVAL: Duration = Duration::from_secs(60);
+
+// Hardware facts that can't change while we run, probed once instead of every tick
+pub struct StaticInfo {
+    ram_max: u64,
+    ram_scaled: Scaled,
+    // The selected GPUs as first seen, their name and backend pick what refresh() queries
+    gpus: Vec<GpuInfo>,
+    gpus_probed: Instant,
+}
+
+impl StaticInfo {
+    pub async fn probe(
+        system_info: &mut sysinfo::System,
+        gpu_index: usize,
+        gpu_prefer: GpuPreference,
+        units: Units,
+    ) -> Self {
+        system_info.refresh_memory_specifics(MemoryRefreshKind::new().with_ram());
+
+        let ram_max = system_info.total_memory();
+        let gpus = Self::probe_gpus(gpu_index, gpu_prefer).await;
+        if gpus.is_empty() {
+            tracing::info!(gpu_index, ?gpu_prefer, "No GPU found");
+        }
+
+        StaticInfo {
+            ram_max,
+            ram_scaled: units.scale(ram_max),
+            gpus,
+            gpus_probed: Instant::now(),
+        }
+    }
+
+    async fn probe_gpus(gpu_index: usize, gpu_prefer: GpuPreference) -> Vec<GpuInfo> {
+        let gpus = GpuInfo::select(gpu_index, gpu_prefer).await;
+        for gpu in &gpus {
+            tracing::info!(name = %gpu.name, "Reporting GPU");
+        }
+        gpus
+    }
+
+    // For when the backend picked at startup stops answering (e.g. after a driver update)
+    // or there was no GPU yet, eGPUs and late loading drivers happen
+    async fn reprobe_gpus(&mut self, gpu_index: usize, gpu_prefer: GpuPreference) {
+        if self.gpus_probed.elapsed() < GPU_REPROBE_INTERVAL {
+            return;
+        }
+
+        tracing::debug!("No GPU data, probing GPU backends again");
+        self.gpus = Self::probe_gpus(gpu_index, gpu_prefer).await;
+        self.gpus_probed = Instant::now();
+    }
+}
+
+pub struct SystemCollector {
+    // Reuse system variable between ticks (small performance and RAM boost)
+    system: sysinfo::System,
+    gpu_index: usize,
+    gpu_prefer: GpuPreference,
+    units: Units,
+    // Probed on the first tick, collecting is async but new() isn't
+    statics: Option<StaticInfo>,
+    interval: Duration,
+    // Started on the first tick too, it needs a runtime to spawn on
+    cpu: Option<CpuSampler>,
+    // GPU probes spawn processes on most platforms, so they are left out on battery
+    low_power: bool,
+}
+
+impl SystemCollector {
+    pub fn new(gpu_index: usize, gpu_prefer: GpuPreference, interval: u64, units: Units) -> Self {
+        SystemCollector {
+            system: sysinfo::System::new_all(),
+            gpu_index,
+            gpu_prefer,
+            units,
+            statics: None,
+            interval: Duration::from_millis(interval),
+            cpu: None,
+            low_power: false,
+        }
+    }
+}
+
+#[async_trait]
+impl Collector for SystemCollector {
+    fn name(&self) -> &'static str {
+        "system"
+    }
+
+    async fn collect(&mut self) -> Metric {
+        let statics = match &mut self.statics {
+            Some(statics) => statics,
+            None => self.statics.insert(
+                StaticInfo::probe(
+                    &mut self.system,
+                    self.gpu_index,
+                    self.gpu_prefer,
+                    self.units,
+                )
+                .await,
+            ),
+        };
+        let interval = self.interval;
+        let cpu = self.cpu.get_or_insert_with(|| CpuSampler::spawn(interval));
+        let info = SystemInfo::get_system_info(
+            &mut self.system,
+            statics,
+            cpu,
+            self.units,
+            interval,
+            !self.low_power,
+        )
+        .await;
+        if info.valid & SystemInfo::VALID_GPU == 0 && !self.low_power {
+            statics.reprobe_gpus(self.gpu_index, self.gpu_prefer).await;
+        }
+        Metric::System(info)
+    }
+
+    fn set_low_power(&mut self, low_power: bool) {
+        self.low_power = low_power;
+    }
+}
@@ -0,0 +1,68 @@
+use super::{Collector, Metric};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TempInfo {
+    pub label: [u8; 16],
+    // Degrees Celsius
+    pub temp: u8,
+}
+
+impl TempInfo {
+    // Sensors named "<chip> <label>", e.g. "nvme Composite", kept when any filter is a
+    // case-insensitive substring of the name. No filters keeps everything
+    pub fn get_temp_info(filters: &[String]) -> Vec<Self> {
+        #[cfg(target_os = "linux")]
+        {
+            crate::helpers::read_hwmon_sensors("temp")
+                .into_iter()
+                .map(|sensor| {
+                    let name = format!(
+                        "{} {}",
+                        sensor.chip,
+                        sensor.label.as_deref().unwrap_or(&sensor.id)
+                    );
+                    (name, sensor.value)
+                })
+                .filter(|(name, _)| {
+                    filters.is_empty()
+                        || filters
+                            .iter()
+                            .any(|f| name.to_lowercase().contains(&f.to_lowercase()))
+                })
+                .map(|(name, millidegrees)| TempInfo {
+                    label: crate::helpers::pop_16u8(name.as_bytes()),
+                    temp: (millidegrees / 1000).min(u8::MAX as u64 - 1) as u8,
+                })
+                .collect()
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = filters;
+            Vec::new()
+        }
+    }
+}
+
+pub struct TempCollector {
+    filters: Vec<String>,
+}
+
+impl TempCollector {
+    pub fn new(filters: Vec<String>) -> Self {
+        TempCollector { filters }
+    }
+}
+
+#[async_trait]
+impl Collector for TempCollector {
+    fn name(&self) -> &'static str {
+        "temps"
+    }
+
+    async fn collect(&mut self) -> Metric {
+        Metric::Temps(TempInfo::get_temp_info(&self.filters))
+    }
+}
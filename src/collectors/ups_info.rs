@@ -0,0 +1,161 @@
+use super::{Collector, Metric};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum UpsDaemon {
+    Nut,
+    Apcupsd,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct UpsConfig {
+    pub daemon: UpsDaemon,
+    pub host: String,
+    // Defaults to 3493 for NUT and 3551 for apcupsd
+    pub port: Option<u16>,
+    // UPS name as configured in ups.conf, NUT only
+    pub name: String,
+}
+
+impl Default for UpsConfig {
+    fn default() -> Self {
+        UpsConfig {
+            daemon: UpsDaemon::Nut,
+            host: "localhost".to_owned(),
+            port: None,
+            name: "ups".to_owned(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UpsInfo {
+    // Percent, u8::MAX if unknown
+    pub charge: u8,
+    pub load: u8,
+    pub on_battery: u8,
+}
+
+// Both daemons report numbers like "100" or "100.0 Percent"
+fn parse_percent(value: Option<&String>) -> u8 {
+    value
+        .and_then(|v| v.split_whitespace().next())
+        .and_then(|v| v.parse::<f32>().ok())
+        .map_or(u8::MAX, |v| v.round().clamp(0.0, 100.0) as u8)
+}
+
+async fn nut_vars(stream: TcpStream, name: &str) -> std::io::Result<HashMap<String, String>> {
+    let mut stream = BufReader::new(stream);
+    stream
+        .write_all(format!("LIST VAR {name}\n").as_bytes())
+        .await?;
+
+    // BEGIN LIST VAR ups / VAR ups battery.charge "100" / ... / END LIST VAR ups
+    let mut vars = HashMap::new();
+    let mut lines = stream.lines();
+    while let Some(line) = lines.next_line().await? {
+        if line.starts_with("END LIST") {
+            break;
+        }
+        if let Some(err) = line.strip_prefix("ERR ") {
+            return Err(std::io::Error::other(err.to_owned()));
+        }
+
+        let mut parts = line.splitn(4, ' ');
+        if let (Some("VAR"), Some(_), Some(key), Some(value)) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        {
+            vars.insert(key.to_owned(), value.trim_matches('"').to_owned());
+        }
+    }
+
+    let _ = lines.get_mut().write_all(b"LOGOUT\n").await;
+    Ok(vars)
+}
+
+async fn apcupsd_vars(mut stream: TcpStream) -> std::io::Result<HashMap<String, String>> {
+    // NIS messages are a big endian u16 length followed by the text
+    stream.write_all(&[0, 6]).await?;
+    stream.write_all(b"status").await?;
+
+    // One "KEY      : value" line per message, an empty message ends the list
+    let mut vars = HashMap::new();
+    loop {
+        let length = stream.read_u16().await? as usize;
+        if length == 0 {
+            break;
+        }
+
+        let mut line = vec![0; length];
+        stream.read_exact(&mut line).await?;
+        if let Some((key, value)) = String::from_utf8_lossy(&line).split_once(':') {
+            vars.insert(key.trim().to_owned(), value.trim().to_owned());
+        }
+    }
+    Ok(vars)
+}
+
+impl UpsInfo {
+    pub async fn get_ups_info(config: &UpsConfig) -> Option<Self> {
+        let port = config.port.unwrap_or(match config.daemon {
+            UpsDaemon::Nut => 3493,
+            UpsDaemon::Apcupsd => 3551,
+        });
+        let stream = TcpStream::connect((config.host.as_str(), port))
+            .await
+            .map_err(|e| tracing::debug!("Failed to connect to {}:{port}: {e}", config.host))
+            .ok()?;
+
+        let vars = match config.daemon {
+            UpsDaemon::Nut => nut_vars(stream, &config.name).await,
+            UpsDaemon::Apcupsd => apcupsd_vars(stream).await,
+        }
+        .map_err(|e| tracing::warn!("Failed to query the UPS: {e}"))
+        .ok()?;
+
+        let info = match config.daemon {
+            // ups.status is a list of flags like "OL CHRG" or "OB LB"
+            UpsDaemon::Nut => UpsInfo {
+                charge: parse_percent(vars.get("battery.charge")),
+                load: parse_percent(vars.get("ups.load")),
+                on_battery: vars
+                    .get("ups.status")
+                    .is_some_and(|s| s.split_whitespace().any(|flag| flag == "OB"))
+                    as u8,
+            },
+            UpsDaemon::Apcupsd => UpsInfo {
+                charge: parse_percent(vars.get("BCHARGE")),
+                load: parse_percent(vars.get("LOADPCT")),
+                on_battery: vars.get("STATUS").is_some_and(|s| s.contains("ONBATT")) as u8,
+            },
+        };
+        Some(info)
+    }
+}
+
+pub struct UpsCollector {
+    config: UpsConfig,
+}
+
+impl UpsCollector {
+    pub fn new(config: UpsConfig) -> Self {
+        UpsCollector { config }
+    }
+}
+
+#[async_trait]
+impl Collector for UpsCollector {
+    fn name(&self) -> &'static str {
+        "ups"
+    }
+
+    async fn collect(&mut self) -> Metric {
+        Metric::Ups(UpsInfo::get_ups_info(&self.config).await)
+    }
+}
@@ -0,0 +1,230 @@
+use crate::protocol::crypto::{Opener, Session};
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+
+/*
+Commands written by the PC Monitor app to the Flipper's TX characteristic
+(or the serial port).
+
+typedef struct {
+    uint8_t magic;        // COMMAND_MAGIC
+    uint8_t command;      // COMMAND_*
+    uint8_t args[];
+} Command;
+
+COMMAND_CHANGE_PAGE    uint8_t page
+COMMAND_SET_INTERVAL   uint16_t interval_ms
+COMMAND_REQUEST_METRIC uint8_t tag             // v2 TLV tag, sent right away
+COMMAND_MEDIA          uint8_t action          // MEDIA_*
+COMMAND_POWER          uint8_t action          // POWER_*, only if allowed in config
+COMMAND_SWITCH_HOST    int8_t step             // hub only, 1 next host, -1 previous
+COMMAND_VERSION        uint8_t layout; uint16_t app_version
+                                               // answer to the VersionPacket (handshake.rs),
+                                               // app_version is major << 8 | minor
+*/
+
+pub const COMMAND_MAGIC: u8 = 0xFB;
+pub const COMMAND_CHANGE_PAGE: u8 = 0x01;
+pub const COMMAND_SET_INTERVAL: u8 = 0x02;
+pub const COMMAND_REQUEST_METRIC: u8 = 0x03;
+pub const COMMAND_MEDIA: u8 = 0x04;
+pub const COMMAND_POWER: u8 = 0x05;
+pub const COMMAND_SWITCH_HOST: u8 = 0x06;
+pub const COMMAND_VERSION: u8 = 0x07;
+
+pub const MEDIA_PLAY_PAUSE: u8 = 0x00;
+pub const MEDIA_NEXT: u8 = 0x01;
+pub const MEDIA_PREVIOUS: u8 = 0x02;
+pub const MEDIA_VOLUME_UP: u8 = 0x03;
+pub const MEDIA_VOLUME_DOWN: u8 = 0x04;
+
+pub const POWER_LOCK: u8 = 0x00;
+pub const POWER_SLEEP: u8 = 0x01;
+pub const POWER_SHUTDOWN: u8 = 0x02;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MediaAction {
+    PlayPause,
+    Next,
+    Previous,
+    VolumeUp,
+    VolumeDown,
+}
+
+impl MediaAction {
+    fn from_u8(action: u8) -> Option<Self> {
+        match action {
+            MEDIA_PLAY_PAUSE => Some(MediaAction::PlayPause),
+            MEDIA_NEXT => Some(MediaAction::Next),
+            MEDIA_PREVIOUS => Some(MediaAction::Previous),
+            MEDIA_VOLUME_UP => Some(MediaAction::VolumeUp),
+            MEDIA_VOLUME_DOWN => Some(MediaAction::VolumeDown),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PowerAction {
+    Lock,
+    Sleep,
+    Shutdown,
+}
+
+impl PowerAction {
+    fn from_u8(action: u8) -> Option<Self> {
+        match action {
+            POWER_LOCK => Some(PowerAction::Lock),
+            POWER_SLEEP => Some(PowerAction::Sleep),
+            POWER_SHUTDOWN => Some(PowerAction::Shutdown),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    ChangePage(u8),
+    SetInterval(u16),
+    RequestMetric(u8),
+    Media(MediaAction),
+    Power(PowerAction),
+    SwitchHost(i8),
+    Version { layout: u8, app_version: u16 },
+}
+
+impl Command {
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let [COMMAND_MAGIC, command, args @ ..] = bytes else {
+            return None;
+        };
+
+        match (*command, args) {
+            (COMMAND_CHANGE_PAGE, [page, ..]) => Some(Command::ChangePage(*page)),
+            (COMMAND_SET_INTERVAL, [lo, hi, ..]) => {
+                Some(Command::SetInterval(u16::from_le_bytes([*lo, *hi])))
+            }
+            (COMMAND_REQUEST_METRIC, [tag, ..]) => Some(Command::RequestMetric(*tag)),
+            (COMMAND_MEDIA, [action, ..]) => MediaAction::from_u8(*action).map(Command::Media),
+            (COMMAND_POWER, [action, ..]) => PowerAction::from_u8(*action).map(Command::Power),
+            (COMMAND_SWITCH_HOST, [step, ..]) => Some(Command::SwitchHost(*step as i8)),
+            (COMMAND_VERSION, [layout, lo, hi, ..]) => Some(Command::Version {
+                layout: *layout,
+                app_version: u16::from_le_bytes([*lo, *hi]),
+            }),
+            _ => None,
+        }
+    }
+}
+
+// Fans commands out to everyone interested (collector loop, handlers)
+#[derive(Clone)]
+pub struct Dispatcher {
+    commands: broadcast::Sender<Command>,
+    // Set with `psk`, commands from the Flipper then have to be sealed (see crypto.rs)
+    opener: Arc<Mutex<Option<Opener>>>,
+}
+
+impl Default for Dispatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Dispatcher {
+    pub fn new() -> Self {
+        let (commands, _) = broadcast::channel(16);
+        Dispatcher {
+            commands,
+            opener: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    pub fn set_key(&self, psk: &str) {
+        *self.opener.lock().unwrap() = Some(Opener::new(psk));
+    }
+
+    // Only commands sealed for the session of our outgoing packets are accepted from now on
+    pub fn bind(&self, session: Session) {
+        if let Some(opener) = self.opener.lock().unwrap().as_mut() {
+            opener.bind(session);
+        }
+    }
+
+    pub fn dispatch(&self, bytes: &[u8]) {
+        let opened;
+        let bytes = match self.opener.lock().unwrap().as_mut() {
+            Some(opener) => match opener.open(bytes) {
+                Some(command) => {
+                    opened = command;
+                    &opened
+                }
+                None => return tracing::warn!("Dropping unauthenticated command from Flipper"),
+            },
+            None => bytes,
+        };
+
+        match Command::parse(bytes) {
+            Some(command) => {
+                tracing::info!(?command, "Received command from Flipper");
+                let _ = self.commands.send(command);
+            }
+            None => tracing::warn!(?bytes, "Ignoring unknown command from Flipper"),
+        }
+    }
+
+    // Same as a command from the Flipper, for local control over D-Bus and the like
+    pub fn send(&self, command: Command) {
+        tracing::info!(?command, "Received local command");
+        let _ = self.commands.send(command);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Command> {
+        self.commands.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_version() {
+        assert_eq!(
+            Command::parse(&[COMMAND_MAGIC, COMMAND_VERSION, 0x01, 0x03, 0x02]),
+            Some(Command::Version {
+                layout: 0x01,
+                app_version: 0x0203,
+            })
+        );
+        // Unknown layouts are passed on, the encoder decides what to make of them
+        assert_eq!(
+            Command::parse(&[COMMAND_MAGIC, COMMAND_VERSION, 0x7F, 0x00, 0x01]),
+            Some(Command::Version {
+                layout: 0x7F,
+                app_version: 0x0100,
+            })
+        );
+    }
+
+    #[test]
+    fn truncated_commands_are_dropped() {
+        assert_eq!(Command::parse(&[]), None);
+        assert_eq!(Command::parse(&[COMMAND_MAGIC]), None);
+        assert_eq!(Command::parse(&[COMMAND_MAGIC, COMMAND_VERSION]), None);
+        assert_eq!(
+            Command::parse(&[COMMAND_MAGIC, COMMAND_VERSION, 0x01, 0x03]),
+            None
+        );
+        assert_eq!(
+            Command::parse(&[COMMAND_MAGIC, COMMAND_SET_INTERVAL, 0xE8]),
+            None
+        );
+        assert_eq!(Command::parse(&[COMMAND_MAGIC, COMMAND_MEDIA, 0x7F]), None);
+        assert_eq!(
+            Command::parse(&[0xFC, COMMAND_VERSION, 0x01, 0x03, 0x02]),
+            None
+        );
+    }
+}
@@ -0,0 +1,238 @@
+use crate::alerts::AlertConfig;
+use crate::collectors::disk_info::DiskInfo;
+use crate::collectors::gpu_info::GpuPreference;
+use crate::collectors::ipmi_info::IpmiConfig;
+use crate::collectors::snmp_info::SnmpConfig;
+use crate::collectors::ups_info::UpsConfig;
+use crate::collectors::{LowPowerConfig, Registry};
+use crate::commands::PowerAction;
+use crate::history;
+use crate::logging::LogFormat;
+use crate::protocol;
+use crate::relay::ssh::SshConfig;
+use crate::relay::{AgentConfig, HubConfig};
+use crate::sinks::csv::CsvConfig;
+use crate::sinks::http::HttpConfig;
+use crate::sinks::influxdb::InfluxConfig;
+use crate::sinks::json::JsonConfig;
+use crate::sinks::mqtt::MqttConfig;
+use crate::sinks::otlp::OtlpConfig;
+use crate::sinks::sqlite::SqliteConfig;
+use crate::sinks::statsd::StatsdConfig;
+use crate::sinks::webhook::WebhookConfig;
+use crate::transport;
+use crate::units::{TempUnit, Units};
+use serde::Deserialize;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Config {
+    pub transport: transport::Kind,
+    // Kept for older configs, same as a single entry in `devices`
+    pub address: Option<String>,
+    // BLE names (patterns like "PC Mon*" too)/addresses/ids, serial ports or host:port to
+    // stream to, empty means the first Flipper found
+    pub devices: Vec<String>,
+    pub ble: transport::BleConfig,
+    // App the `rpc` transport starts, as installed on the SD card
+    pub rpc_app: String,
+    pub protocol: protocol::Version,
+    // Wrap packets in a frame with sequence number and CRC16, always on for serial and tcp
+    pub framing: bool,
+    // Encrypts and authenticates packets and commands, the app needs the same key
+    pub psk: Option<String>,
+    // v2 only sends fields that changed, with a full packet every `delta_keyframe_every` ticks
+    pub delta: bool,
+    pub delta_keyframe_every: u32,
+    // v2 also sends the old value x10 plus unit string size fields next to the wide ones
+    pub legacy_sizes: bool,
+    // Milliseconds between updates
+    pub interval: u64,
+    // Back off up to `max_interval` milliseconds while writes to the Flipper are slow or failing
+    pub adaptive_interval: bool,
+    pub max_interval: u64,
+    // Slower updates and fewer collectors while a laptop runs on battery
+    pub low_power: LowPowerConfig,
+    pub cores: u8,
+    // Which entry of `gpus` to report
+    pub gpu_index: usize,
+    // Pick a GPU by kind instead, "active" follows whichever is busiest (hybrid laptops)
+    pub gpu_prefer: GpuPreference,
+    pub disks: Vec<String>,
+    // Steps and labels for RAM, VRAM, disk and network sizes
+    pub units: Units,
+    // Converted when encoding, collectors and alert thresholds stay in Celsius
+    pub temp_unit: TempUnit,
+    // Samples kept per series for sparklines (at most 254), 0 disables history packets
+    pub history_length: u8,
+    // Send history every this many ticks
+    pub history_every: u32,
+    pub alerts: AlertConfig,
+    pub collectors: Vec<String>,
+    // Milliseconds a single collector may take before its previous value is reused
+    pub collector_timeout: u64,
+    // Seconds a collector may stay unresponsive before it is thrown away and restarted
+    pub collector_restart_after: u64,
+    // How many of the busiest processes the `processes` collector sends
+    pub process_count: usize,
+    // Substrings of "<chip> <label>" picking which sensors `temps` sends, empty sends all
+    pub temp_sensors: Vec<String>,
+    // Label substrings picking which HWiNFO readings `hwinfo` sends (Windows)
+    pub hwinfo_sensors: Vec<String>,
+    // Average CPU/GPU usage over about this many samples, 0 or 1 sends raw values
+    pub smoothing: u32,
+    // NUT or apcupsd server the `ups` collector queries
+    pub ups: UpsConfig,
+    // BMC the `ipmi` collector queries through ipmitool
+    pub ipmi: IpmiConfig,
+    // Devices and OIDs the `snmp` collector polls
+    pub snmp: Vec<SnmpConfig>,
+    pub log_level: Option<String>,
+    pub log_format: LogFormat,
+    // Rotated daily, logs go to stderr when unset
+    pub log_file: Option<PathBuf>,
+    // Let the Flipper send play/pause/next/previous/volume
+    pub media_control: bool,
+    // Power actions the Flipper may trigger, none by default
+    pub power_actions: Vec<PowerAction>,
+    // Desktop notification when a Flipper disconnects unexpectedly and when it reconnects
+    pub notifications: bool,
+    // Used by the `agent` subcommand to reach its hub
+    pub agent: Option<AgentConfig>,
+    // Accept agents and show their stats on this instance's Flipper
+    pub hub: Option<HubConfig>,
+    // Linux hosts polled over ssh, shown like agents (see `hub.show` and `hub.cycle`)
+    pub ssh: Vec<SshConfig>,
+    pub mqtt: Option<MqttConfig>,
+    // Local HTTP API serving the latest sample
+    pub http: Option<HttpConfig>,
+    // InfluxDB v2 server every sample is written to
+    pub influxdb: Option<InfluxConfig>,
+    // CSV file every sample is appended to
+    pub csv: Option<CsvConfig>,
+    // Newline delimited JSON samples for other displays, on a serial port or stdout
+    pub json: Option<JsonConfig>,
+    // Local SQLite history read by the `history` subcommand
+    pub sqlite: Option<SqliteConfig>,
+    // POSTed to (JSON, Discord or Slack) when an [alerts] threshold trips or clears, or the
+    // Flipper stays disconnected
+    pub webhooks: Vec<WebhookConfig>,
+    // OpenTelemetry collector the metrics are exported to
+    pub otlp: Option<OtlpConfig>,
+    // StatsD server or agent (Telegraf, Datadog) every sample is sent to as gauges
+    pub statsd: Option<StatsdConfig>,
+    // org.flipper.PcMonitor on the session bus (Linux)
+    pub dbus: bool,
+    // Local JSON control API, see control/mod.rs
+    pub control: bool,
+    // Unix socket it listens on, $XDG_RUNTIME_DIR/flipper-pc-monitor.sock by default
+    pub control_socket: Option<PathBuf>,
+    // Named pipe it listens on (Windows), \\.\pipe\flipper-pc-monitor by default
+    pub control_pipe: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            transport: match cfg!(target_os = "freebsd") {
+                true => transport::Kind::Serial,
+                false => transport::Kind::Ble,
+            },
+            address: None,
+            devices: Vec::new(),
+            ble: transport::BleConfig::default(),
+            rpc_app: "/ext/apps/Tools/pc_monitor.fap".to_owned(),
+            protocol: protocol::Version::V1,
+            framing: false,
+            psk: None,
+            delta: false,
+            delta_keyframe_every: 30,
+            legacy_sizes: false,
+            interval: 1000,
+            adaptive_interval: true,
+            max_interval: 8000,
+            low_power: LowPowerConfig::default(),
+            cores: 0,
+            gpu_index: 0,
+            gpu_prefer: GpuPreference::Index,
+            disks: DiskInfo::default_mount_points(),
+            units: Units::Binary,
+            temp_unit: TempUnit::Celsius,
+            history_length: 0,
+            history_every: 10,
+            alerts: AlertConfig::default(),
+            collectors: Registry::DEFAULT_COLLECTORS
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+            collector_timeout: 500,
+            collector_restart_after: 30,
+            process_count: 3,
+            temp_sensors: Vec::new(),
+            hwinfo_sensors: Vec::new(),
+            smoothing: 0,
+            ups: UpsConfig::default(),
+            ipmi: IpmiConfig::default(),
+            snmp: Vec::new(),
+            log_level: None,
+            log_format: LogFormat::Text,
+            log_file: None,
+            media_control: true,
+            power_actions: Vec::new(),
+            notifications: false,
+            agent: None,
+            hub: None,
+            ssh: Vec::new(),
+            mqtt: None,
+            http: None,
+            influxdb: None,
+            csv: None,
+            json: None,
+            sqlite: None,
+            webhooks: Vec::new(),
+            otlp: None,
+            statsd: None,
+            dbus: false,
+            control: false,
+            control_socket: None,
+            control_pipe: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn devices(&self) -> Vec<String> {
+        self.address.iter().chain(&self.devices).cloned().collect()
+    }
+
+    pub fn default_path() -> Option<PathBuf> {
+        Some(
+            dirs::config_dir()?
+                .join("flipper-pc-monitor")
+                .join("config.toml"),
+        )
+    }
+
+    // An explicitly passed path has to exist, the default one is optional
+    pub fn load(path: Option<&Path>) -> Result<Self, Box<dyn Error>> {
+        let path = match path {
+            Some(path) => path.to_owned(),
+            None => match Self::default_path() {
+                Some(path) if path.exists() => path,
+                _ => return Ok(Config::default()),
+            },
+        };
+
+        let contents = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+        let config: Config = toml::from_str(&contents)
+            .map_err(|e| format!("Failed to parse {}: {e}", path.display()))?;
+
+        if config.history_length > history::MAX_LENGTH {
+            return Err(format!("history_length can be at most {}", history::MAX_LENGTH).into());
+        }
+        Ok(config)
+    }
+}
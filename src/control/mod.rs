@@ -0,0 +1,144 @@
+use crate::collectors::Sample;
+use crate::commands::Command;
+use crate::config::Config;
+use crate::transport::Link;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::sync::broadcast;
+
+#[cfg(unix)]
+pub mod unix;
+#[cfg(windows)]
+pub mod windows;
+
+/*
+Local control API for scripts and GUIs on a Unix socket or a Windows named
+pipe, newline delimited JSON with one response line per request line.
+
+{"command": "pause"}                             -> {"ok": true}
+{"command": "resume"}                            -> {"ok": true}
+{"command": "set-interval", "interval_ms": 500}  -> {"ok": true}
+{"command": "get-status"}                        -> {"ok": true, "connection": "connected", "paused": false}
+{"command": "get-last-sample"}                   -> {"ok": true, "sample": {...}, "age_ms": 420}
+anything else                                    -> {"ok": false, "error": "..."}
+*/
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+enum Request {
+    Pause,
+    Resume,
+    SetInterval { interval_ms: u16 },
+    GetStatus,
+    GetLastSample,
+}
+
+#[derive(Clone)]
+pub struct Control {
+    link: Link,
+    latest: Arc<RwLock<Option<(Sample, Instant)>>>,
+}
+
+impl Control {
+    fn new(link: Link, mut samples: broadcast::Receiver<Sample>) -> Self {
+        let latest = Arc::new(RwLock::new(None));
+        let shared = latest.clone();
+        tokio::spawn(async move {
+            loop {
+                match samples.recv().await {
+                    Ok(sample) => *shared.write().unwrap() = Some((sample, Instant::now())),
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return,
+                }
+            }
+        });
+
+        Control { link, latest }
+    }
+
+    fn handle(&self, line: &str) -> Value {
+        let request = match serde_json::from_str::<Request>(line) {
+            Ok(request) => request,
+            Err(e) => return json!({ "ok": false, "error": e.to_string() }),
+        };
+        tracing::debug!(?request, "Control request");
+
+        match request {
+            Request::Pause => self.link.set_paused(true),
+            Request::Resume => self.link.set_paused(false),
+            Request::SetInterval { interval_ms } => {
+                self.link.commands().send(Command::SetInterval(interval_ms))
+            }
+            Request::GetStatus => {
+                return json!({
+                    "ok": true,
+                    "connection": format!("{:?}", *self.link.state().borrow()).to_lowercase(),
+                    "paused": self.link.is_paused(),
+                });
+            }
+            Request::GetLastSample => {
+                let latest = self.latest.read().unwrap();
+                return match &*latest {
+                    Some((sample, collected)) => json!({
+                        "ok": true,
+                        "sample": sample,
+                        "age_ms": collected.elapsed().as_millis() as u64,
+                    }),
+                    None => json!({ "ok": false, "error": "no sample collected yet" }),
+                };
+            }
+        }
+        json!({ "ok": true })
+    }
+
+    // One client connection, until it hangs up
+    pub async fn serve<S: AsyncRead + AsyncWrite + Unpin>(self, stream: S) {
+        let (reader, mut writer) = tokio::io::split(stream);
+        let mut lines = BufReader::new(reader).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let response = self.handle(&line).to_string() + "\n";
+            if writer.write_all(response.as_bytes()).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(windows)]
+pub const DEFAULT_PIPE: &str = r"\\.\pipe\flipper-pc-monitor";
+
+// $XDG_RUNTIME_DIR/flipper-pc-monitor.sock, or in the temp dir where there is none
+#[cfg(unix)]
+pub fn default_socket() -> std::path::PathBuf {
+    dirs::runtime_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("flipper-pc-monitor.sock")
+}
+
+pub fn spawn(link: &Link, samples: &broadcast::Sender<Sample>, config: &Config) {
+    if !config.control {
+        return;
+    }
+
+    let control = Control::new(link.clone(), samples.subscribe());
+    #[cfg(unix)]
+    tokio::spawn(unix::run(
+        config.control_socket.clone().unwrap_or_else(default_socket),
+        control,
+    ));
+    #[cfg(windows)]
+    tokio::spawn(windows::run(
+        config
+            .control_pipe
+            .clone()
+            .unwrap_or_else(|| DEFAULT_PIPE.to_owned()),
+        control,
+    ));
+}
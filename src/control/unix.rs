@@ -0,0 +1,39 @@
+use super::Control;
+use std::fs::Permissions;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use tokio::net::{UnixListener, UnixStream};
+
+pub async fn run(path: PathBuf, control: Control) {
+    // A socket file nobody answers on is left over from a crashed instance
+    if path.exists() {
+        if UnixStream::connect(&path).await.is_ok() {
+            return tracing::error!(
+                path = %path.display(),
+                "Another instance already listens on the control socket"
+            );
+        }
+        let _ = std::fs::remove_file(&path);
+    }
+
+    let listener = match UnixListener::bind(&path) {
+        Ok(listener) => listener,
+        Err(e) => {
+            return tracing::error!(path = %path.display(), "Failed to open control socket: {e}");
+        }
+    };
+    // Only the current user gets to control the backend
+    if let Err(e) = std::fs::set_permissions(&path, Permissions::from_mode(0o600)) {
+        tracing::warn!("Failed to restrict control socket permissions: {e}");
+    }
+    tracing::info!(path = %path.display(), "Listening for control commands");
+
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                tokio::spawn(control.clone().serve(stream));
+            }
+            Err(e) => tracing::warn!("Failed to accept control connection: {e}"),
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use super::Control;
+use std::ffi::c_void;
+use tokio::net::windows::named_pipe::{NamedPipeServer, ServerOptions};
+use windows_sys::Win32::Security::Authorization::{
+    ConvertStringSecurityDescriptorToSecurityDescriptorW, SDDL_REVISION_1,
+};
+use windows_sys::Win32::Security::{PSECURITY_DESCRIPTOR, SECURITY_ATTRIBUTES};
+
+// The service runs as LocalSystem, interactive users still need to be able to write requests
+const SDDL: &str = "D:(A;;GA;;;SY)(A;;GA;;;BA)(A;;GRGW;;;IU)";
+
+struct PipeSecurity(SECURITY_ATTRIBUTES);
+
+// Only points at the descriptor below, which is never freed or written to
+unsafe impl Send for PipeSecurity {}
+
+fn pipe_security() -> Option<PipeSecurity> {
+    let sddl: Vec<u16> = SDDL.encode_utf16().chain(Some(0)).collect();
+    let mut descriptor: PSECURITY_DESCRIPTOR = std::ptr::null_mut();
+    // Leaked on purpose, every pipe instance for as long as the backend runs uses it
+    let ok = unsafe {
+        ConvertStringSecurityDescriptorToSecurityDescriptorW(
+            sddl.as_ptr(),
+            SDDL_REVISION_1,
+            &mut descriptor,
+            std::ptr::null_mut(),
+        )
+    };
+    if ok == 0 {
+        tracing::warn!(
+            "Failed to build the control pipe's security descriptor: {}",
+            std::io::Error::last_os_error()
+        );
+        return None;
+    }
+
+    Some(PipeSecurity(SECURITY_ATTRIBUTES {
+        nLength: std::mem::size_of::<SECURITY_ATTRIBUTES>() as u32,
+        lpSecurityDescriptor: descriptor,
+        bInheritHandle: 0,
+    }))
+}
+
+fn create(
+    name: &str,
+    first: bool,
+    security: &mut Option<PipeSecurity>,
+) -> std::io::Result<NamedPipeServer> {
+    let mut options = ServerOptions::new();
+    options.first_pipe_instance(first);
+    match security {
+        Some(PipeSecurity(attributes)) => unsafe {
+            options.create_with_security_attributes_raw(
+                name,
+                attributes as *mut SECURITY_ATTRIBUTES as *mut c_void,
+            )
+        },
+        None => options.create(name),
+    }
+}
+
+// A pipe instance serves one client, a fresh one is created for the next as soon as it connects
+pub async fn run(name: String, control: Control) {
+    let mut security = pipe_security();
+    let mut server = match create(&name, true, &mut security) {
+        Ok(server) => server,
+        Err(e) => return tracing::error!(pipe = %name, "Failed to open control pipe: {e}"),
+    };
+    tracing::info!(pipe = %name, "Listening for control commands");
+
+    loop {
+        if let Err(e) = server.connect().await {
+            tracing::warn!("Failed to accept control connection: {e}");
+            continue;
+        }
+
+        let next = match create(&name, false, &mut security) {
+            Ok(next) => next,
+            Err(e) => return tracing::error!(pipe = %name, "Failed to open control pipe: {e}"),
+        };
+        tokio::spawn(control.clone().serve(std::mem::replace(&mut server, next)));
+    }
+}
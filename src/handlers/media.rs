@@ -0,0 +1,62 @@
+use crate::commands::{Command, MediaAction};
+use tokio::sync::broadcast;
+
+pub async fn run(mut commands: broadcast::Receiver<Command>) {
+    loop {
+        let action = match commands.recv().await {
+            Ok(Command::Media(action)) => action,
+            Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+
+        if let Err(e) = perform(action).await {
+            tracing::warn!(?action, "Failed to perform media action: {e}");
+        }
+    }
+}
+
+// MPRIS through playerctl for playback, the default PulseAudio/PipeWire sink for volume
+#[cfg(target_os = "linux")]
+async fn perform(action: MediaAction) -> Result<(), Box<dyn std::error::Error>> {
+    let (program, args) = match action {
+        MediaAction::PlayPause => ("playerctl", vec!["play-pause"]),
+        MediaAction::Next => ("playerctl", vec!["next"]),
+        MediaAction::Previous => ("playerctl", vec!["previous"]),
+        MediaAction::VolumeUp => ("pactl", vec!["set-sink-volume", "@DEFAULT_SINK@", "+5%"]),
+        MediaAction::VolumeDown => ("pactl", vec!["set-sink-volume", "@DEFAULT_SINK@", "-5%"]),
+    };
+
+    let status = tokio::process::Command::new(program)
+        .args(args)
+        .status()
+        .await?;
+    if !status.success() {
+        return Err(format!("{program} exited with {status}").into());
+    }
+
+    Ok(())
+}
+
+// Media keys are routed to the system media controls (SMTC on Windows,
+// Now Playing on macOS), so whatever is playing gets them
+#[cfg(not(target_os = "linux"))]
+async fn perform(action: MediaAction) -> Result<(), Box<dyn std::error::Error>> {
+    use enigo::{Direction, Enigo, Key, Keyboard, Settings};
+
+    let key = match action {
+        MediaAction::PlayPause => Key::MediaPlayPause,
+        MediaAction::Next => Key::MediaNextTrack,
+        MediaAction::Previous => Key::MediaPrevTrack,
+        MediaAction::VolumeUp => Key::VolumeUp,
+        MediaAction::VolumeDown => Key::VolumeDown,
+    };
+
+    // Enigo isn't Send, keep it on a blocking thread
+    tokio::task::spawn_blocking(move || -> Result<(), String> {
+        let mut enigo = Enigo::new(&Settings::default()).map_err(|e| e.to_string())?;
+        enigo.key(key, Direction::Click).map_err(|e| e.to_string())
+    })
+    .await??;
+
+    Ok(())
+}
@@ -0,0 +1,19 @@
+use crate::config::Config;
+use crate::transport::Link;
+
+pub mod media;
+pub mod power;
+
+// Spawns everything that acts on commands coming back from the Flipper
+pub fn spawn(link: &Link, config: &Config) {
+    if config.media_control {
+        tokio::spawn(media::run(link.commands().subscribe()));
+    }
+
+    if !config.power_actions.is_empty() {
+        tokio::spawn(power::run(
+            link.commands().subscribe(),
+            config.power_actions.clone(),
+        ));
+    }
+}
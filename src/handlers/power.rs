@@ -0,0 +1,80 @@
+use crate::commands::{Command, PowerAction};
+use tokio::sync::broadcast;
+
+pub async fn run(mut commands: broadcast::Receiver<Command>, allowed: Vec<PowerAction>) {
+    loop {
+        let action = match commands.recv().await {
+            Ok(Command::Power(action)) => action,
+            Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+
+        if !allowed.contains(&action) {
+            tracing::warn!(
+                ?action,
+                "Ignoring power action from Flipper, not in power_actions"
+            );
+            continue;
+        }
+
+        tracing::info!(?action, "Performing power action requested by Flipper");
+        let (program, args) = command_for(action);
+        match tokio::process::Command::new(program)
+            .args(args)
+            .status()
+            .await
+        {
+            Ok(status) if status.success() => {}
+            Ok(status) => {
+                tracing::error!(
+                    ?action,
+                    "Failed to perform power action: {program} exited with {status}"
+                )
+            }
+            Err(e) => tracing::error!(?action, "Failed to perform power action: {e}"),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn command_for(action: PowerAction) -> (&'static str, Vec<&'static str>) {
+    match action {
+        PowerAction::Lock => ("loginctl", vec!["lock-session"]),
+        PowerAction::Sleep => ("systemctl", vec!["suspend"]),
+        PowerAction::Shutdown => ("systemctl", vec!["poweroff"]),
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn command_for(action: PowerAction) -> (&'static str, Vec<&'static str>) {
+    match action {
+        PowerAction::Lock => ("rundll32.exe", vec!["user32.dll,LockWorkStation"]),
+        PowerAction::Sleep => (
+            "rundll32.exe",
+            vec!["powrprof.dll,SetSuspendState", "0,1,0"],
+        ),
+        PowerAction::Shutdown => ("shutdown", vec!["/s", "/t", "0"]),
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn command_for(action: PowerAction) -> (&'static str, Vec<&'static str>) {
+    match action {
+        // Locks as long as "require password after sleep" is set, which is the default
+        PowerAction::Lock => ("pmset", vec!["displaysleepnow"]),
+        PowerAction::Sleep => ("pmset", vec!["sleepnow"]),
+        PowerAction::Shutdown => (
+            "osascript",
+            vec!["-e", "tell application \"System Events\" to shut down"],
+        ),
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "windows", target_os = "macos")))]
+fn command_for(action: PowerAction) -> (&'static str, Vec<&'static str>) {
+    match action {
+        PowerAction::Lock => ("loginctl", vec!["lock-session"]),
+        PowerAction::Sleep => ("zzz", vec![]),
+        PowerAction::Shutdown => ("shutdown", vec!["-p", "now"]),
+    }
+}
@@ -1,18 +1,198 @@
 pub fn avg_vecu32(v: Vec<u32>) -> u32 {
-    v.iter().sum::<u32>() / v.len() as u32
+    v.iter().sum::<u32>() / v.len().max(1) as u32
+}
+
+// Float casts and totals read at slightly different times can leave a percentage out of range
+pub fn percent(field: &'static str, value: f64) -> u8 {
+    if !(0.0..=100.0).contains(&value) {
+        tracing::debug!(field, value, "Clamping out of range percentage");
+    }
+    // NaN (e.g. a zero total) ends up as 0
+    value.clamp(0.0, 100.0).round() as u8
 }
 
 pub fn pop_4u8(barry: &[u8]) -> [u8; 4] {
     [barry, &[0, 0, 0, 0]].concat()[0..4].try_into().unwrap()
 }
 
-pub fn nvd_r2u64(res: String) -> Option<u64> {
-    let mut chars = res.chars();
-    chars.next();
-    chars.next_back();
+pub fn pop_8u8(barry: &[u8]) -> [u8; 8] {
+    [barry, &[0; 8]].concat()[0..8].try_into().unwrap()
+}
+
+pub fn pop_16u8(barry: &[u8]) -> [u8; 16] {
+    [barry, &[0; 16]].concat()[0..16].try_into().unwrap()
+}
+
+// Inverse of the pop_* helpers, a fixed size label up to its first NUL
+pub fn c_str(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+// "192.168.4.1" or "server.local" get `port`, IPv6 addresses need brackets
+pub fn with_default_port(address: &str, port: u16) -> String {
+    match address
+        .rsplit_once(':')
+        .is_some_and(|(_, port)| port.parse::<u16>().is_ok())
+    {
+        true => address.to_owned(),
+        false => format!("{address}:{port}"),
+    }
+}
+
+// Case-insensitive, `*` matches any run of characters and `?` a single one
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Where to retry from when the part after the last `*` stops matching
+    let mut star = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    star = Some((star_p, star_t + 1));
+                    p = star_p + 1;
+                    t = star_t + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(target_os = "linux")]
+pub fn read_sysfs_u64(path: &std::path::Path) -> Option<u64> {
+    std::fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+// `sysctl -n <name>`, None if the OID doesn't exist
+#[cfg(target_os = "freebsd")]
+pub async fn sysctl(name: &str) -> Option<String> {
+    let output = tokio::process::Command::new("sysctl")
+        .arg("-n")
+        .arg(name)
+        .kill_on_drop(true)
+        .output()
+        .await
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
+
+#[cfg(target_os = "linux")]
+pub struct HwmonSensor {
+    // Driver name of the chip, e.g. "nct6798" or "nvme"
+    pub chip: String,
+    // e.g. "fan2" or "temp1"
+    pub id: String,
+    // From <id>_label, not every driver provides one
+    pub label: Option<String>,
+    pub value: u64,
+}
+
+// Every <kind>N_input in /sys/class/hwmon, e.g. kind "fan" or "temp"
+#[cfg(target_os = "linux")]
+pub fn read_hwmon_sensors(kind: &str) -> Vec<HwmonSensor> {
+    let Ok(chips) = std::fs::read_dir("/sys/class/hwmon") else {
+        return Vec::new();
+    };
+
+    let mut sensors = Vec::new();
+    for chip in chips.flatten() {
+        let chip_path = chip.path();
+        let Ok(entries) = std::fs::read_dir(&chip_path) else {
+            continue;
+        };
+        let chip_name = std::fs::read_to_string(chip_path.join("name"))
+            .map(|name| name.trim().to_owned())
+            .unwrap_or_else(|_| chip.file_name().to_string_lossy().into_owned());
+
+        for entry in entries.flatten() {
+            let file_name = entry.file_name();
+            let Some(id) = file_name
+                .to_str()
+                .and_then(|name| name.strip_suffix("_input"))
+                .filter(|id| {
+                    id.strip_prefix(kind)
+                        .is_some_and(|n| n.parse::<u32>().is_ok())
+                })
+            else {
+                continue;
+            };
+
+            let Some(value) = read_sysfs_u64(&entry.path()) else {
+                continue;
+            };
+
+            sensors.push(HwmonSensor {
+                chip: chip_name.clone(),
+                id: id.to_owned(),
+                label: std::fs::read_to_string(chip_path.join(format!("{id}_label")))
+                    .ok()
+                    .map(|label| label.trim().to_owned()),
+                value,
+            });
+        }
+    }
+    sensors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_stars() {
+        assert!(glob_match("PC Mon*", "PC Monitor"));
+        assert!(glob_match("PC Mon*", "PC Mon"));
+        assert!(glob_match("*desk*", "Flipper desk 2"));
+        assert!(glob_match("a*b*c", "abbbc"));
+        assert!(glob_match("a*b*c", "aXbYbZc"));
+        assert!(!glob_match("a*b*c", "aXbYcZ"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("**", "anything"));
+    }
+
+    #[test]
+    fn glob_question_marks() {
+        assert!(glob_match("Flip?er", "Flipper"));
+        assert!(!glob_match("Flip?er", "Flipr"));
+        assert!(!glob_match("?", ""));
+        assert!(glob_match("???", "abc"));
+    }
+
+    #[test]
+    fn glob_is_anchored() {
+        assert!(!glob_match("Mon", "PC Monitor"));
+        assert!(!glob_match("PC", "PC Monitor"));
+        assert!(!glob_match("Monitor", "PC Monitor"));
+        assert!(glob_match("PC Monitor", "PC Monitor"));
+    }
+
+    #[test]
+    fn glob_empty() {
+        assert!(glob_match("", ""));
+        assert!(!glob_match("", "PC Monitor"));
+        assert!(!glob_match("PC", ""));
+    }
 
-    chars.as_str().split(" ").collect::<Vec<&str>>()[0]
-        .trim()
-        .parse()
-        .ok()
+    #[test]
+    fn glob_ignores_case() {
+        assert!(glob_match("pc mon*", "PC MONITOR"));
+        assert!(glob_match("AA:BB:*", "aa:bb:cc:dd:ee:ff"));
+        assert!(glob_match("Ä*", "äpfel"));
+    }
 }
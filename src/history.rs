@@ -0,0 +1,62 @@
+use crate::collectors::Sample;
+use std::collections::VecDeque;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Series {
+    Cpu = 0,
+    Ram = 1,
+    Gpu = 2,
+    Vram = 3,
+}
+
+impl Series {
+    pub const ALL: [Series; 4] = [Series::Cpu, Series::Ram, Series::Gpu, Series::Vram];
+}
+
+// A v2 TAG_HISTORY field holds the series byte and every value, and has a single length byte
+pub const MAX_LENGTH: u8 = 254;
+
+// Rolling window of the last `length` usage values per series, oldest first
+pub struct History {
+    length: usize,
+    series: [VecDeque<u8>; 4],
+}
+
+impl History {
+    pub fn new(length: usize) -> Self {
+        History {
+            length,
+            series: std::array::from_fn(|_| VecDeque::with_capacity(length)),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.series.iter_mut().for_each(VecDeque::clear);
+    }
+
+    pub fn push(&mut self, sample: &Sample) {
+        let Some(system) = &sample.system else {
+            return;
+        };
+
+        let values = [
+            system.cpu_usage,
+            system.ram_usage,
+            system.gpu_usage,
+            system.vram_usage,
+        ];
+        for (series, value) in self.series.iter_mut().zip(values) {
+            if series.len() == self.length {
+                series.pop_front();
+            }
+            series.push_back(value);
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Series, Vec<u8>)> + '_ {
+        Series::ALL
+            .into_iter()
+            .zip(&self.series)
+            .map(|(series, values)| (series, values.iter().copied().collect()))
+    }
+}
@@ -0,0 +1,57 @@
+use crate::config::Config;
+use serde::Deserialize;
+use std::error::Error;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::EnvFilter;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+fn filter(config: &Config, verbose: u8) -> Result<EnvFilter, Box<dyn Error>> {
+    let crate_name = env!("CARGO_CRATE_NAME");
+
+    // An explicit filter wins, then -v, then RUST_LOG
+    let filter = match (&config.log_level, verbose) {
+        (Some(level), _) => EnvFilter::try_new(level)?,
+        (None, 0) => EnvFilter::try_from_default_env()
+            .unwrap_or_else(|_| EnvFilter::new(format!("warn,{crate_name}=info"))),
+        (None, 1) => EnvFilter::new(format!("info,{crate_name}=debug")),
+        (None, _) => EnvFilter::new(format!("debug,{crate_name}=trace")),
+    };
+    Ok(filter)
+}
+
+// The guard flushes the file writer on drop, keep it alive until exit
+pub fn init(config: &Config, verbose: u8) -> Result<Option<WorkerGuard>, Box<dyn Error>> {
+    let (writer, guard) = match &config.log_file {
+        Some(path) => {
+            let dir = path.parent().ok_or("log_file needs a file name")?;
+            let name = path.file_name().ok_or("log_file needs a file name")?;
+            std::fs::create_dir_all(dir)?;
+
+            // Rotated daily, the date is appended to the file name
+            let (writer, guard) =
+                tracing_appender::non_blocking(tracing_appender::rolling::daily(dir, name));
+            (BoxMakeWriter::new(writer), Some(guard))
+        }
+        None => (BoxMakeWriter::new(std::io::stderr), None),
+    };
+
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter(config, verbose)?)
+        .with_writer(writer)
+        .with_ansi(config.log_file.is_none());
+
+    match config.log_format {
+        LogFormat::Text => builder.init(),
+        LogFormat::Json => builder.json().init(),
+    }
+
+    Ok(guard)
+}
@@ -1,115 +1,549 @@
-use btleplug::api::{Central, CentralEvent, Peripheral as _, ScanFilter};
-use btleplug::platform::{Adapter, Manager, Peripheral, PeripheralId};
-use futures::stream::StreamExt;
-use std::collections::HashMap;
+use clap::Parser;
 use std::error::Error;
+use tracing::Instrument;
 
-mod flipper_manager;
+mod alerts;
+mod cli;
+mod collectors;
+mod commands;
+mod config;
+mod control;
+mod handlers;
 mod helpers;
-mod system_info;
-
-async fn data_sender(flipper: Peripheral) {
-    let id = flipper.id();
-    let chars = flipper.characteristics();
-    let cmd_char = match chars
-        .iter()
-        .find(|c| c.uuid == flipper_manager::FLIPPER_CHARACTERISTIC_UUID)
-    {
-        Some(c) => c,
-        None => {
-            return println!("[{id}] Failed to find characteristic");
-        }
-    };
-    println!("[{id}] Sending data...");
+mod history;
+mod logging;
+mod notifications;
+mod protocol;
+mod relay;
+mod service;
+mod sinks;
+mod transport;
+#[cfg(feature = "tray")]
+mod tray;
+pub mod units;
+
+const SHUTDOWN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
 
-    // Reuse system variable in loop (small performance and RAM boost)
-    let mut system_info = sysinfo::System::new_all();
+async fn data_collector(
+    link: transport::Link,
+    samples: tokio::sync::broadcast::Sender<collectors::Sample>,
+    config: config::Config,
+    hub: Option<relay::Hub>,
+    mut stop: tokio::sync::oneshot::Receiver<()>,
+) {
+    // Dropped while idle, which also stops the processes and tasks collectors keep running
+    let mut registry: Option<collectors::Registry> = None;
+    let mut encoder = protocol::Encoder::new(&config);
+    if let Some(session) = encoder.session() {
+        link.commands().bind(session);
+    }
+    let mut commands = link.commands().subscribe();
+    let mut base = std::time::Duration::from_millis(config.interval);
+    let low_power_interval = std::time::Duration::from_millis(config.low_power.interval);
+    let mut low_power = false;
+    let mut interval = transport::quality::AdaptiveInterval::new(
+        base,
+        match config.adaptive_interval {
+            true => std::time::Duration::from_millis(config.max_interval),
+            false => base,
+        },
+    );
+    let mut state = link.state();
     loop {
-        let systeminfo = system_info::SystemInfo::get_system_info(&mut system_info).await;
-        let systeminfo_bytes = bincode::serialize(&systeminfo).unwrap();
-        // println!("Writing {:?} to Flipper", systeminfo_bytes);
-
-        if let Err(e) = flipper
-            .write(
-                cmd_char,
-                &systeminfo_bytes,
-                btleplug::api::WriteType::WithoutResponse,
-            )
-            .await
+        // Nobody would see the data without a Flipper, unless a sink or the tray wants it
+        if samples.receiver_count() == 0 && *state.borrow() != transport::ConnectionState::Connected
         {
-            println!("[{id}] Failed to write: {e}");
-        };
+            if registry.take().is_some() {
+                tracing::info!("No Flipper connected, pausing collection");
+            }
+            let connected = state.wait_for(|state| *state == transport::ConnectionState::Connected);
+            tokio::select! {
+                connected = connected => if connected.is_err() {
+                    return;
+                },
+                _ = &mut stop => return,
+            }
+            encoder.keyframe();
+            link.send(encoder.hello());
+        }
+
+        let registry = registry.get_or_insert_with(|| {
+            let mut registry = collectors::Registry::new(&config);
+            registry.set_low_power(low_power);
+            registry
+        });
+        let sample = registry
+            .collect()
+            .instrument(tracing::debug_span!("collect"))
+            .await;
+
+        // A Flipper that just connected has none of the previous values
+        if state.has_changed().unwrap_or(false)
+            && *state.borrow_and_update() == transport::ConnectionState::Connected
+        {
+            encoder.keyframe();
+            link.send(encoder.hello());
+        }
+
+        if *state.borrow() == transport::ConnectionState::Connected && !link.is_paused() {
+            // A hub pages through its agents, with a header saying whose stats these are
+            let remote = hub.as_ref().and_then(|hub| {
+                let page = hub.page();
+                encoder.set_host(page.header);
+                page.sample
+            });
+            for packet in encoder.encode(remote.as_ref().unwrap_or(&sample)) {
+                tracing::trace!(?packet, "Writing to Flipper");
+                link.send(packet);
+            }
+        }
 
-        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+        let on_battery = config.low_power.enabled
+            && sample
+                .battery
+                .as_ref()
+                .is_some_and(|battery| battery.charging == 0);
+        if on_battery != low_power {
+            low_power = on_battery;
+            tracing::info!(low_power, "Power source changed");
+            registry.set_low_power(low_power);
+            interval.set_base(match low_power {
+                true => base.max(low_power_interval),
+                false => base,
+            });
+        }
+
+        // No receivers just means no sinks are enabled
+        let _ = samples.send(sample);
+
+        // Commands cut the wait short so their effect shows up right away
+        tokio::select! {
+            _ = tokio::time::sleep(interval.update(link.quality())) => {}
+            Ok(command) = commands.recv() => match command {
+                commands::Command::SetInterval(ms) => {
+                    base = std::time::Duration::from_millis(ms.max(100) as u64);
+                    interval.set_base(match low_power {
+                        true => base.max(low_power_interval),
+                        false => base,
+                    });
+                }
+                commands::Command::ChangePage(page) => tracing::info!(page, "Flipper switched page"),
+                commands::Command::SwitchHost(step) => match &hub {
+                    Some(hub) => hub.switch(step),
+                    None => tracing::debug!("Not a hub, ignoring host switch"),
+                },
+                commands::Command::Version { layout, app_version } => {
+                    let app = protocol::Version::from_layout(layout);
+                    let app_version = format!("{}.{}", app_version >> 8, app_version & 0xFF);
+                    tracing::info!(?app, %app_version, "Flipper app reported its version");
+                    match encoder.negotiate(app) {
+                        Some(version) => {
+                            tracing::warn!(
+                                ?version,
+                                configured = ?config.protocol,
+                                "Flipper app expects an older layout, falling back to it"
+                            );
+                            if config.notifications {
+                                notifications::outdated_app();
+                            }
+                        }
+                        None if app > config.protocol => tracing::info!(
+                            ?app,
+                            "Flipper app understands a newer protocol, set `protocol` to use it"
+                        ),
+                        None => {}
+                    }
+                }
+                // Everything else only needs the immediate tick
+                _ => {}
+            },
+            _ = &mut stop => {
+                if *state.borrow() == transport::ConnectionState::Connected {
+                    link.send(encoder.goodbye());
+                }
+                return;
+            }
+        }
     }
 }
 
-async fn reconnect_thread(central: Adapter, id: PeripheralId) {
-    loop {
-        if let Some(flipper) = flipper_manager::get_flipper(&central, &id).await {
-            let _ = flipper.connect().await;
-        };
+async fn run(config: config::Config, daemon: bool) -> Result<(), Box<dyn Error>> {
+    let (samples, _) = tokio::sync::broadcast::channel(16);
+    serve(
+        transport::Link::new(),
+        samples,
+        config,
+        daemon,
+        shutdown_signal(),
+    )
+    .await
+}
 
-        tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+// Everything `run` does, for callers that want to watch the link or the samples themselves
+async fn serve(
+    link: transport::Link,
+    samples: tokio::sync::broadcast::Sender<collectors::Sample>,
+    config: config::Config,
+    daemon: bool,
+    // Resolves when it's time to say goodbye and disconnect
+    shutdown: impl std::future::Future<Output = ()>,
+) -> Result<(), Box<dyn Error>> {
+    if daemon {
+        #[cfg(target_os = "linux")]
+        service::systemd::spawn(&link);
     }
-}
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
-    pretty_env_logger::init();
-    // std::env::set_var("RUST_BACKTRACE", "full");
+    if let Some(mqtt) = &config.mqtt {
+        tokio::spawn(sinks::mqtt::run(mqtt.clone(), samples.subscribe()));
+    }
 
-    let manager = Manager::new().await?;
+    if let Some(csv) = &config.csv {
+        tokio::spawn(sinks::csv::run(csv.clone(), samples.subscribe()));
+    }
 
-    let central = flipper_manager::get_central(&manager).await;
-    println!("Found {:?} adapter", central.adapter_info().await.unwrap());
+    // `transport = "json"` without a [json] section writes to stdout
+    if config.json.is_some() || config.transport == transport::Kind::Json {
+        tokio::spawn(sinks::json::run(
+            config.json.clone().unwrap_or_default(),
+            samples.subscribe(),
+        ));
+    }
 
-    let mut events = central.events().await?;
+    if let Some(sqlite) = &config.sqlite {
+        tokio::spawn(sinks::sqlite::run(sqlite.clone(), samples.subscribe()));
+    }
 
-    println!("Scanning... Launch PC Monitor app on Flipper");
-    central.start_scan(ScanFilter::default()).await?;
+    if let Some(otlp) = &config.otlp {
+        tokio::spawn(sinks::otlp::run(otlp.clone(), samples.subscribe()));
+    }
 
-    let mut data_workers: HashMap<PeripheralId, tokio::task::JoinHandle<()>> = HashMap::new();
-    let mut reconnect_workers: HashMap<PeripheralId, tokio::task::JoinHandle<()>> = HashMap::new();
+    if let Some(statsd) = &config.statsd {
+        tokio::spawn(sinks::statsd::run(statsd.clone(), samples.subscribe()));
+    }
 
-    while let Some(event) = events.next().await {
-        match event {
-            CentralEvent::DeviceDiscovered(id) => {
-                if let Some(flp) = flipper_manager::get_flipper(&central, &id).await {
-                    println!("[{}] Connecting to Flipper", &id.to_string());
-                    if let Err(e) = flp.connect().await {
-                        println!("[{id}] Failed to connect to Flipper: {e}");
-                    }
-                }
-            }
-            CentralEvent::DeviceConnected(id) => {
-                if let Some(flp) = flipper_manager::get_flipper(&central, &id).await {
-                    flp.discover_services().await?;
-                    println!("[{id}] Connected to Flipper");
+    if let Some(influxdb) = &config.influxdb {
+        tokio::spawn(sinks::influxdb::run(influxdb.clone(), samples.subscribe()));
+    }
 
-                    data_workers.insert(id.clone(), tokio::spawn(data_sender(flp)));
-                };
+    if !config.webhooks.is_empty() {
+        tokio::spawn(sinks::webhook::run(
+            config.webhooks.clone(),
+            config.alerts.clone(),
+            link.clone(),
+            samples.subscribe(),
+        ));
+    }
 
-                if let Some(worker) = reconnect_workers.get(&id) {
-                    worker.abort();
-                    reconnect_workers.remove(&id);
-                }
+    #[cfg(target_os = "linux")]
+    if config.dbus {
+        tokio::spawn(sinks::dbus::run(link.clone(), samples.subscribe()));
+    }
+
+    if let Some(http) = &config.http {
+        tokio::spawn(sinks::http::run(
+            http.clone(),
+            link.clone(),
+            samples.clone(),
+        ));
+    }
+
+    // SSH hosts are shown the same way as agents, without having to listen for any
+    let hub = (config.hub.is_some() || !config.ssh.is_empty()).then(|| {
+        let hub = relay::Hub::new(&config.hub.clone().unwrap_or_default());
+        if let Some(listen) = &config.hub {
+            tokio::spawn(relay::hub::listen(listen.clone(), hub.clone()));
+        }
+        for host in &config.ssh {
+            tokio::spawn(relay::ssh::run(host.clone(), config.units, hub.clone()));
+        }
+        hub
+    });
+
+    if let Some(psk) = &config.psk {
+        link.commands().set_key(psk);
+    }
+    handlers::spawn(&link, &config);
+    control::spawn(&link, &samples, &config);
+    if config.notifications {
+        notifications::spawn(&link);
+    }
+    let (stop, stopped) = tokio::sync::oneshot::channel();
+    let collector = tokio::spawn(data_collector(
+        link.clone(),
+        samples,
+        config.clone(),
+        hub,
+        stopped,
+    ));
+
+    let transport = run_transport(
+        link.clone(),
+        config.transport,
+        config.devices(),
+        config.ble.clone(),
+        config.rpc_app.clone(),
+    );
+    tokio::pin!(transport);
+    tokio::select! {
+        result = &mut transport => return result,
+        _ = shutdown => {}
+    }
+
+    tracing::info!("Shutting down");
+    #[cfg(target_os = "linux")]
+    if daemon {
+        service::systemd::notify("STOPPING=1");
+    }
+
+    // The collector queues the goodbye packet on its way out, then the transports
+    // write it and disconnect. Neither gets to hold up the exit for long
+    let _ = stop.send(());
+    let _ = tokio::time::timeout(SHUTDOWN_TIMEOUT, collector).await;
+    link.close();
+    match tokio::time::timeout(SHUTDOWN_TIMEOUT, transport).await {
+        Ok(result) => result,
+        Err(_) => {
+            tracing::warn!("Transport didn't close in time");
+            Ok(())
+        }
+    }
+}
+
+// Collects without a Flipper of its own and forwards everything to a hub
+async fn agent(config: config::Config) -> Result<(), Box<dyn Error>> {
+    let agent = config.agent.clone().unwrap_or_default();
+    let (samples, _) = tokio::sync::broadcast::channel(16);
+    let receiver = samples.subscribe();
+    let forward = async move {
+        match agent.hub.is_empty() {
+            true => relay::agent::serve(agent, receiver).await,
+            false => relay::agent::run(agent, receiver).await,
+        }
+    };
+    let (stop, stopped) = tokio::sync::oneshot::channel();
+    let collector = tokio::spawn(data_collector(
+        transport::Link::new(),
+        samples,
+        config,
+        None,
+        stopped,
+    ));
+
+    tokio::select! {
+        _ = forward => {}
+        _ = shutdown_signal() => {}
+    }
+    let _ = stop.send(());
+    let _ = tokio::time::timeout(SHUTDOWN_TIMEOUT, collector).await;
+    Ok(())
+}
+
+async fn run_transport(
+    link: transport::Link,
+    kind: transport::Kind,
+    devices: Vec<String>,
+    ble: transport::BleConfig,
+    rpc_app: String,
+) -> Result<(), Box<dyn Error>> {
+    match kind {
+        #[cfg(not(target_os = "freebsd"))]
+        transport::Kind::Ble => transport::ble::run(link, devices, ble).await,
+        #[cfg(target_os = "freebsd")]
+        transport::Kind::Ble => {
+            let _ = ble;
+            Err("BLE isn't supported on FreeBSD, use the serial transport".into())
+        }
+        transport::Kind::Serial => transport::serial::run(link, devices).await,
+        transport::Kind::Rpc => transport::rpc::run(link, devices, rpc_app).await,
+        transport::Kind::Tcp => transport::net::run_tcp(link, devices).await,
+        transport::Kind::Udp => transport::net::run_udp(link, devices).await,
+        transport::Kind::DryRun => transport::dry_run::run(link).await,
+        // The json sink has the samples already, there's no Flipper to send packets to
+        transport::Kind::Json => {
+            link.closed().await;
+            Ok(())
+        }
+    }
+}
+
+// Ctrl+C, or SIGTERM from systemd, launchd or kill
+async fn shutdown_signal() {
+    let interrupt = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::warn!("Failed to listen for Ctrl+C: {e}");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
             }
-            CentralEvent::DeviceDisconnected(id) => {
-                if let Some(worker) = data_workers.get(&id) {
-                    worker.abort();
-                    println!("[{id}] Disconnected from Flipper. Waiting for reconnection");
+            Err(e) => {
+                tracing::warn!("Failed to listen for SIGTERM: {e}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => {}
+        _ = terminate => {}
+    }
+}
+
+async fn test(config: &config::Config) -> Result<(), Box<dyn Error>> {
+    let mut registry = collectors::Registry::new(config);
+    println!("Collectors: {:?}", registry.names());
+
+    // CPU usage needs two refreshes to be meaningful
+    registry.collect().await;
+    tokio::time::sleep(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL).await;
+    let sample = registry.collect().await;
+
+    println!("{sample:#?}");
+    for packet in protocol::Encoder::new(config).encode(&sample) {
+        println!("{packet:02x?}");
+    }
+
+    Ok(())
+}
+
+// Single sample as JSON on stdout, meant for scripts and for debugging odd values
+async fn once(config: &config::Config) -> Result<(), Box<dyn Error>> {
+    let mut registry = collectors::Registry::new(config);
+
+    // CPU usage needs two refreshes to be meaningful
+    registry.collect().await;
+    tokio::time::sleep(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL).await;
+    let sample = registry.collect().await;
+    let gpus = collectors::gpu_info::GpuInfo::get_all_gpu_info().await;
+
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&serde_json::json!({ "sample": sample, "gpus": gpus }))?
+    );
 
-                    data_workers.remove(&id);
-                };
+    if let Some(system) = &sample.system {
+        let unknown: Vec<&str> = collectors::system_info::SystemInfo::VALID_FLAGS
+            .into_iter()
+            .filter(|(flag, _)| system.valid & flag == 0)
+            .map(|(_, name)| name)
+            .collect();
+        if !unknown.is_empty() {
+            tracing::warn!(?unknown, "Some values couldn't be read");
+        }
+    }
+
+    match sample.is_empty() {
+        true => Err("No collector returned any data".into()),
+        false => Ok(()),
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    // std::env::set_var("RUST_BACKTRACE", "full");
+    let cli = cli::Cli::parse();
+
+    let mut config = config::Config::load(cli.config.as_deref())?;
+    if cli.log_level.is_some() {
+        config.log_level = cli.log_level;
+    }
 
-                reconnect_workers.insert(
-                    id.clone(),
-                    tokio::spawn(reconnect_thread(central.clone(), id)),
+    let _log_guard = logging::init(&config, cli.verbose)?;
+
+    match cli
+        .command
+        .unwrap_or(cli::Command::Run(cli::RunArgs::default()))
+    {
+        cli::Command::Run(args) => {
+            let daemon = args.daemon;
+            let one_shot = args.once;
+            #[cfg(feature = "tray")]
+            let tray = args.tray;
+            args.apply(&mut config);
+
+            if one_shot {
+                return once(&config).await;
+            }
+
+            #[cfg(feature = "tray")]
+            if tray {
+                return tray::run(config);
+            }
+            run(config, daemon).await
+        }
+        #[cfg(not(target_os = "freebsd"))]
+        cli::Command::Scan { timeout } => {
+            transport::ble::scan(std::time::Duration::from_secs(timeout), &config.ble).await
+        }
+        #[cfg(target_os = "freebsd")]
+        cli::Command::Scan { .. } => Err("BLE isn't supported on FreeBSD".into()),
+        #[cfg(target_os = "linux")]
+        cli::Command::Pair { timeout } => {
+            let timeout = std::time::Duration::from_secs(timeout);
+            transport::bluez::pair(config.devices(), config.ble.clone(), timeout).await
+        }
+        #[cfg(target_os = "linux")]
+        cli::Command::ForgetDevice { device } => {
+            transport::bluez::forget(device.into_iter().collect(), config.ble.service_uuid).await
+        }
+        // The system asks for the PIN by itself the first time the app connects, and keeps
+        // the bond in its Bluetooth settings
+        #[cfg(not(target_os = "linux"))]
+        cli::Command::Pair { .. } | cli::Command::ForgetDevice { .. } => Err(
+            "Pairing is handled by the system here, remove the Flipper in its Bluetooth settings \
+             to forget it"
+                .into(),
+        ),
+        #[cfg(target_os = "linux")]
+        cli::Command::InstallService => service::systemd::install(cli.config.as_deref()),
+        #[cfg(target_os = "linux")]
+        cli::Command::UninstallService => service::systemd::uninstall(),
+        #[cfg(target_os = "macos")]
+        cli::Command::InstallService => service::launchd::install(cli.config.as_deref()).await,
+        #[cfg(target_os = "macos")]
+        cli::Command::UninstallService => service::launchd::uninstall(),
+        #[cfg(windows)]
+        cli::Command::InstallService => service::windows::install(cli.config.as_deref()),
+        #[cfg(windows)]
+        cli::Command::UninstallService => service::windows::uninstall(),
+        #[cfg(windows)]
+        cli::Command::Service => service::windows::dispatch(config),
+        cli::Command::Agent { hub } => {
+            if let Some(hub) = hub {
+                config.agent.get_or_insert_with(Default::default).hub = hub;
+            }
+            agent(config).await
+        }
+        cli::Command::Test => test(&config).await,
+        cli::Command::History { metric, last, raw } => {
+            sinks::sqlite::query(&config.sqlite.unwrap_or_default(), &metric, last, raw)
+        }
+        cli::Command::Gpus => {
+            for (index, gpu) in collectors::gpu_info::GpuInfo::get_all_gpu_info()
+                .await
+                .iter()
+                .enumerate()
+            {
+                println!(
+                    "[{index}] {} ({}, {} MiB VRAM, {}% load)",
+                    gpu.name,
+                    match gpu.is_integrated() {
+                        true => "integrated",
+                        false => "discrete",
+                    },
+                    gpu.vram_max,
+                    gpu.gpu_usage
                 );
             }
-            _ => {}
+            Ok(())
         }
     }
-    Ok(())
 }
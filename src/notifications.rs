@@ -0,0 +1,56 @@
+use crate::transport::{ConnectionState, Link};
+
+// Desktop notifications when a Flipper drops off and when it's back, the app itself just
+// keeps showing the last values
+pub fn spawn(link: &Link) {
+    let link = link.clone();
+    let mut state = link.state();
+    tokio::spawn(async move {
+        let mut connected = *state.borrow_and_update() == ConnectionState::Connected;
+        let mut lost = false;
+        while state.changed().await.is_ok() {
+            let now_connected = *state.borrow_and_update() == ConnectionState::Connected;
+            match (connected, now_connected) {
+                // Disconnecting on purpose during shutdown isn't worth a notification
+                (true, false) if !link.is_closed() => {
+                    lost = true;
+                    show(
+                        "Flipper disconnected",
+                        "PC Monitor stopped receiving updates, trying to reconnect",
+                    );
+                }
+                (false, true) if lost => {
+                    lost = false;
+                    show(
+                        "Flipper reconnected",
+                        "PC Monitor is receiving updates again",
+                    );
+                }
+                _ => {}
+            }
+            connected = now_connected;
+        }
+    });
+}
+
+// The app asked for an older layout than `protocol`, so some stats don't make it to the Flipper
+pub fn outdated_app() {
+    show(
+        "Flipper app is outdated",
+        "Update PC Monitor on the Flipper, it only gets the stats its version understands",
+    );
+}
+
+fn show(summary: &'static str, body: &'static str) {
+    // Talks to D-Bus/WinRT/NotificationCenter synchronously
+    tokio::task::spawn_blocking(move || {
+        let result = notify_rust::Notification::new()
+            .appname("Flipper PC Monitor")
+            .summary(summary)
+            .body(body)
+            .show();
+        if let Err(e) = result {
+            tracing::debug!("Failed to show notification: {e}");
+        }
+    });
+}
@@ -0,0 +1,184 @@
+/*
+Optional BLE layer for packets bigger than a single write. A write carries at most the ATT
+MTU minus 3 bytes, 20 with the default MTU of 23. Packets that fit are written as they are,
+anything longer is split into chunks the receiver puts back together:
+
+typedef struct {
+    uint8_t magic;        // CHUNK_MAGIC
+    uint8_t id;           // same for every chunk of a packet, increments per packet
+    uint8_t index;        // 0 for the first chunk
+    uint8_t count;        // chunks making up the packet
+    uint8_t data[];       // up to MTU - 3 - 4 bytes of the packet
+} Chunk;
+
+A chunk with a different id than the ones before drops the incomplete packet, so do
+chunks with a count of 0 or an index past it. Repeated chunks are ignored. Commands
+from the Flipper may be chunked the same way, a sealed command is over 20 bytes.
+*/
+
+pub const CHUNK_MAGIC: u8 = 0xF9;
+pub const DEFAULT_MTU: u16 = 23;
+const ATT_HEADER: usize = 3;
+const CHUNK_HEADER: usize = 4;
+
+#[derive(Debug)]
+pub struct Chunker {
+    // Largest write, MTU minus the ATT header
+    max_write: usize,
+    id: u8,
+}
+
+impl Chunker {
+    pub fn new(mtu: u16) -> Self {
+        Chunker {
+            // Leaves at least a byte of data per chunk
+            max_write: (mtu as usize)
+                .saturating_sub(ATT_HEADER)
+                .max(CHUNK_HEADER + 1),
+            id: 0,
+        }
+    }
+
+    pub fn split(&mut self, packet: Vec<u8>) -> Vec<Vec<u8>> {
+        if packet.len() <= self.max_write {
+            return vec![packet];
+        }
+
+        let chunks: Vec<&[u8]> = packet.chunks(self.max_write - CHUNK_HEADER).collect();
+        let Ok(count) = u8::try_from(chunks.len()) else {
+            tracing::error!(length = packet.len(), "Dropping packet too big to chunk");
+            return Vec::new();
+        };
+
+        let id = self.id;
+        self.id = self.id.wrapping_add(1);
+        chunks
+            .into_iter()
+            .enumerate()
+            .map(|(index, data)| {
+                let mut chunk = vec![CHUNK_MAGIC, id, index as u8, count];
+                chunk.extend_from_slice(data);
+                chunk
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Reassembler {
+    id: u8,
+    count: u8,
+    data: Vec<u8>,
+    received: u8,
+}
+
+impl Reassembler {
+    // The whole packet once its last chunk arrives, anything not chunked right away
+    pub fn push(&mut self, write: &[u8]) -> Option<Vec<u8>> {
+        let [CHUNK_MAGIC, id, index, count, data @ ..] = write else {
+            return Some(write.to_vec());
+        };
+
+        if *count == 0 || index >= count {
+            // Can't be part of anything we sent a Chunker, drop what's pending with it
+            self.reset();
+            return None;
+        }
+
+        let ongoing = self.received > 0 && *id == self.id && *count == self.count;
+        if !ongoing && *index == 0 {
+            self.reset();
+            self.id = *id;
+            self.count = *count;
+        } else if !ongoing || *index > self.received {
+            // Lost or reordered chunk, the rest of this packet is useless
+            self.reset();
+            return None;
+        } else if *index < self.received {
+            // Repeated write of a chunk we already have
+            return None;
+        }
+
+        self.data.extend_from_slice(data);
+        self.received = self.received.checked_add(1)?;
+        (self.received == self.count).then(|| {
+            self.received = 0;
+            std::mem::take(&mut self.data)
+        })
+    }
+
+    fn reset(&mut self) {
+        self.received = 0;
+        self.data.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let packet: Vec<u8> = (0..100).collect();
+        let mut reassembler = Reassembler::default();
+        let chunks = Chunker::new(DEFAULT_MTU).split(packet.clone());
+        assert_eq!(chunks.len(), 7);
+
+        let (last, rest) = chunks.split_last().unwrap();
+        for chunk in rest {
+            assert_eq!(reassembler.push(chunk), None);
+        }
+        assert_eq!(reassembler.push(last), Some(packet));
+    }
+
+    #[test]
+    fn small_packets_pass_through() {
+        assert_eq!(
+            Chunker::new(DEFAULT_MTU).split(vec![1, 2]),
+            vec![vec![1, 2]]
+        );
+        assert_eq!(Reassembler::default().push(&[0xFB, 1]), Some(vec![0xFB, 1]));
+    }
+
+    #[test]
+    fn malformed_chunks_are_dropped() {
+        let mut reassembler = Reassembler::default();
+        assert_eq!(reassembler.push(&[CHUNK_MAGIC, 0, 0, 0, 1]), None);
+        assert_eq!(reassembler.push(&[CHUNK_MAGIC, 0, 2, 2, 1]), None);
+        assert_eq!(reassembler.push(&[CHUNK_MAGIC, 0, 255, 255, 1]), None);
+
+        // A chunk without its predecessors
+        assert_eq!(reassembler.push(&[CHUNK_MAGIC, 1, 1, 2, 1]), None);
+    }
+
+    #[test]
+    fn duplicates_are_ignored() {
+        let mut reassembler = Reassembler::default();
+        assert_eq!(reassembler.push(&[CHUNK_MAGIC, 3, 0, 3, 1]), None);
+        assert_eq!(reassembler.push(&[CHUNK_MAGIC, 3, 0, 3, 1]), None);
+        assert_eq!(reassembler.push(&[CHUNK_MAGIC, 3, 1, 3, 2]), None);
+        assert_eq!(reassembler.push(&[CHUNK_MAGIC, 3, 1, 3, 2]), None);
+        assert_eq!(
+            reassembler.push(&[CHUNK_MAGIC, 3, 2, 3, 3]),
+            Some(vec![1, 2, 3])
+        );
+    }
+
+    #[test]
+    fn lost_chunk_drops_the_packet() {
+        let mut reassembler = Reassembler::default();
+        assert_eq!(reassembler.push(&[CHUNK_MAGIC, 4, 0, 3, 1]), None);
+        assert_eq!(reassembler.push(&[CHUNK_MAGIC, 4, 2, 3, 3]), None);
+        assert_eq!(reassembler.push(&[CHUNK_MAGIC, 5, 0, 1, 9]), Some(vec![9]));
+    }
+
+    #[test]
+    fn every_chunk_index_fits() {
+        let mut reassembler = Reassembler::default();
+        let mut result = None;
+        for index in 0..=254u8 {
+            result = reassembler.push(&[CHUNK_MAGIC, 6, index, 255, index]);
+        }
+        assert_eq!(result, Some((0..=254).collect()));
+    }
+}
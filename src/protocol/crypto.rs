@@ -0,0 +1,284 @@
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+/*
+Optional authenticated encryption with a pre-shared key (`psk`), entered on both ends.
+Every packet to the Flipper and every command from it is wrapped as a whole, after delta
+encoding and before framing.
+
+typedef struct {
+    uint8_t magic;        // SEALED_MAGIC
+    uint8_t session[16];  // random, picked by each end when it starts
+    uint64_t counter;     // increments per packet within a session, starts at 0
+    uint8_t ciphertext[]; // the plain packet or command, magic byte included
+    uint8_t tag[16];
+} SealedPacket;
+
+The cipher is AES-256-GCM, which the Flipper's crypto hardware supports. Every session
+gets its own key, so nonces only have to be unique within one:
+
+key   = SHA-256(SHA-256("flipper-pc-monitor" || psk) || session || bound)
+nonce = counter, 4 zero bytes
+aad   = magic, direction  // DIRECTION_TO_FLIPPER or DIRECTION_FROM_FLIPPER
+
+`bound` is empty for packets to the Flipper. Commands are bound to the backend: their
+`bound` is the session of the last packet the app received, so commands recorded while an
+earlier backend ran never open. The app may start new sessions of its own any time.
+
+Receivers drop anything that fails to verify, and within a session anything whose counter
+isn't higher than the last one accepted. The backend remembers every session of the app it
+has accepted, so a recorded one can't be replayed after the app moved on to the next.
+Anything not sealed is dropped as well once a key is set.
+*/
+
+pub const SEALED_MAGIC: u8 = 0xFA;
+pub const DIRECTION_TO_FLIPPER: u8 = 0x00;
+pub const DIRECTION_FROM_FLIPPER: u8 = 0x01;
+
+const KEY_CONTEXT: &[u8] = b"flipper-pc-monitor";
+const SESSION_LEN: usize = 16;
+const COUNTER_LEN: usize = 8;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
+pub type Session = [u8; SESSION_LEN];
+
+fn psk_key(psk: &str) -> [u8; 32] {
+    Sha256::new()
+        .chain_update(KEY_CONTEXT)
+        .chain_update(psk.as_bytes())
+        .finalize()
+        .into()
+}
+
+fn session_cipher(psk_key: &[u8; 32], session: &Session, bound: &[u8]) -> Aes256Gcm {
+    let key = Sha256::new()
+        .chain_update(psk_key)
+        .chain_update(session)
+        .chain_update(bound)
+        .finalize();
+    Aes256Gcm::new(&key)
+}
+
+fn nonce(counter: u64) -> [u8; NONCE_LEN] {
+    let mut nonce = [0; NONCE_LEN];
+    nonce[..COUNTER_LEN].copy_from_slice(&counter.to_le_bytes());
+    nonce
+}
+
+// Outgoing side, packets to the Flipper
+pub struct Sealer {
+    cipher: Aes256Gcm,
+    session: Session,
+    counter: u64,
+    direction: u8,
+}
+
+impl Sealer {
+    pub fn new(psk: &str) -> Self {
+        Self::with_direction(psk, DIRECTION_TO_FLIPPER, &[])
+    }
+
+    fn with_direction(psk: &str, direction: u8, bound: &[u8]) -> Self {
+        let mut session = [0; SESSION_LEN];
+        OsRng.fill_bytes(&mut session);
+        Sealer {
+            cipher: session_cipher(&psk_key(psk), &session, bound),
+            session,
+            counter: 0,
+            direction,
+        }
+    }
+
+    // Commands have to be bound to this (see above)
+    pub fn session(&self) -> Session {
+        self.session
+    }
+
+    pub fn seal(&mut self, packet: &[u8]) -> Vec<u8> {
+        let counter = self.counter;
+        self.counter += 1;
+
+        let payload = Payload {
+            msg: packet,
+            aad: &[SEALED_MAGIC, self.direction],
+        };
+        // Only fails for payloads far beyond anything a packet gets to
+        let sealed = self
+            .cipher
+            .encrypt(Nonce::from_slice(&nonce(counter)), payload)
+            .unwrap();
+
+        let mut packet = Vec::with_capacity(1 + SESSION_LEN + COUNTER_LEN + sealed.len());
+        packet.push(SEALED_MAGIC);
+        packet.extend(self.session);
+        packet.extend(counter.to_le_bytes());
+        packet.extend(sealed);
+        packet
+    }
+}
+
+// Incoming side, commands from the Flipper
+pub struct Opener {
+    psk_key: [u8; 32],
+    // Session of our Sealer, nothing opens until it's known
+    bound: Option<Session>,
+    // Every session of the app accepted so far, its cipher and last counter
+    sessions: HashMap<Session, (Aes256Gcm, u64)>,
+    direction: u8,
+}
+
+impl Opener {
+    pub fn new(psk: &str) -> Self {
+        Opener {
+            psk_key: psk_key(psk),
+            bound: None,
+            sessions: HashMap::new(),
+            direction: DIRECTION_FROM_FLIPPER,
+        }
+    }
+
+    pub fn bind(&mut self, session: Session) {
+        self.bound = Some(session);
+        self.sessions.clear();
+    }
+
+    // None for anything forged, corrupted, replayed or not sealed at all
+    pub fn open(&mut self, sealed: &[u8]) -> Option<Vec<u8>> {
+        let bound = self.bound?;
+        let [SEALED_MAGIC, rest @ ..] = sealed else {
+            return None;
+        };
+        if rest.len() < SESSION_LEN + COUNTER_LEN + TAG_LEN {
+            return None;
+        }
+
+        let (session, rest) = rest.split_at(SESSION_LEN);
+        let (counter, ciphertext) = rest.split_at(COUNTER_LEN);
+        let session: Session = session.try_into().unwrap();
+        let counter = u64::from_le_bytes(counter.try_into().unwrap());
+
+        let cipher = match self.sessions.get(&session) {
+            Some((_, last)) if counter <= *last => return None,
+            Some((cipher, _)) => cipher.clone(),
+            None => session_cipher(&self.psk_key, &session, &bound),
+        };
+
+        let payload = Payload {
+            msg: ciphertext,
+            aad: &[SEALED_MAGIC, self.direction],
+        };
+        let packet = cipher
+            .decrypt(Nonce::from_slice(&nonce(counter)), payload)
+            .ok()?;
+        self.sessions.insert(session, (cipher, counter));
+        Some(packet)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The app's end, replying to `backend`
+    fn app(psk: &str, backend: &Sealer) -> Sealer {
+        Sealer::with_direction(psk, DIRECTION_FROM_FLIPPER, &backend.session())
+    }
+
+    fn pair(psk: &str) -> (Sealer, Opener) {
+        let backend = Sealer::new(psk);
+        let mut opener = Opener::new(psk);
+        opener.bind(backend.session());
+        (app(psk, &backend), opener)
+    }
+
+    #[test]
+    fn round_trip() {
+        let (mut sealer, mut opener) = pair("hunter2");
+        for command in [&[0xFB, 0x01, 0x02][..], &[0xFB, 0x06, 0xFF], &[]] {
+            let sealed = sealer.seal(command);
+            assert_eq!(
+                sealed.len(),
+                1 + SESSION_LEN + COUNTER_LEN + command.len() + TAG_LEN
+            );
+            assert_eq!(opener.open(&sealed).as_deref(), Some(command));
+        }
+    }
+
+    #[test]
+    fn tampering_and_wrong_keys_are_rejected() {
+        let (mut sealer, mut opener) = pair("hunter2");
+        let sealed = sealer.seal(&[0xFB, 0x05, 0x02]);
+
+        let mut tampered = sealed.clone();
+        *tampered.last_mut().unwrap() ^= 0x01;
+        assert_eq!(opener.open(&tampered), None);
+
+        let mut tampered = sealed.clone();
+        tampered[1 + SESSION_LEN + COUNTER_LEN] ^= 0x01;
+        assert_eq!(opener.open(&tampered), None);
+
+        let mut wrong_key = Opener::new("hunter3");
+        wrong_key.bind(opener.bound.unwrap());
+        assert_eq!(wrong_key.open(&sealed), None);
+        assert_eq!(opener.open(&sealed[..sealed.len() - 1]), None);
+        assert_eq!(opener.open(&[0xFB, 0x05, 0x02]), None);
+    }
+
+    #[test]
+    fn directions_dont_mix() {
+        let mut to_flipper = Sealer::new("hunter2");
+        let mut opener = Opener::new("hunter2");
+        opener.bind(to_flipper.session());
+        assert_eq!(opener.open(&to_flipper.seal(&[0xFB])), None);
+    }
+
+    #[test]
+    fn replayed_and_old_counters_are_rejected() {
+        let (mut sealer, mut opener) = pair("hunter2");
+        let first = sealer.seal(&[0xFB, 0x01, 0x00]);
+        let second = sealer.seal(&[0xFB, 0x01, 0x01]);
+
+        assert!(opener.open(&second).is_some());
+        assert_eq!(opener.open(&second), None);
+        assert_eq!(opener.open(&first), None);
+        assert!(opener.open(&sealer.seal(&[0xFB, 0x01, 0x02])).is_some());
+    }
+
+    #[test]
+    fn new_sessions_get_their_own_key() {
+        let backend = Sealer::new("hunter2");
+        let mut opener = Opener::new("hunter2");
+        opener.bind(backend.session());
+        let mut first = app("hunter2", &backend);
+        let mut second = app("hunter2", &backend);
+        assert_ne!(first.session, second.session);
+
+        let a = first.seal(&[0xFB]);
+        let b = second.seal(&[0xFB]);
+        // Same counter, same plaintext, different sessions
+        assert_ne!(a[1 + SESSION_LEN..], b[1 + SESSION_LEN..]);
+        assert!(opener.open(&a).is_some());
+        assert!(opener.open(&b).is_some());
+        // The first session is still remembered after the app moved on
+        assert_eq!(opener.open(&a), None);
+    }
+
+    #[test]
+    fn sessions_from_earlier_backends_are_rejected() {
+        let earlier = Sealer::new("hunter2");
+        let recorded = app("hunter2", &earlier).seal(&[0xFB, 0x01, 0x00]);
+
+        let backend = Sealer::new("hunter2");
+        let mut opener = Opener::new("hunter2");
+        assert_eq!(opener.open(&recorded), None);
+        opener.bind(backend.session());
+        assert_eq!(opener.open(&recorded), None);
+        assert!(opener
+            .open(&app("hunter2", &backend).seal(&[0xFB, 0x01, 0x00]))
+            .is_some());
+    }
+}
@@ -0,0 +1,190 @@
+use super::v2::{TlvWriter, TAG_ALERT, TAG_DELTA, TAG_HISTORY};
+
+/*
+Optional mode for v2 that leaves out fields which didn't change since the
+previous packet. A delta packet starts with TAG_DELTA, tags not set in its
+mask keep the values from the packet before. Keyframes are plain v2 packets
+without TAG_DELTA and replace everything.
+
+TAG_DELTA    uint64_t changed  // bit n set for every tag n included in this packet
+
+Repeated tags (disks, processes, ...) are sent as a whole whenever any of
+them changed. Tags that disappeared (an unplugged battery, a list that
+emptied) get their mask bit set and a single empty field, which clears
+them. History and alerts are events rather than state and always go out
+when present. Ticks where nothing changed send no packet at all.
+*/
+
+// Tags that are only sent when something happened, never diffed
+const EVENT_TAGS: [u8; 2] = [TAG_HISTORY, TAG_ALERT];
+
+#[derive(Debug)]
+pub struct Delta {
+    keyframe_every: u32,
+    ticks: u32,
+    // Raw fields of the last packet per tag, in the order they first appeared
+    previous: Vec<(u8, Vec<u8>)>,
+}
+
+impl Delta {
+    pub fn new(keyframe_every: u32) -> Self {
+        Delta {
+            keyframe_every: keyframe_every.max(1),
+            ticks: 0,
+            previous: Vec::new(),
+        }
+    }
+
+    // The next packet replaces everything, e.g. for a Flipper that just connected
+    pub fn keyframe(&mut self) {
+        self.ticks = 0;
+    }
+
+    pub fn encode(&mut self, packet: Vec<u8>) -> Option<Vec<u8>> {
+        let current = group_fields(&packet[2..]);
+        let removed: Vec<u8> = self
+            .previous
+            .iter()
+            .map(|(tag, _)| *tag)
+            .filter(|tag| !EVENT_TAGS.contains(tag) && !current.iter().any(|(t, _)| t == tag))
+            .collect();
+        // Past the mask, only a keyframe can clear those
+        if removed.iter().any(|tag| *tag >= 64) {
+            self.keyframe();
+        }
+        let keyframe = self.ticks % self.keyframe_every == 0;
+        self.ticks = self.ticks.wrapping_add(1);
+
+        if keyframe {
+            self.previous = current;
+            return Some(packet);
+        }
+
+        let mut changed: Vec<_> = current
+            .iter()
+            .filter(|(tag, fields)| {
+                EVENT_TAGS.contains(tag)
+                    || !self
+                        .previous
+                        .iter()
+                        .any(|(previous, values)| previous == tag && values == fields)
+            })
+            .cloned()
+            .collect();
+        changed.extend(removed.into_iter().map(|tag| (tag, vec![tag, 0])));
+        self.previous = current;
+        if changed.is_empty() {
+            return None;
+        }
+
+        let mask = changed
+            .iter()
+            .filter(|(tag, _)| *tag < 64)
+            .fold(0u64, |mask, (tag, _)| mask | 1 << tag);
+        let mut writer = TlvWriter::new();
+        writer.field(TAG_DELTA, &mask);
+        let mut delta = writer.finish();
+        for (_, fields) in &changed {
+            delta.extend_from_slice(fields);
+        }
+        Some(delta)
+    }
+}
+
+// Splits TLV fields by tag, keeping each field's tag and length bytes
+fn group_fields(mut fields: &[u8]) -> Vec<(u8, Vec<u8>)> {
+    let mut groups: Vec<(u8, Vec<u8>)> = Vec::new();
+    while let [tag, length, ..] = *fields {
+        let end = (2 + length as usize).min(fields.len());
+        let (field, rest) = fields.split_at(end);
+        match groups.iter_mut().find(|(group, _)| *group == tag) {
+            Some((_, values)) => values.extend_from_slice(field),
+            None => groups.push((tag, field.to_vec())),
+        }
+        fields = rest;
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::v2::{PACKET_MAGIC, TAG_CPU, TAG_GPU_TEMP, TAG_UPTIME, VERSION};
+
+    fn packet(cpu: u8, gpu_temp: u8, uptime: u8) -> Vec<u8> {
+        let mut writer = TlvWriter::new();
+        writer.raw(TAG_CPU, &[cpu]);
+        writer.raw(TAG_GPU_TEMP, &[gpu_temp]);
+        writer.raw(TAG_UPTIME, &[uptime, 0, 0, 0]);
+        writer.finish()
+    }
+
+    #[test]
+    fn unchanged_fields_are_left_out() {
+        let mut delta = Delta::new(30);
+        assert_eq!(delta.encode(packet(10, 50, 1)), Some(packet(10, 50, 1)));
+        assert_eq!(delta.encode(packet(10, 50, 1)), None);
+
+        let mask = 1u64 << TAG_UPTIME;
+        let mut expected = vec![PACKET_MAGIC, VERSION, TAG_DELTA, 8];
+        expected.extend(mask.to_le_bytes());
+        expected.extend([TAG_UPTIME, 4, 2, 0, 0, 0]);
+        assert_eq!(delta.encode(packet(10, 50, 2)), Some(expected));
+    }
+
+    #[test]
+    fn mask_bits_match_the_tags() {
+        let mut delta = Delta::new(30);
+        delta.encode(packet(10, 50, 1));
+
+        let encoded = delta.encode(packet(20, 60, 1)).unwrap();
+        let mask = u64::from_le_bytes(encoded[4..12].try_into().unwrap());
+        assert_eq!(mask, (1 << TAG_CPU) | (1 << TAG_GPU_TEMP));
+        assert_eq!(encoded[12..], [TAG_CPU, 1, 20, TAG_GPU_TEMP, 1, 60]);
+    }
+
+    #[test]
+    fn events_always_go_out() {
+        let mut delta = Delta::new(30);
+        let mut writer = TlvWriter::new();
+        writer.raw(TAG_ALERT, &[0, 1, 95, 90]);
+        let alert = writer.finish();
+
+        delta.encode(alert.clone());
+        let encoded = delta.encode(alert).unwrap();
+        assert_eq!(encoded[12..], [TAG_ALERT, 4, 0, 1, 95, 90]);
+    }
+
+    #[test]
+    fn keyframe_every_few_ticks() {
+        let mut delta = Delta::new(3);
+        let keyframes: Vec<bool> = (0..7)
+            .map(|_| delta.encode(packet(10, 50, 1)) == Some(packet(10, 50, 1)))
+            .collect();
+        assert_eq!(keyframes, [true, false, false, true, false, false, true]);
+
+        delta.encode(packet(10, 50, 1));
+        delta.keyframe();
+        assert_eq!(delta.encode(packet(10, 50, 1)), Some(packet(10, 50, 1)));
+    }
+
+    #[test]
+    fn removed_tags_are_cleared() {
+        let mut delta = Delta::new(30);
+        delta.encode(packet(10, 50, 1));
+
+        let mut writer = TlvWriter::new();
+        writer.raw(TAG_CPU, &[10]);
+        writer.raw(TAG_UPTIME, &[1, 0, 0, 0]);
+        let encoded = delta.encode(writer.finish()).unwrap();
+        let mask = u64::from_le_bytes(encoded[4..12].try_into().unwrap());
+        assert_eq!(mask, 1 << TAG_GPU_TEMP);
+        assert_eq!(encoded[12..], [TAG_GPU_TEMP, 0]);
+
+        // Gone stays gone without being sent again
+        let mut writer = TlvWriter::new();
+        writer.raw(TAG_CPU, &[10]);
+        writer.raw(TAG_UPTIME, &[1, 0, 0, 0]);
+        assert_eq!(delta.encode(writer.finish()), None);
+    }
+}
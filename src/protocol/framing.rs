@@ -0,0 +1,183 @@
+/*
+Optional envelope around every packet so the app can detect truncated or
+corrupted writes and notice dropped ones through gaps in the sequence. Always used on
+byte stream transports (see `Kind::is_stream`), where nothing else marks where a packet
+ends, and commands from the app come framed the same way there.
+
+typedef struct {
+    uint8_t magic;        // FRAME_MAGIC
+    uint16_t sequence;    // increments per frame, wraps around
+    uint16_t length;      // payload length
+    uint8_t payload[];
+    uint16_t crc;         // CRC-16/CCITT-FALSE of everything before it
+} Frame;
+*/
+
+pub const FRAME_MAGIC: u8 = 0xFE;
+const HEADER: usize = 5;
+const CRC: usize = 2;
+// Commands are a few bytes, sealed ones a few dozen. Anything longer is a bogus length
+const MAX_COMMAND: usize = 512;
+
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = match crc & 0x8000 {
+                0 => crc << 1,
+                _ => (crc << 1) ^ 0x1021,
+            };
+        }
+    }
+    crc
+}
+
+#[derive(Debug, Default)]
+pub struct Framer {
+    sequence: u16,
+}
+
+impl Framer {
+    pub fn frame(&mut self, payload: &[u8]) -> Vec<u8> {
+        let mut frame = Vec::with_capacity(payload.len() + 7);
+        frame.push(FRAME_MAGIC);
+        frame.extend(self.sequence.to_le_bytes());
+        frame.extend((payload.len() as u16).to_le_bytes());
+        frame.extend_from_slice(payload);
+        frame.extend(crc16(&frame).to_le_bytes());
+
+        self.sequence = self.sequence.wrapping_add(1);
+        frame
+    }
+}
+
+// Sequence and payload of a single complete frame
+pub fn decode(frame: &[u8]) -> Option<(u16, &[u8])> {
+    let [FRAME_MAGIC, s0, s1, l0, l1, rest @ ..] = frame else {
+        return None;
+    };
+    let length = u16::from_le_bytes([*l0, *l1]) as usize;
+    if rest.len() != length + CRC {
+        return None;
+    }
+    let (body, crc) = frame.split_at(frame.len() - CRC);
+    if crc16(body) != u16::from_le_bytes([crc[0], crc[1]]) {
+        return None;
+    }
+    Some((u16::from_le_bytes([*s0, *s1]), &rest[..length]))
+}
+
+// Splits a byte stream back into commands, however the reads cut it up
+#[derive(Debug, Default)]
+pub struct Deframer {
+    buffer: Vec<u8>,
+}
+
+impl Deframer {
+    // Payloads of every frame completed by `data`, garbage in between is skipped
+    pub fn push(&mut self, data: &[u8]) -> Vec<Vec<u8>> {
+        self.buffer.extend_from_slice(data);
+        let mut payloads = Vec::new();
+        loop {
+            let start = self.buffer.iter().position(|&b| b == FRAME_MAGIC);
+            self.buffer.drain(..start.unwrap_or(self.buffer.len()));
+            if self.buffer.len() < HEADER {
+                break;
+            }
+
+            let length = u16::from_le_bytes([self.buffer[3], self.buffer[4]]) as usize;
+            let end = HEADER + length + CRC;
+            if length <= MAX_COMMAND && self.buffer.len() < end {
+                break;
+            }
+            match decode(&self.buffer[..end.min(self.buffer.len())]) {
+                Some((_, payload)) if length <= MAX_COMMAND => {
+                    payloads.push(payload.to_vec());
+                    self.buffer.drain(..end);
+                }
+                // Not a frame after all, look for the next magic byte
+                _ => {
+                    self.buffer.drain(..1);
+                }
+            }
+        }
+        payloads
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc_check_value() {
+        assert_eq!(crc16(b"123456789"), 0x29B1);
+        assert_eq!(crc16(&[]), 0xFFFF);
+    }
+
+    #[test]
+    fn frame_layout() {
+        let mut framer = Framer { sequence: u16::MAX };
+        let frame = framer.frame(&[0xFB, 0x01]);
+        assert_eq!(
+            frame[..7],
+            [FRAME_MAGIC, 0xFF, 0xFF, 0x02, 0x00, 0xFB, 0x01]
+        );
+        assert_eq!(frame[7..], crc16(&frame[..7]).to_le_bytes());
+        // Wraps around
+        assert_eq!(framer.frame(&[])[1..3], [0x00, 0x00]);
+    }
+
+    #[test]
+    fn round_trip_across_sequence_wrap() {
+        let mut framer = Framer {
+            sequence: u16::MAX - 1,
+        };
+        for (expected, payload) in [(u16::MAX - 1, &[1u8, 2][..]), (u16::MAX, &[]), (0, &[3])] {
+            let frame = framer.frame(payload);
+            assert_eq!(frame.len(), payload.len() + HEADER + CRC);
+            assert_eq!(decode(&frame), Some((expected, payload)));
+        }
+    }
+
+    #[test]
+    fn corrupted_frames_are_rejected() {
+        let mut frame = Framer::default().frame(&[0xFB, 0x01, 0x02]);
+        assert_eq!(decode(&frame[..frame.len() - 1]), None);
+        frame[5] ^= 0x01;
+        assert_eq!(decode(&frame), None);
+    }
+
+    #[test]
+    fn deframer_handles_split_and_merged_reads() {
+        let mut framer = Framer::default();
+        let mut stream = vec![0x00, FRAME_MAGIC, 0x42];
+        stream.extend(framer.frame(&[0xFB, 0x01, 0x02]));
+        stream.extend(framer.frame(&[0xFB, 0x02, 0xE8, 0x03]));
+
+        let mut deframer = Deframer::default();
+        let mut commands = Vec::new();
+        for read in stream.chunks(3) {
+            commands.extend(deframer.push(read));
+        }
+        assert_eq!(
+            commands,
+            vec![vec![0xFB, 0x01, 0x02], vec![0xFB, 0x02, 0xE8, 0x03]]
+        );
+
+        let mut merged = framer.frame(&[0xFB, 0x06, 0x01]);
+        merged.extend(framer.frame(&[0xFB, 0x06, 0xFF]));
+        assert_eq!(deframer.push(&merged).len(), 2);
+    }
+
+    #[test]
+    fn bogus_lengths_are_skipped() {
+        let mut stream = vec![FRAME_MAGIC, 0x00, 0x00, 0xFF, 0xFF];
+        stream.extend(Framer::default().frame(&[0xFB, 0x01, 0x00]));
+        assert_eq!(
+            Deframer::default().push(&stream),
+            vec![vec![0xFB, 0x01, 0x00]]
+        );
+    }
+}
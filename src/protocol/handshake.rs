@@ -0,0 +1,78 @@
+use super::Version;
+use crate::helpers::pop_8u8;
+
+/*
+Sent once whenever a Flipper connects, before any stats. Apps that know it answer with
+COMMAND_VERSION (see commands.rs) telling which layout they understand, apps that don't
+ignore it like any other packet with an unknown magic.
+
+typedef struct {
+    uint8_t magic;        // VERSION_MAGIC
+    uint8_t newest;       // newest layout the backend speaks, LAYOUT_*
+    uint8_t layout;       // layout it's sending right now, as configured
+    char backend[8];      // backend version, e.g. "0.9.0"
+} VersionPacket;
+
+LAYOUT_DATA_STRUCT 0  // only the fixed DataStruct, the very first app versions
+LAYOUT_V1          1  // DataStruct plus the typed extended packets
+LAYOUT_V2          2  // single TLV packet per tick
+
+When the app's layout is older than the configured one the backend falls back to it until
+the next connect. Apps that don't answer keep getting what's configured.
+*/
+
+pub const VERSION_MAGIC: u8 = 0xF8;
+pub const LAYOUT_DATA_STRUCT: u8 = 0x00;
+pub const LAYOUT_V1: u8 = 0x01;
+pub const LAYOUT_V2: u8 = 0x02;
+
+impl Version {
+    pub fn layout(self) -> u8 {
+        match self {
+            Version::Legacy => LAYOUT_DATA_STRUCT,
+            Version::V1 => LAYOUT_V1,
+            Version::V2 => LAYOUT_V2,
+        }
+    }
+
+    // Layouts newer than ours are fine, we just can't send them
+    pub fn from_layout(layout: u8) -> Self {
+        match layout {
+            LAYOUT_DATA_STRUCT => Version::Legacy,
+            LAYOUT_V1 => Version::V1,
+            _ => Version::V2,
+        }
+    }
+}
+
+pub fn encode_version(layout: Version) -> Vec<u8> {
+    let mut packet = vec![VERSION_MAGIC, LAYOUT_V2, layout.layout()];
+    packet.extend(pop_8u8(env!("CARGO_PKG_VERSION").as_bytes()));
+    packet
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layouts_round_trip() {
+        for version in [Version::Legacy, Version::V1, Version::V2] {
+            assert_eq!(Version::from_layout(version.layout()), version);
+        }
+    }
+
+    #[test]
+    fn unknown_layouts_are_newer() {
+        assert_eq!(Version::from_layout(LAYOUT_V2 + 1), Version::V2);
+        assert_eq!(Version::from_layout(0xFF), Version::V2);
+    }
+
+    #[test]
+    fn version_packet_layout() {
+        let packet = encode_version(Version::V1);
+        assert_eq!(packet.len(), 11);
+        assert_eq!(packet[..3], [VERSION_MAGIC, LAYOUT_V2, LAYOUT_V1]);
+        assert!(packet[3..].starts_with(env!("CARGO_PKG_VERSION").as_bytes()));
+    }
+}
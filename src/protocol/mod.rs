@@ -0,0 +1,268 @@
+use crate::alerts::{AlertKind, Alerts};
+use crate::collectors::Sample;
+use crate::config::Config;
+use crate::history::History;
+use crate::units::TempUnit;
+use serde::{Deserialize, Serialize};
+
+pub mod chunking;
+pub mod crypto;
+pub mod delta;
+pub mod framing;
+pub mod handshake;
+pub mod v1;
+pub mod v2;
+
+// Oldest first, an app that reports an older layout than configured gets that one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Version {
+    // Only the fixed DataStruct, for the very first app versions
+    Legacy,
+    // Fixed DataStruct plus the typed extended packets, understood by every app version
+    V1,
+    // Single TLV packet per tick
+    V2,
+}
+
+// Which host the stats are from, sent by a hub so the Flipper can label and page through them
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct HostHeader {
+    pub page: u8,
+    pub page_count: u8,
+    pub name: [u8; 16],
+}
+
+pub struct Encoder {
+    version: Version,
+    // What `protocol` says, `version` may be older for the app that's connected
+    configured: Version,
+    max_cores: u8,
+    framer: Option<framing::Framer>,
+    sealer: Option<crypto::Sealer>,
+    delta: Option<delta::Delta>,
+    history: Option<History>,
+    history_every: u32,
+    ticks: u32,
+    alerts: Alerts,
+    legacy_sizes: bool,
+    temp_unit: TempUnit,
+    host: Option<HostHeader>,
+}
+
+impl Encoder {
+    pub fn new(config: &Config) -> Self {
+        Encoder {
+            version: config.protocol,
+            configured: config.protocol,
+            max_cores: config.cores,
+            framer: (config.framing || config.transport.is_stream()).then(framing::Framer::default),
+            sealer: config.psk.as_deref().map(crypto::Sealer::new),
+            delta: match (config.delta, config.protocol) {
+                (true, Version::V2) => Some(delta::Delta::new(config.delta_keyframe_every)),
+                (true, _) => {
+                    tracing::warn!("Delta updates need protocol v2, sending full packets");
+                    None
+                }
+                (false, _) => None,
+            },
+            history: match config.history_length {
+                0 => None,
+                length => Some(History::new(length as usize)),
+            },
+            history_every: config.history_every.max(1),
+            ticks: 0,
+            alerts: Alerts::new(config.alerts.clone()),
+            legacy_sizes: config.legacy_sizes,
+            temp_unit: config.temp_unit,
+            host: None,
+        }
+    }
+
+    // Sends everything again on the next tick, for a Flipper that just (re)connected
+    pub fn keyframe(&mut self) {
+        if let Some(delta) = &mut self.delta {
+            delta.keyframe();
+        }
+    }
+
+    // First packet to a Flipper that just connected, asking the app which layout it wants. Goes
+    // back to the configured one until the app answers
+    pub fn hello(&mut self) -> Vec<u8> {
+        self.set_version(self.configured);
+        self.wrap(handshake::encode_version(self.configured))
+    }
+
+    // The app's answer to `hello`, the layout used from now on if it changed
+    pub fn negotiate(&mut self, app: Version) -> Option<Version> {
+        let version = app.min(self.configured);
+        (version != self.version).then(|| {
+            self.set_version(version);
+            version
+        })
+    }
+
+    fn set_version(&mut self, version: Version) {
+        if version != self.version {
+            self.version = version;
+            self.keyframe();
+        }
+    }
+
+    // Labels the following samples, switching to another host starts over with a keyframe and
+    // an empty history so nothing of the previous one is left on screen
+    pub fn set_host(&mut self, host: HostHeader) {
+        if self
+            .host
+            .as_ref()
+            .is_some_and(|current| current.name != host.name)
+        {
+            self.keyframe();
+            if let Some(history) = &mut self.history {
+                history.clear();
+            }
+        }
+        self.host = Some(host);
+    }
+
+    // Last packet before shutting down, so the app shows the backend as gone instead of
+    // freezing on the last values. Never delta encoded
+    pub fn goodbye(&mut self) -> Vec<u8> {
+        let packet = match self.version {
+            Version::Legacy | Version::V1 => v1::encode_goodbye(),
+            Version::V2 => v2::encode_goodbye(),
+        };
+        self.wrap(packet)
+    }
+
+    // Session of the sealed packets, None without `psk`
+    pub fn session(&self) -> Option<crypto::Session> {
+        self.sealer.as_ref().map(crypto::Sealer::session)
+    }
+
+    // Encryption and framing, the last steps before a packet goes out
+    fn wrap(&mut self, packet: Vec<u8>) -> Vec<u8> {
+        let packet = match &mut self.sealer {
+            Some(sealer) => sealer.seal(&packet),
+            None => packet,
+        };
+        match &mut self.framer {
+            Some(framer) => framer.frame(&packet),
+            None => packet,
+        }
+    }
+
+    pub fn encode(&mut self, sample: &Sample) -> Vec<Vec<u8>> {
+        if let Some(history) = &mut self.history {
+            history.push(sample);
+        }
+
+        // History only goes out every few ticks, it changes slowly and is comparatively big
+        let history = match self.ticks % self.history_every {
+            0 => self.history.as_ref(),
+            _ => None,
+        };
+        self.ticks = self.ticks.wrapping_add(1);
+
+        // Thresholds are in Celsius, so alerts are checked before converting
+        let mut alerts = self.alerts.check(sample);
+        let converted;
+        let sample = match self.temp_unit {
+            TempUnit::Celsius => sample,
+            unit => {
+                for alert in alerts.iter_mut().filter(|a| a.kind == AlertKind::GpuTemp) {
+                    alert.value = unit.convert(alert.value);
+                    alert.threshold = unit.convert(alert.threshold);
+                }
+                converted = convert_temps(sample, unit);
+                &converted
+            }
+        };
+
+        let packets = match self.version {
+            Version::Legacy => sample.system.iter().map(v1::encode_data).collect(),
+            Version::V1 => {
+                let mut packets: Vec<_> = self.host.iter().map(v1::encode_host).collect();
+                packets.extend(v1::encode_sample(sample, self.max_cores, self.temp_unit));
+                if let Some(history) = history {
+                    packets.extend(v1::encode_history(history));
+                }
+                packets.extend(alerts.iter().map(v1::encode_alert));
+                packets
+            }
+            Version::V2 => {
+                let packet = v2::encode_sample(
+                    sample,
+                    self.max_cores,
+                    history,
+                    &alerts,
+                    self.legacy_sizes,
+                    self.temp_unit,
+                    self.host.as_ref(),
+                );
+                match &mut self.delta {
+                    Some(delta) => delta.encode(packet).into_iter().collect(),
+                    None => vec![packet],
+                }
+            }
+        };
+
+        packets.into_iter().map(|p| self.wrap(p)).collect()
+    }
+}
+
+fn convert_temps(sample: &Sample, unit: TempUnit) -> Sample {
+    let mut sample = sample.clone();
+    if let Some(system) = &mut sample.system {
+        system.gpu_temp = unit.convert(system.gpu_temp);
+    }
+    for temp in sample.temps.iter_mut().flatten() {
+        temp.temp = unit.convert(temp.temp);
+    }
+    for drive in sample.smart.iter_mut().flatten() {
+        drive.temp = unit.convert(drive.temp);
+    }
+    #[cfg(target_os = "linux")]
+    if let Some(rpi) = &mut sample.rpi {
+        rpi.temp = unit.convert(rpi.temp);
+    }
+    sample
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encoder(protocol: Version) -> Encoder {
+        Encoder::new(&Config {
+            protocol,
+            ..Config::default()
+        })
+    }
+
+    #[test]
+    fn falls_back_to_older_apps() {
+        let mut encoder = encoder(Version::V2);
+        assert_eq!(encoder.negotiate(Version::V1), Some(Version::V1));
+        assert_eq!(encoder.version, Version::V1);
+        // Same answer again, nothing changes
+        assert_eq!(encoder.negotiate(Version::V1), None);
+        assert_eq!(encoder.negotiate(Version::Legacy), Some(Version::Legacy));
+    }
+
+    #[test]
+    fn never_goes_past_the_configured_layout() {
+        let mut encoder = encoder(Version::V1);
+        assert_eq!(encoder.negotiate(Version::from_layout(0x7F)), None);
+        assert_eq!(encoder.version, Version::V1);
+    }
+
+    #[test]
+    fn reconnecting_starts_over_with_the_configured_layout() {
+        let mut encoder = encoder(Version::V2);
+        encoder.negotiate(Version::Legacy);
+        encoder.hello();
+        assert_eq!(encoder.version, Version::V2);
+        assert_eq!(encoder.negotiate(Version::V2), None);
+    }
+}
@@ -0,0 +1,602 @@
+use crate::alerts::Alert;
+use crate::collectors::battery_info::BatteryInfo;
+use crate::collectors::disk_info::DiskInfo;
+use crate::collectors::fan_info::FanInfo;
+use crate::collectors::gpu_process_info::GpuProcessInfo;
+use crate::collectors::network_info::NetworkInfo;
+use crate::collectors::power_info::PowerInfo;
+use crate::collectors::process_info::ProcessInfo;
+#[cfg(target_os = "linux")]
+use crate::collectors::rpi_info::RpiInfo;
+use crate::collectors::sensor_info::SensorReading;
+use crate::collectors::smart_info::SmartInfo;
+use crate::collectors::system_info::SystemInfo;
+use crate::collectors::temp_info::TempInfo;
+use crate::collectors::ups_info::UpsInfo;
+use crate::collectors::Sample;
+use crate::history::History;
+use crate::protocol::HostHeader;
+use crate::units::TempUnit;
+use serde::Serialize;
+
+/*
+typedef struct {
+    uint8_t cpu_usage;
+    uint16_t ram_max;
+    uint8_t ram_usage;
+    char ram_unit[4];
+    uint8_t gpu_usage;
+    uint16_t vram_max;
+    uint8_t vram_usage;
+    char vram_unit[4];
+} DataStruct;
+*/
+
+#[derive(Serialize, Debug, Clone)]
+struct DataStruct {
+    cpu_usage: u8,
+    ram_max: u16,
+    ram_usage: u8,
+    ram_unit: [u8; 4],
+    gpu_usage: u8,
+    vram_max: u16,
+    vram_usage: u8,
+    vram_unit: [u8; 4],
+}
+
+/*
+Extended packets start with a magic byte that can never be a valid cpu_usage,
+so app versions that only know DataStruct can tell them apart.
+
+typedef struct {
+    uint8_t magic;        // PACKET_MAGIC
+    uint8_t type;         // PACKET_CORES
+    uint8_t core_count;   // cores on the PC, may be more than the slots sent
+    uint8_t core_usage[]; // always max_cores entries, 0xFF for unused slots
+} CoresPacket;
+*/
+
+/*
+typedef struct {
+    uint8_t magic;        // PACKET_MAGIC
+    uint8_t type;         // PACKET_GPU
+    uint8_t gpu_temp;     // degrees in temp_unit, 0xFF if unknown
+    uint16_t gpu_power;   // watts x10, 0xFFFF if unknown
+    uint16_t gpu_clock;   // MHz, 0xFFFF if unknown
+    uint16_t vram_clock;  // MHz, 0xFFFF if unknown
+    uint8_t gpu_fan;      // percent, 0xFF if unknown
+    uint8_t gpu_encoder;  // video encoder load in percent, 0xFF if unknown
+    uint8_t gpu_decoder;  // video decoder load in percent, 0xFF if unknown
+} GpuPacket;
+
+typedef struct {
+    char label[8];        // mount point as configured, e.g. "/" or "C:"
+    uint16_t disk_max;
+    uint8_t disk_usage;
+    char disk_unit[4];
+} DiskStruct;
+
+typedef struct {
+    uint8_t magic;        // PACKET_MAGIC
+    uint8_t type;         // PACKET_DISKS
+    uint8_t disk_count;
+    DiskStruct disks[];
+} DisksPacket;
+
+typedef struct {
+    uint8_t magic;        // PACKET_MAGIC
+    uint8_t type;         // PACKET_NETWORK
+    uint16_t rx_rate;     // download per second, same x10 encoding as ram_max
+    char rx_unit[4];
+    uint16_t tx_rate;     // upload per second
+    char tx_unit[4];
+} NetworkPacket;
+
+typedef struct {
+    uint8_t magic;        // PACKET_MAGIC
+    uint8_t type;         // PACKET_BATTERY
+    uint8_t level;        // percent
+    uint8_t charging;     // 1 while plugged in
+    uint16_t time_remaining; // minutes, 0xFFFF if unknown
+} BatteryPacket;
+
+typedef struct {
+    uint8_t magic;        // PACKET_MAGIC
+    uint8_t type;         // PACKET_HISTORY
+    uint8_t series;       // 0 cpu, 1 ram, 2 gpu, 3 vram usage
+    uint8_t count;
+    uint8_t values[];     // oldest first
+} HistoryPacket;
+
+typedef struct {
+    uint8_t magic;        // PACKET_MAGIC
+    uint8_t type;         // PACKET_ALERT
+    uint8_t metric;       // 0 cpu usage, 1 ram usage, 2 gpu usage, 3 gpu temp
+    uint8_t active;       // 1 when crossing the threshold, 0 once cleared
+    uint8_t value;
+    uint8_t threshold;
+} AlertPacket;
+
+typedef struct {
+    uint8_t magic;        // PACKET_MAGIC
+    uint8_t type;         // PACKET_LOAD
+    uint16_t load[3];     // 1, 5 and 15 minute load average x100, Unix only
+} LoadPacket;
+
+typedef struct {
+    uint8_t magic;        // PACKET_MAGIC
+    uint8_t type;         // PACKET_CPU_FREQ
+    uint16_t freq_avg;    // MHz, average over all cores
+    uint16_t freq_max;    // MHz, fastest core
+} CpuFreqPacket;
+
+typedef struct {
+    uint8_t magic;        // PACKET_MAGIC
+    uint8_t type;         // PACKET_UPTIME
+    uint32_t uptime;      // seconds since boot
+} UptimePacket;
+
+typedef struct {
+    uint8_t magic;        // PACKET_MAGIC
+    uint8_t type;         // PACKET_MEMORY
+    uint32_t ram_used;    // MiB
+    uint32_t ram_total;   // MiB
+    uint32_t vram_used;   // MiB, 0xFFFFFFFF if unknown
+    uint32_t vram_total;  // MiB, 0xFFFFFFFF if unknown
+} MemoryPacket;
+
+typedef struct {
+    uint8_t magic;        // PACKET_MAGIC
+    uint8_t type;         // PACKET_VALID
+    uint16_t valid;       // bit set for every value that was read, unset ones are N/A
+} ValidPacket;
+
+VALID_CPU 0, VALID_RAM 1, VALID_GPU 2, VALID_VRAM 3, VALID_GPU_TEMP 4, VALID_GPU_POWER 5,
+VALID_GPU_CLOCK 6, VALID_GPU_FAN 7, VALID_GPU_CODEC 8, VALID_LOAD 9
+
+typedef struct {
+    char name[16];
+    uint8_t cpu_usage;    // percent of the whole machine
+    uint8_t ram_usage;    // percent of total RAM
+} ProcessStruct;
+
+typedef struct {
+    uint8_t magic;        // PACKET_MAGIC
+    uint8_t type;         // PACKET_PROCESSES
+    uint8_t process_count;
+    ProcessStruct processes[]; // busiest first
+} ProcessesPacket;
+
+typedef struct {
+    char label[8];
+    uint16_t rpm;
+} FanStruct;
+
+typedef struct {
+    uint8_t magic;        // PACKET_MAGIC
+    uint8_t type;         // PACKET_FANS
+    uint8_t fan_count;
+    FanStruct fans[];
+} FansPacket;
+
+typedef struct {
+    char label[16];       // "<chip> <label>", e.g. "nvme Composite"
+    uint8_t temp;         // degrees in temp_unit
+} TempStruct;
+
+typedef struct {
+    uint8_t magic;        // PACKET_MAGIC
+    uint8_t type;         // PACKET_TEMPS
+    uint8_t temp_count;
+    TempStruct temps[];
+} TempsPacket;
+
+typedef struct {
+    char label[16];       // as named in HWiNFO, the SNMP config or the BMC
+    float value;
+    char unit[4];
+} SensorStruct;
+
+typedef struct {
+    uint8_t magic;        // PACKET_MAGIC
+    uint8_t type;         // PACKET_SENSORS
+    uint8_t sensor_count;
+    SensorStruct sensors[]; // HWiNFO (Windows), SNMP and IPMI readings, at most 16
+} SensorsPacket;
+
+typedef struct {
+    char label[8];        // device name, e.g. "sda" or "nvme0"
+    uint8_t temp;         // degrees in temp_unit, 0xFF if unknown
+    uint8_t status;       // 0 OK, 1 WARN
+} SmartStruct;
+
+typedef struct {
+    uint8_t magic;        // PACKET_MAGIC
+    uint8_t type;         // PACKET_SMART
+    uint8_t drive_count;
+    SmartStruct drives[];
+} SmartPacket;
+
+typedef struct {
+    uint8_t magic;        // PACKET_MAGIC
+    uint8_t type;         // PACKET_UPS
+    uint8_t charge;       // percent, 0xFF if unknown
+    uint8_t load;         // percent, 0xFF if unknown
+    uint8_t on_battery;
+} UpsPacket;
+
+typedef struct {
+    uint8_t magic;        // PACKET_MAGIC
+    uint8_t type;         // PACKET_RPI
+    uint8_t temp;         // SoC degrees in temp_unit, 0xFF if unknown
+    uint32_t throttled;   // vcgencmd get_throttled flags
+    uint16_t core_voltage; // millivolts
+    uint16_t gpu_mem;     // VideoCore memory split in MB
+} RpiPacket;
+
+typedef struct {
+    char name[8];         // e.g. "E" or "P0"
+    uint8_t usage;        // active residency in percent
+} ClusterStruct;
+
+typedef struct {
+    uint8_t magic;        // PACKET_MAGIC
+    uint8_t type;         // PACKET_POWER
+    uint16_t cpu_power;   // watts x10, 0xFFFF if unknown
+    uint16_t gpu_power;   // watts x10, 0xFFFF if unknown
+    uint8_t cluster_count; // Apple Silicon only
+    ClusterStruct clusters[];
+} PowerPacket;
+
+typedef struct {
+    char name[16];
+    uint16_t vram_used;   // MB over all NVIDIA GPUs
+} GpuProcessStruct;
+
+typedef struct {
+    uint8_t magic;        // PACKET_MAGIC
+    uint8_t type;         // PACKET_GPU_PROCESSES
+    uint8_t process_count;
+    GpuProcessStruct processes[]; // most VRAM first
+} GpuProcessesPacket;
+
+typedef struct {
+    uint8_t magic;        // PACKET_MAGIC
+    uint8_t type;         // PACKET_TEMP_UNIT
+    char temp_unit;       // 'C' or 'F', applies to every temperature above
+} TempUnitPacket;
+
+typedef struct {
+    uint8_t magic;        // PACKET_MAGIC
+    uint8_t type;         // PACKET_GOODBYE, the backend is shutting down
+} GoodbyePacket;
+
+typedef struct {
+    uint8_t magic;        // PACKET_MAGIC
+    uint8_t type;         // PACKET_HOST, sent before the other packets by a hub
+    uint8_t page;         // 0 is the hub itself
+    uint8_t page_count;
+    char name[16];        // host the following packets describe
+} HostPacket;
+*/
+
+pub const PACKET_MAGIC: u8 = 0xFC;
+pub const PACKET_CORES: u8 = 0x01;
+pub const PACKET_GPU: u8 = 0x02;
+pub const PACKET_DISKS: u8 = 0x03;
+pub const PACKET_NETWORK: u8 = 0x04;
+pub const PACKET_BATTERY: u8 = 0x05;
+pub const PACKET_HISTORY: u8 = 0x06;
+pub const PACKET_ALERT: u8 = 0x07;
+pub const PACKET_LOAD: u8 = 0x08;
+pub const PACKET_CPU_FREQ: u8 = 0x09;
+pub const PACKET_UPTIME: u8 = 0x0A;
+pub const PACKET_PROCESSES: u8 = 0x0B;
+pub const PACKET_FANS: u8 = 0x0C;
+pub const PACKET_TEMPS: u8 = 0x0D;
+pub const PACKET_SENSORS: u8 = 0x0E;
+pub const PACKET_SMART: u8 = 0x0F;
+pub const PACKET_UPS: u8 = 0x10;
+pub const PACKET_RPI: u8 = 0x11;
+pub const PACKET_POWER: u8 = 0x12;
+pub const PACKET_GPU_PROCESSES: u8 = 0x13;
+pub const PACKET_MEMORY: u8 = 0x14;
+pub const PACKET_VALID: u8 = 0x15;
+pub const PACKET_TEMP_UNIT: u8 = 0x16;
+pub const PACKET_GOODBYE: u8 = 0x17;
+pub const PACKET_HOST: u8 = 0x18;
+
+pub fn encode_data(info: &SystemInfo) -> Vec<u8> {
+    let data = DataStruct {
+        cpu_usage: info.cpu_usage,
+        ram_max: info.ram_max,
+        ram_usage: info.ram_usage,
+        ram_unit: info.ram_unit,
+        gpu_usage: info.gpu_usage,
+        vram_max: info.vram_max,
+        vram_usage: info.vram_usage,
+        vram_unit: info.vram_unit,
+    };
+
+    bincode::serialize(&data).unwrap()
+}
+
+pub fn encode_cores(info: &SystemInfo, max_cores: u8) -> Vec<u8> {
+    let core_count = info.core_usage.len().min(u8::MAX as usize) as u8;
+
+    let mut packet = vec![PACKET_MAGIC, PACKET_CORES, core_count];
+    packet.extend(
+        (0..max_cores as usize).map(|i| info.core_usage.get(i).copied().unwrap_or(u8::MAX)),
+    );
+    packet
+}
+
+pub fn encode_gpu(info: &SystemInfo) -> Vec<u8> {
+    let mut packet = vec![PACKET_MAGIC, PACKET_GPU, info.gpu_temp];
+    packet.extend(info.gpu_power.to_le_bytes());
+    packet.extend(info.gpu_clock.to_le_bytes());
+    packet.extend(info.vram_clock.to_le_bytes());
+    packet.push(info.gpu_fan);
+    packet.push(info.gpu_encoder);
+    packet.push(info.gpu_decoder);
+    packet
+}
+
+pub fn encode_disks(disks: &[DiskInfo]) -> Vec<u8> {
+    let disks = &disks[..disks.len().min(u8::MAX as usize)];
+
+    let mut packet = vec![PACKET_MAGIC, PACKET_DISKS, disks.len() as u8];
+    for disk in disks {
+        packet.extend(bincode::serialize(disk).unwrap());
+    }
+    packet
+}
+
+pub fn encode_network(network: &NetworkInfo) -> Vec<u8> {
+    let mut packet = vec![PACKET_MAGIC, PACKET_NETWORK];
+    packet.extend(bincode::serialize(&network.wire()).unwrap());
+    packet
+}
+
+pub fn encode_battery(battery: &BatteryInfo) -> Vec<u8> {
+    let mut packet = vec![PACKET_MAGIC, PACKET_BATTERY];
+    packet.extend(bincode::serialize(battery).unwrap());
+    packet
+}
+
+pub fn encode_history(history: &History) -> Vec<Vec<u8>> {
+    history
+        .iter()
+        .map(|(series, values)| {
+            let mut packet = vec![
+                PACKET_MAGIC,
+                PACKET_HISTORY,
+                series as u8,
+                values.len() as u8,
+            ];
+            packet.extend(values);
+            packet
+        })
+        .collect()
+}
+
+pub fn encode_alert(alert: &Alert) -> Vec<u8> {
+    vec![
+        PACKET_MAGIC,
+        PACKET_ALERT,
+        alert.kind as u8,
+        alert.active as u8,
+        alert.value,
+        alert.threshold,
+    ]
+}
+
+pub fn encode_load(load_average: &[u16; 3]) -> Vec<u8> {
+    let mut packet = vec![PACKET_MAGIC, PACKET_LOAD];
+    packet.extend(bincode::serialize(load_average).unwrap());
+    packet
+}
+
+pub fn encode_cpu_freq(info: &SystemInfo) -> Vec<u8> {
+    let mut packet = vec![PACKET_MAGIC, PACKET_CPU_FREQ];
+    packet.extend(bincode::serialize(&(info.cpu_freq_avg, info.cpu_freq_max)).unwrap());
+    packet
+}
+
+pub fn encode_uptime(info: &SystemInfo) -> Vec<u8> {
+    let mut packet = vec![PACKET_MAGIC, PACKET_UPTIME];
+    packet.extend(info.uptime.to_le_bytes());
+    packet
+}
+
+pub fn encode_valid(info: &SystemInfo) -> Vec<u8> {
+    let mut packet = vec![PACKET_MAGIC, PACKET_VALID];
+    packet.extend(info.valid.to_le_bytes());
+    packet
+}
+
+pub fn encode_temp_unit(unit: TempUnit) -> Vec<u8> {
+    vec![PACKET_MAGIC, PACKET_TEMP_UNIT, unit.symbol()]
+}
+
+pub fn encode_goodbye() -> Vec<u8> {
+    vec![PACKET_MAGIC, PACKET_GOODBYE]
+}
+
+pub fn encode_host(host: &HostHeader) -> Vec<u8> {
+    let mut packet = vec![PACKET_MAGIC, PACKET_HOST, host.page, host.page_count];
+    packet.extend(host.name);
+    packet
+}
+
+pub fn encode_memory(info: &SystemInfo) -> Vec<u8> {
+    let mut packet = vec![PACKET_MAGIC, PACKET_MEMORY];
+    for value in [
+        info.ram_used_mib,
+        info.ram_total_mib,
+        info.vram_used_mib,
+        info.vram_total_mib,
+    ] {
+        packet.extend(value.to_le_bytes());
+    }
+    packet
+}
+
+pub fn encode_processes(processes: &[ProcessInfo]) -> Vec<u8> {
+    let processes = &processes[..processes.len().min(u8::MAX as usize)];
+
+    let mut packet = vec![PACKET_MAGIC, PACKET_PROCESSES, processes.len() as u8];
+    for process in processes {
+        packet.extend(bincode::serialize(process).unwrap());
+    }
+    packet
+}
+
+pub fn encode_gpu_processes(processes: &[GpuProcessInfo]) -> Vec<u8> {
+    let processes = &processes[..processes.len().min(u8::MAX as usize)];
+
+    let mut packet = vec![PACKET_MAGIC, PACKET_GPU_PROCESSES, processes.len() as u8];
+    for process in processes {
+        packet.extend(bincode::serialize(process).unwrap());
+    }
+    packet
+}
+
+pub fn encode_fans(fans: &[FanInfo]) -> Vec<u8> {
+    let fans = &fans[..fans.len().min(u8::MAX as usize)];
+
+    let mut packet = vec![PACKET_MAGIC, PACKET_FANS, fans.len() as u8];
+    for fan in fans {
+        packet.extend(bincode::serialize(fan).unwrap());
+    }
+    packet
+}
+
+pub fn encode_temps(temps: &[TempInfo]) -> Vec<u8> {
+    let temps = &temps[..temps.len().min(u8::MAX as usize)];
+
+    let mut packet = vec![PACKET_MAGIC, PACKET_TEMPS, temps.len() as u8];
+    for temp in temps {
+        packet.extend(bincode::serialize(temp).unwrap());
+    }
+    packet
+}
+
+pub fn encode_smart(drives: &[SmartInfo]) -> Vec<u8> {
+    let drives = &drives[..drives.len().min(u8::MAX as usize)];
+
+    let mut packet = vec![PACKET_MAGIC, PACKET_SMART, drives.len() as u8];
+    for drive in drives {
+        packet.extend(bincode::serialize(drive).unwrap());
+    }
+    packet
+}
+
+pub fn encode_ups(ups: &UpsInfo) -> Vec<u8> {
+    let mut packet = vec![PACKET_MAGIC, PACKET_UPS];
+    packet.extend(bincode::serialize(ups).unwrap());
+    packet
+}
+
+pub fn encode_power(power: &PowerInfo) -> Vec<u8> {
+    let clusters = &power.clusters[..power.clusters.len().min(u8::MAX as usize)];
+
+    let mut packet = vec![PACKET_MAGIC, PACKET_POWER];
+    packet.extend(bincode::serialize(&(power.cpu_power, power.gpu_power)).unwrap());
+    packet.push(clusters.len() as u8);
+    for cluster in clusters {
+        packet.extend(bincode::serialize(cluster).unwrap());
+    }
+    packet
+}
+
+#[cfg(target_os = "linux")]
+pub fn encode_rpi(rpi: &RpiInfo) -> Vec<u8> {
+    let mut packet = vec![PACKET_MAGIC, PACKET_RPI];
+    packet.extend(bincode::serialize(rpi).unwrap());
+    packet
+}
+
+pub fn encode_sensors(sensors: &[SensorReading]) -> Vec<u8> {
+    let sensors = &sensors[..sensors.len().min(u8::MAX as usize)];
+
+    let mut packet = vec![PACKET_MAGIC, PACKET_SENSORS, sensors.len() as u8];
+    for sensor in sensors {
+        packet.extend(bincode::serialize(sensor).unwrap());
+    }
+    packet
+}
+
+// All packets for one tick, DataStruct first so legacy apps update as early as possible
+pub fn encode_sample(sample: &Sample, max_cores: u8, temp_unit: TempUnit) -> Vec<Vec<u8>> {
+    let mut packets: Vec<_> = sample.system.iter().map(encode_data).collect();
+    packets.push(encode_temp_unit(temp_unit));
+
+    if let Some(system) = &sample.system {
+        packets.push(encode_gpu(system));
+        packets.push(encode_cpu_freq(system));
+        packets.push(encode_uptime(system));
+        packets.push(encode_memory(system));
+        packets.push(encode_valid(system));
+
+        if max_cores > 0 {
+            packets.push(encode_cores(system, max_cores));
+        }
+
+        if let Some(load_average) = &system.load_average {
+            packets.push(encode_load(load_average));
+        }
+    }
+
+    if let Some(disks) = &sample.disks {
+        packets.push(encode_disks(disks));
+    }
+
+    if let Some(network) = &sample.network {
+        packets.push(encode_network(network));
+    }
+
+    if let Some(processes) = &sample.processes {
+        packets.push(encode_processes(processes));
+    }
+
+    if let Some(processes) = &sample.gpu_processes {
+        packets.push(encode_gpu_processes(processes));
+    }
+
+    if let Some(fans) = &sample.fans {
+        packets.push(encode_fans(fans));
+    }
+
+    if let Some(temps) = &sample.temps {
+        packets.push(encode_temps(temps));
+    }
+
+    if let Some(drives) = &sample.smart {
+        packets.push(encode_smart(drives));
+    }
+
+    if let Some(ups) = &sample.ups {
+        packets.push(encode_ups(ups));
+    }
+
+    if let Some(power) = &sample.power {
+        packets.push(encode_power(power));
+    }
+
+    #[cfg(target_os = "linux")]
+    if let Some(rpi) = &sample.rpi {
+        packets.push(encode_rpi(rpi));
+    }
+
+    if let Some(sensors) = &sample.sensors {
+        packets.push(encode_sensors(sensors));
+    }
+
+    // Desktops simply don't get a battery packet
+    if let Some(battery) = &sample.battery {
+        packets.push(encode_battery(battery));
+    }
+
+    packets
+}
@@ -0,0 +1,435 @@
+use crate::alerts::Alert;
+use crate::collectors::Sample;
+use crate::history::History;
+use crate::protocol::HostHeader;
+use crate::units::TempUnit;
+use serde::Serialize;
+
+/*
+A v2 packet is a header followed by any number of TLV encoded fields.
+Apps skip tags they don't know, so new metrics can be added without
+breaking older versions.
+
+typedef struct {
+    uint8_t magic;        // PACKET_MAGIC
+    uint8_t version;      // VERSION
+} Header;
+
+typedef struct {
+    uint8_t tag;
+    uint8_t length;
+    uint8_t value[];      // little endian, layouts below
+} Field;
+
+TAG_VALID    uint16_t valid  // which system values were read, bits as in the v1 ValidPacket
+TAG_TEMP_UNIT char temp_unit  // 'C' or 'F', applies to every temperature in the packet
+TAG_DELTA    uint64_t changed  // only with `delta`, see delta.rs
+TAG_GOODBYE  (empty)                          // the backend is shutting down, sent alone
+TAG_HOST     uint8_t page; uint8_t page_count; char name[16]  // hub only, page 0 is the hub
+TAG_CPU      uint8_t cpu_usage
+TAG_RAM      uint16_t ram_max; uint8_t ram_usage; char ram_unit[4]  // legacy_sizes only
+TAG_GPU      uint8_t gpu_usage; uint16_t vram_max; uint8_t vram_usage; char vram_unit[4]
+             (legacy_sizes only)
+TAG_GPU_TEMP uint8_t gpu_temp
+TAG_CORES    uint8_t core_usage[]              // one byte per core
+TAG_DISK     char label[8]; uint16_t disk_max; uint8_t disk_usage; char disk_unit[4]
+             (repeated once per disk, legacy_sizes only)
+TAG_NETWORK  uint16_t rx_rate; char rx_unit[4]; uint16_t tx_rate; char tx_unit[4]
+TAG_BATTERY  uint8_t level; uint8_t charging; uint16_t time_remaining
+TAG_HISTORY  uint8_t series; uint8_t values[]  // 0 cpu, 1 ram, 2 gpu, 3 vram, oldest first
+             (repeated once per series, only every few packets)
+TAG_ALERT    uint8_t metric; uint8_t active; uint8_t value; uint8_t threshold
+             (same layout as the v1 AlertPacket, only sent when an alert changes)
+TAG_LOAD     uint16_t load[3]                 // 1, 5 and 15 minute load average x100, Unix only
+TAG_CPU_FREQ uint16_t freq_avg; uint16_t freq_max  // MHz, all cores averaged and fastest core
+TAG_UPTIME   uint32_t uptime                   // seconds since boot
+TAG_PROCESS  char name[16]; uint8_t cpu_usage; uint8_t ram_usage
+             (repeated once per process, busiest first)
+TAG_FAN      char label[8]; uint16_t rpm
+             (repeated once per fan)
+TAG_TEMP     char label[16]; uint8_t temp     // degrees in TAG_TEMP_UNIT
+             (repeated once per sensor)
+TAG_SENSOR   char label[16]; float value; char unit[4]  // HWiNFO (Windows), SNMP and IPMI readings
+             (repeated once per reading)
+TAG_SMART    char label[8]; uint8_t temp; uint8_t status  // 0 OK, 1 WARN
+             (repeated once per drive)
+TAG_UPS      uint8_t charge; uint8_t load; uint8_t on_battery  // percent, 0xFF if unknown
+TAG_RPI      uint8_t temp; uint32_t throttled; uint16_t core_voltage; uint16_t gpu_mem
+             (Raspberry Pi only, same layout as the v1 RpiPacket)
+TAG_POWER    uint16_t cpu_power; uint16_t gpu_power  // watts x10, 0xFFFF if unknown
+TAG_CLUSTER  char name[8]; uint8_t usage     // Apple Silicon CPU cluster active residency
+             (repeated once per cluster)
+TAG_GPU_POWER uint16_t gpu_power              // watts x10, 0xFFFF if unknown
+TAG_GPU_CLOCK uint16_t gpu_clock; uint16_t vram_clock  // MHz, 0xFFFF if unknown
+TAG_GPU_FAN  uint8_t gpu_fan                  // percent, 0xFF if unknown
+TAG_GPU_CODEC uint8_t encoder; uint8_t decoder  // NVENC/NVDEC or QuickSync load, 0xFF if unknown
+TAG_GPU_PROCESS char name[16]; uint16_t vram_used  // MB over all NVIDIA GPUs
+             (repeated once per process, most VRAM first)
+TAG_MEMORY   uint32_t ram_used; uint32_t ram_total; uint32_t vram_used; uint32_t vram_total
+             (MiB, VRAM 0xFFFFFFFF if unknown)
+
+The *_WIDE tags replace the value x10 plus unit string sizes above, which top out at 6553.5
+of a unit. Sizes are a mantissa and exponent, bytes = max * 1024^exp, exp 0xFF if unknown.
+
+TAG_RAM_WIDE  uint8_t ram_usage; uint32_t ram_max; uint8_t ram_exp
+TAG_GPU_WIDE  uint8_t gpu_usage; uint8_t vram_usage; uint32_t vram_max; uint8_t vram_exp
+TAG_DISK_WIDE char label[8]; uint8_t disk_usage; uint32_t disk_max; uint8_t disk_exp
+              (repeated once per disk)
+*/
+
+pub const PACKET_MAGIC: u8 = 0xFD;
+pub const VERSION: u8 = 2;
+
+pub const TAG_CPU: u8 = 0x01;
+pub const TAG_RAM: u8 = 0x02;
+pub const TAG_GPU: u8 = 0x03;
+pub const TAG_GPU_TEMP: u8 = 0x04;
+pub const TAG_CORES: u8 = 0x05;
+pub const TAG_DISK: u8 = 0x06;
+pub const TAG_NETWORK: u8 = 0x07;
+pub const TAG_BATTERY: u8 = 0x08;
+pub const TAG_HISTORY: u8 = 0x09;
+pub const TAG_ALERT: u8 = 0x0A;
+pub const TAG_LOAD: u8 = 0x0B;
+pub const TAG_CPU_FREQ: u8 = 0x0C;
+pub const TAG_UPTIME: u8 = 0x0D;
+pub const TAG_PROCESS: u8 = 0x0E;
+pub const TAG_FAN: u8 = 0x0F;
+pub const TAG_TEMP: u8 = 0x10;
+pub const TAG_SENSOR: u8 = 0x11;
+pub const TAG_SMART: u8 = 0x12;
+pub const TAG_UPS: u8 = 0x13;
+pub const TAG_RPI: u8 = 0x14;
+pub const TAG_POWER: u8 = 0x15;
+pub const TAG_CLUSTER: u8 = 0x16;
+pub const TAG_GPU_POWER: u8 = 0x17;
+pub const TAG_GPU_CLOCK: u8 = 0x18;
+pub const TAG_GPU_FAN: u8 = 0x19;
+pub const TAG_GPU_CODEC: u8 = 0x1A;
+pub const TAG_GPU_PROCESS: u8 = 0x1B;
+pub const TAG_MEMORY: u8 = 0x1C;
+pub const TAG_RAM_WIDE: u8 = 0x1D;
+pub const TAG_GPU_WIDE: u8 = 0x1E;
+pub const TAG_DISK_WIDE: u8 = 0x1F;
+pub const TAG_VALID: u8 = 0x20;
+pub const TAG_TEMP_UNIT: u8 = 0x21;
+pub const TAG_DELTA: u8 = 0x22;
+pub const TAG_GOODBYE: u8 = 0x23;
+pub const TAG_HOST: u8 = 0x24;
+
+const UNKNOWN_SIZE: (u32, u8) = (u32::MAX, u8::MAX);
+
+// Smallest power of 1024 that fits the size into a u32 (rounded half up), exact below 4 GiB
+pub fn wide_size(bytes: u64) -> (u32, u8) {
+    let mut mantissa = bytes;
+    let mut exp = 0;
+    while mantissa > u32::MAX as u64 {
+        mantissa = mantissa / 1024 + u64::from(mantissa % 1024 >= 512);
+        exp += 1;
+    }
+    (mantissa as u32, exp)
+}
+
+// vram_total_mib as a wide size, u32::MAX there means unknown
+fn vram_size(total_mib: u32) -> (u32, u8) {
+    match total_mib {
+        u32::MAX => UNKNOWN_SIZE,
+        mib => wide_size((mib as u64) << 20),
+    }
+}
+
+pub struct TlvWriter {
+    packet: Vec<u8>,
+}
+
+impl TlvWriter {
+    pub fn new() -> Self {
+        TlvWriter {
+            packet: vec![PACKET_MAGIC, VERSION],
+        }
+    }
+
+    pub fn raw(&mut self, tag: u8, value: &[u8]) {
+        // Fields have a single length byte, anything longer is a bug in the encoder
+        let Ok(length) = u8::try_from(value.len()) else {
+            return tracing::warn!(tag, length = value.len(), "Dropping oversized TLV field");
+        };
+
+        self.packet.push(tag);
+        self.packet.push(length);
+        self.packet.extend_from_slice(value);
+    }
+
+    pub fn field<T: Serialize + ?Sized>(&mut self, tag: u8, value: &T) {
+        self.raw(tag, &bincode::serialize(value).unwrap());
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.packet
+    }
+}
+
+impl Default for TlvWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn encode_goodbye() -> Vec<u8> {
+    let mut writer = TlvWriter::new();
+    writer.raw(TAG_GOODBYE, &[]);
+    writer.finish()
+}
+
+pub fn encode_sample(
+    sample: &Sample,
+    max_cores: u8,
+    history: Option<&History>,
+    alerts: &[Alert],
+    legacy_sizes: bool,
+    temp_unit: TempUnit,
+    host: Option<&HostHeader>,
+) -> Vec<u8> {
+    let mut writer = TlvWriter::new();
+    writer.field(TAG_TEMP_UNIT, &temp_unit.symbol());
+    if let Some(host) = host {
+        writer.field(TAG_HOST, host);
+    }
+
+    if let Some(system) = &sample.system {
+        writer.field(TAG_VALID, &system.valid);
+        writer.field(TAG_CPU, &system.cpu_usage);
+
+        let (ram_max, ram_exp) = wide_size((system.ram_total_mib as u64) << 20);
+        writer.field(TAG_RAM_WIDE, &(system.ram_usage, ram_max, ram_exp));
+        let (vram_max, vram_exp) = vram_size(system.vram_total_mib);
+        writer.field(
+            TAG_GPU_WIDE,
+            &(system.gpu_usage, system.vram_usage, vram_max, vram_exp),
+        );
+
+        if legacy_sizes {
+            writer.field(
+                TAG_RAM,
+                &(system.ram_max, system.ram_usage, system.ram_unit),
+            );
+            writer.field(
+                TAG_GPU,
+                &(
+                    system.gpu_usage,
+                    system.vram_max,
+                    system.vram_usage,
+                    system.vram_unit,
+                ),
+            );
+        }
+        writer.field(TAG_GPU_TEMP, &system.gpu_temp);
+        writer.field(TAG_GPU_POWER, &system.gpu_power);
+        writer.field(TAG_GPU_CLOCK, &(system.gpu_clock, system.vram_clock));
+        writer.field(TAG_GPU_FAN, &system.gpu_fan);
+        writer.field(TAG_GPU_CODEC, &(system.gpu_encoder, system.gpu_decoder));
+        writer.field(TAG_CPU_FREQ, &(system.cpu_freq_avg, system.cpu_freq_max));
+        writer.field(TAG_UPTIME, &system.uptime);
+        writer.field(
+            TAG_MEMORY,
+            &(
+                system.ram_used_mib,
+                system.ram_total_mib,
+                system.vram_used_mib,
+                system.vram_total_mib,
+            ),
+        );
+
+        if max_cores > 0 {
+            let cores = system.core_usage.len().min(max_cores as usize);
+            writer.raw(TAG_CORES, &system.core_usage[..cores]);
+        }
+
+        if let Some(load_average) = &system.load_average {
+            writer.field(TAG_LOAD, load_average);
+        }
+    }
+
+    for disk in sample.disks.iter().flatten() {
+        let (disk_max, disk_exp) = wide_size(disk.disk_bytes);
+        writer.field(
+            TAG_DISK_WIDE,
+            &(disk.label, disk.disk_usage, disk_max, disk_exp),
+        );
+        if legacy_sizes {
+            writer.field(TAG_DISK, disk);
+        }
+    }
+
+    if let Some(network) = &sample.network {
+        writer.field(TAG_NETWORK, &network.wire());
+    }
+
+    if let Some(battery) = &sample.battery {
+        writer.field(TAG_BATTERY, battery);
+    }
+
+    for process in sample.processes.iter().flatten() {
+        writer.field(TAG_PROCESS, process);
+    }
+
+    for process in sample.gpu_processes.iter().flatten() {
+        writer.field(TAG_GPU_PROCESS, process);
+    }
+
+    for fan in sample.fans.iter().flatten() {
+        writer.field(TAG_FAN, fan);
+    }
+
+    for temp in sample.temps.iter().flatten() {
+        writer.field(TAG_TEMP, temp);
+    }
+
+    for drive in sample.smart.iter().flatten() {
+        writer.field(TAG_SMART, drive);
+    }
+
+    if let Some(ups) = &sample.ups {
+        writer.field(TAG_UPS, ups);
+    }
+
+    if let Some(power) = &sample.power {
+        writer.field(TAG_POWER, &(power.cpu_power, power.gpu_power));
+        for cluster in &power.clusters {
+            writer.field(TAG_CLUSTER, cluster);
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    if let Some(rpi) = &sample.rpi {
+        writer.field(TAG_RPI, rpi);
+    }
+
+    for sensor in sample.sensors.iter().flatten() {
+        writer.field(TAG_SENSOR, sensor);
+    }
+
+    for (series, values) in history.iter().flat_map(|h| h.iter()) {
+        let mut value = vec![series as u8];
+        value.extend(values);
+        writer.raw(TAG_HISTORY, &value);
+    }
+
+    for alert in alerts {
+        writer.raw(
+            TAG_ALERT,
+            &[
+                alert.kind as u8,
+                alert.active as u8,
+                alert.value,
+                alert.threshold,
+            ],
+        );
+    }
+
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collectors::disk_info::DiskInfo;
+    use crate::collectors::network_info::NetworkInfo;
+
+    #[test]
+    fn fields_are_tag_length_value() {
+        let mut writer = TlvWriter::new();
+        writer.raw(TAG_CPU, &[42]);
+        writer.field(TAG_BATTERY, &(80u8, 1u8, 3600u16));
+        writer.raw(TAG_CORES, &[]);
+        assert_eq!(
+            writer.finish(),
+            vec![
+                PACKET_MAGIC,
+                VERSION,
+                TAG_CPU,
+                1,
+                42,
+                TAG_BATTERY,
+                4,
+                80,
+                1,
+                0x10,
+                0x0E,
+                TAG_CORES,
+                0,
+            ]
+        );
+    }
+
+    #[test]
+    fn fields_longer_than_255_bytes_are_dropped() {
+        let mut writer = TlvWriter::new();
+        writer.raw(TAG_CORES, &[7; 256]);
+        assert_eq!(writer.finish(), vec![PACKET_MAGIC, VERSION]);
+
+        let mut writer = TlvWriter::new();
+        writer.raw(TAG_CORES, &[7; 255]);
+        let packet = writer.finish();
+        assert_eq!(packet.len(), 2 + 2 + 255);
+        assert_eq!(packet[2..4], [TAG_CORES, 255]);
+    }
+
+    #[test]
+    fn wide_and_unknown_sizes() {
+        assert_eq!(wide_size(0), (0, 0));
+        assert_eq!(wide_size(u32::MAX as u64), (u32::MAX, 0));
+        assert_eq!(wide_size(4 << 30), (4 << 20, 1));
+        assert_eq!(wide_size(u64::MAX), (16 << 20, 4));
+        assert_eq!(vram_size(8192), (8192 << 10, 1));
+        assert_eq!(vram_size(u32::MAX), (u32::MAX, u8::MAX));
+    }
+
+    #[test]
+    fn disks_and_network_layout() {
+        let sample = Sample {
+            disks: Some(vec![DiskInfo {
+                label: *b"/\0\0\0\0\0\0\0",
+                disk_max: 5000,
+                disk_usage: 40,
+                disk_unit: *b"GB\0\0",
+                disk_bytes: 500 << 30,
+            }]),
+            network: Some(NetworkInfo {
+                rx_rate: 15,
+                rx_unit: *b"MB\0\0",
+                tx_rate: 3,
+                tx_unit: *b"KB\0\0",
+                rx_bytes: 1_500_000,
+                tx_bytes: 3_000,
+            }),
+            ..Default::default()
+        };
+        let packet = encode_sample(&sample, 0, None, &[], false, TempUnit::Celsius);
+
+        let disk = [TAG_DISK_WIDE, 14, b'/', 0, 0, 0, 0, 0, 0, 0, 40];
+        let at = packet.windows(disk.len()).position(|w| w == disk).unwrap();
+        assert_eq!(
+            packet[at + disk.len()..at + disk.len() + 5],
+            [0x00, 0x00, 0x40, 0x1F, 1]
+        );
+
+        let network = [
+            TAG_NETWORK,
+            12,
+            15,
+            0,
+            b'M',
+            b'B',
+            0,
+            0,
+            3,
+            0,
+            b'K',
+            b'B',
+            0,
+            0,
+        ];
+        assert!(packet.windows(network.len()).any(|w| w == network));
+    }
+}
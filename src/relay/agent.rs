@@ -0,0 +1,138 @@
+use super::hub::read_message;
+use super::{mdns, AgentConfig, Hello, Proof, Update, DEFAULT_PORT};
+use crate::collectors::Sample;
+use crate::helpers::with_default_port;
+use crate::transport::backoff::Backoff;
+use std::net::SocketAddr;
+use tokio::io::{AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+
+pub(super) fn line<T: serde::Serialize>(message: &T) -> Vec<u8> {
+    let mut line = serde_json::to_vec(message).unwrap();
+    line.push(b'\n');
+    line
+}
+
+fn hello(config: &AgentConfig) -> Hello {
+    Hello {
+        host: config
+            .name
+            .clone()
+            .or_else(sysinfo::System::host_name)
+            .unwrap_or_else(|| "agent".to_owned()),
+        token: config.token.clone(),
+        challenge: None,
+        proof: None,
+    }
+}
+
+// Forwards every sample to the hub, reconnecting whenever it goes away
+pub async fn run(config: AgentConfig, mut samples: broadcast::Receiver<Sample>) {
+    let address = with_default_port(&config.hub, DEFAULT_PORT);
+    let hello = hello(&config);
+    tracing::info!(hub = %address, host = %hello.host, "Forwarding samples to hub");
+
+    let mut backoff = Backoff::new();
+    loop {
+        match forward(&address, &hello, &mut samples, &mut backoff).await {
+            Ok(()) => return,
+            Err(e) => tracing::warn!(hub = %address, "Lost connection to hub: {e}"),
+        }
+        backoff.wait().await;
+    }
+}
+
+// Without a hub address the agent advertises itself over mDNS and waits for hubs to dial in
+pub async fn serve(config: AgentConfig, samples: broadcast::Receiver<Sample>) {
+    let listener = match TcpListener::bind(&config.listen).await {
+        Ok(listener) => listener,
+        Err(e) => return tracing::error!(address = %config.listen, "Failed to start agent: {e}"),
+    };
+    let hello = hello(&config);
+    let port = listener.local_addr().map_or(0, |address| address.port());
+    let _daemon = mdns::advertise(&hello.host, port);
+    tracing::info!(address = %config.listen, host = %hello.host, "Waiting for hubs");
+
+    loop {
+        match listener.accept().await {
+            Ok((stream, peer)) => {
+                tokio::spawn(answer(stream, peer, hello.clone(), samples.resubscribe()));
+            }
+            Err(e) => tracing::warn!("Failed to accept hub: {e}"),
+        }
+    }
+}
+
+// A dialing hub speaks first, so stats only go to hubs that prove they know the token
+async fn answer(
+    stream: TcpStream,
+    peer: SocketAddr,
+    hello: Hello,
+    mut samples: broadcast::Receiver<Sample>,
+) {
+    let mut reader = BufReader::new(stream);
+    let Some(hub) = read_message::<Hello>(&mut reader).await else {
+        return tracing::debug!(%peer, "Hub hung up before saying hello");
+    };
+
+    // Proves the token without sending it, the hub found us over mDNS and could be anyone
+    let challenge = hello.token.as_ref().map(|_| mdns::challenge());
+    let reply = Hello {
+        host: hello.host.clone(),
+        token: None,
+        proof: hello
+            .token
+            .as_deref()
+            .zip(hub.challenge.as_deref())
+            .map(|(token, challenge)| mdns::prove(token, mdns::AGENT, challenge)),
+        challenge: challenge.clone(),
+    };
+    if let Err(e) = reader.get_mut().write_all(&line(&reply)).await {
+        return tracing::info!(%peer, hub = %hub.host, "Hub disconnected: {e}");
+    }
+
+    if let Some((token, challenge)) = hello.token.as_deref().zip(challenge) {
+        let proven = read_message::<Proof>(&mut reader)
+            .await
+            .is_some_and(|proof| mdns::verify(token, mdns::HUB, &challenge, &proof.proof));
+        if !proven {
+            return tracing::warn!(%peer, hub = %hub.host, "Hub doesn't know the token");
+        }
+    }
+
+    tracing::info!(%peer, hub = %hub.host, "Hub connected");
+    if let Err(e) = send_samples(reader.get_mut(), &mut samples).await {
+        tracing::info!(%peer, hub = %hub.host, "Hub disconnected: {e}");
+    }
+}
+
+// Ok once there are no more samples to forward
+async fn forward(
+    address: &str,
+    hello: &Hello,
+    samples: &mut broadcast::Receiver<Sample>,
+    backoff: &mut Backoff,
+) -> std::io::Result<()> {
+    let mut stream = TcpStream::connect(address).await?;
+    tracing::info!(hub = %address, "Connected to hub");
+    backoff.reset();
+    stream.write_all(&line(hello)).await?;
+    send_samples(&mut stream, samples).await
+}
+
+async fn send_samples(
+    stream: &mut TcpStream,
+    samples: &mut broadcast::Receiver<Sample>,
+) -> std::io::Result<()> {
+    // Whatever queued up while disconnected is stale by now
+    *samples = samples.resubscribe();
+    loop {
+        let sample = match samples.recv().await {
+            Ok(sample) => sample,
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => return Ok(()),
+        };
+        stream.write_all(&line(&Update::new(sample))).await?;
+    }
+}
@@ -0,0 +1,81 @@
+use super::{mdns, Hello, Hub, HubConfig, Update};
+use serde::de::DeserializeOwned;
+use std::net::SocketAddr;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+// Far more than a sample needs, keeps a misbehaving client from using up memory
+const MAX_LINE: u64 = 1024 * 1024;
+
+pub async fn listen(config: HubConfig, hub: Hub) {
+    if config.discover {
+        tokio::spawn(mdns::browse(config.token.clone(), hub.clone()));
+    }
+
+    let listener = match TcpListener::bind(&config.listen).await {
+        Ok(listener) => listener,
+        Err(e) => return tracing::error!(address = %config.listen, "Failed to start hub: {e}"),
+    };
+    tracing::info!(address = %config.listen, "Waiting for agents");
+
+    loop {
+        match listener.accept().await {
+            Ok((stream, peer)) => {
+                tokio::spawn(receive(stream, peer, config.token.clone(), hub.clone()));
+            }
+            Err(e) => tracing::warn!("Failed to accept agent: {e}"),
+        }
+    }
+}
+
+pub(super) async fn receive(stream: TcpStream, peer: SocketAddr, token: Option<String>, hub: Hub) {
+    let mut reader = BufReader::new(stream);
+    let Some(hello) = read_message::<Hello>(&mut reader).await else {
+        return tracing::debug!(%peer, "Agent hung up before saying hello");
+    };
+    if let Some(token) = &token {
+        let sent = hello.token.as_deref().unwrap_or_default();
+        if !mdns::constant_time_eq(sent.as_bytes(), token.as_bytes()) {
+            return tracing::warn!(%peer, host = %hello.host, "Agent sent the wrong token");
+        }
+    }
+
+    receive_updates(reader, peer, &hello.host, &hub).await;
+}
+
+// Everything after the hello, for agents dialing in and agents found over mDNS alike
+pub(super) async fn receive_updates(
+    mut reader: BufReader<TcpStream>,
+    peer: SocketAddr,
+    host: &str,
+    hub: &Hub,
+) {
+    tracing::info!(%peer, host, "Agent connected");
+    while let Some(update) = read_message::<Update>(&mut reader).await {
+        hub.update(host, update.into_sample());
+    }
+    tracing::info!(%peer, host, "Agent disconnected");
+}
+
+// None once the other end hangs up or sends something unreadable
+pub(super) async fn read_message<T: DeserializeOwned>(
+    reader: &mut BufReader<TcpStream>,
+) -> Option<T> {
+    let mut line = String::new();
+    let read = (&mut *reader)
+        .take(MAX_LINE)
+        .read_line(&mut line)
+        .await
+        .ok()?;
+    if read == 0 || !line.ends_with('\n') {
+        return None;
+    }
+
+    match serde_json::from_str(&line) {
+        Ok(message) => Some(message),
+        Err(e) => {
+            tracing::warn!("Unreadable relay message: {e}");
+            None
+        }
+    }
+}
@@ -0,0 +1,228 @@
+use super::agent::line;
+use super::hub::read_message;
+use super::{hub, Hello, Hub, Proof};
+use crate::transport::backoff::Backoff;
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use tokio::io::{AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::task::JoinHandle;
+
+// Agents without a hub address advertise this, hubs with `discover` browse for it
+pub const SERVICE_TYPE: &str = "_pc-monitor-agent._tcp.local.";
+
+// Who is proving, so a proof can't just be sent back to the side that asked for it
+pub const AGENT: &[u8] = b"agent";
+pub const HUB: &[u8] = b"hub";
+
+// Keeps answering queries until the returned daemon is dropped
+pub fn advertise(name: &str, port: u16) -> Option<ServiceDaemon> {
+    let register = || {
+        let daemon = ServiceDaemon::new()?;
+        let host = format!("{}.local.", name.replace([' ', '.'], "-"));
+        let info = ServiceInfo::new(SERVICE_TYPE, name, &host, "", port, &[("name", name)][..])?
+            .enable_addr_auto();
+        daemon.register(info)?;
+        Ok::<_, mdns_sd::Error>(daemon)
+    };
+
+    match register() {
+        Ok(daemon) => {
+            tracing::info!(name, port, "Advertising agent over mDNS");
+            Some(daemon)
+        }
+        Err(e) => {
+            tracing::warn!("Failed to advertise agent over mDNS: {e}");
+            None
+        }
+    }
+}
+
+// Dials every agent that shows up and keeps the connection while it's advertised
+pub async fn browse(token: Option<String>, hub: Hub) {
+    let browse = ServiceDaemon::new().and_then(|daemon| {
+        let events = daemon.browse(SERVICE_TYPE)?;
+        Ok((daemon, events))
+    });
+    let (_daemon, events) = match browse {
+        Ok(browse) => browse,
+        Err(e) => return tracing::error!("Failed to browse for agents over mDNS: {e}"),
+    };
+    tracing::info!("Looking for agents over mDNS");
+
+    let mut agents: HashMap<String, JoinHandle<()>> = HashMap::new();
+    while let Ok(event) = events.recv_async().await {
+        match event {
+            ServiceEvent::ServiceResolved(info) => {
+                let Some(ip) = info.get_addresses().iter().next() else {
+                    continue;
+                };
+                let address = SocketAddr::new((*ip).into(), info.get_port());
+                let name = info.get_fullname().to_owned();
+                if agents.get(&name).is_some_and(|task| !task.is_finished()) {
+                    continue;
+                }
+
+                tracing::info!(agent = %name, %address, "Found agent");
+                let task = tokio::spawn(dial(address, token.clone(), hub.clone()));
+                agents.insert(name, task);
+            }
+            ServiceEvent::ServiceRemoved(_, name) => {
+                if let Some(task) = agents.remove(&name) {
+                    tracing::info!(agent = %name, "Agent stopped advertising");
+                    task.abort();
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// The hub says hello first here. Whatever answers could be anyone advertising the service,
+// so the token never goes out, see the handshake in mod.rs
+async fn dial(address: SocketAddr, token: Option<String>, hub: Hub) {
+    let mut backoff = Backoff::new();
+    loop {
+        match TcpStream::connect(address).await {
+            Ok(stream) => {
+                backoff.reset();
+                if let Err(e) = handshake(stream, address, token.as_deref(), &hub).await {
+                    tracing::warn!(%address, "Lost connection to agent: {e}");
+                }
+            }
+            Err(e) => tracing::warn!(%address, "Failed to connect to agent: {e}"),
+        }
+        backoff.wait().await;
+    }
+}
+
+async fn handshake(
+    stream: TcpStream,
+    address: SocketAddr,
+    token: Option<&str>,
+    hub: &Hub,
+) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream);
+    let challenge = challenge();
+    let hello = Hello {
+        host: hub.local.clone(),
+        token: None,
+        challenge: Some(challenge.clone()),
+        proof: None,
+    };
+    reader.get_mut().write_all(&line(&hello)).await?;
+
+    let Some(agent) = read_message::<Hello>(&mut reader).await else {
+        tracing::debug!(%address, "Agent hung up before saying hello");
+        return Ok(());
+    };
+    if let Some(token) = token {
+        let proven = agent
+            .proof
+            .as_deref()
+            .is_some_and(|proof| verify(token, AGENT, &challenge, proof));
+        if !proven {
+            tracing::warn!(%address, host = %agent.host, "Agent doesn't know the token");
+            return Ok(());
+        }
+    }
+    if let Some(challenge) = &agent.challenge {
+        let Some(token) = token else {
+            tracing::warn!(%address, host = %agent.host, "Agent wants a token, set `hub.token`");
+            return Ok(());
+        };
+        let proof = Proof {
+            proof: prove(token, HUB, challenge),
+        };
+        reader.get_mut().write_all(&line(&proof)).await?;
+    }
+
+    hub::receive_updates(reader, address, &agent.host, hub).await;
+    Ok(())
+}
+
+pub(super) fn challenge() -> String {
+    let mut challenge = [0; 16];
+    OsRng.fill_bytes(&mut challenge);
+    hex(&challenge)
+}
+
+pub(super) fn prove(token: &str, side: &[u8], challenge: &str) -> String {
+    hex(&hmac(token.as_bytes(), &[side, challenge.as_bytes()]))
+}
+
+pub(super) fn verify(token: &str, side: &[u8], challenge: &str, proof: &str) -> bool {
+    constant_time_eq(prove(token, side, challenge).as_bytes(), proof.as_bytes())
+}
+
+// Same time however much of it matches
+pub(super) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+// HMAC-SHA256 (RFC 2104)
+fn hmac(key: &[u8], message: &[&[u8]]) -> [u8; 32] {
+    let mut block = [0; 64];
+    if key.len() > block.len() {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+
+    let mut inner = Sha256::new().chain_update(block.map(|byte| byte ^ 0x36));
+    for part in message {
+        inner.update(part);
+    }
+    Sha256::new()
+        .chain_update(block.map(|byte| byte ^ 0x5C))
+        .chain_update(inner.finalize())
+        .finalize()
+        .into()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hmac_matches_rfc_4231() {
+        assert_eq!(
+            hex(&hmac(b"Jefe", &[b"what do ya want ", b"for nothing?"])),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        // Keys longer than a block are hashed first
+        assert_eq!(
+            hex(&hmac(
+                &[0xAA; 131],
+                &[b"Test Using Larger Than Block-Size Key - Hash Key First"]
+            )),
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+        );
+    }
+
+    #[test]
+    fn proofs_need_the_token_side_and_challenge() {
+        let challenge = challenge();
+        let proof = prove("hunter2", AGENT, &challenge);
+        assert!(verify("hunter2", AGENT, &challenge, &proof));
+        assert!(!verify("hunter3", AGENT, &challenge, &proof));
+        assert!(!verify("hunter2", HUB, &challenge, &proof));
+        assert!(!verify("hunter2", AGENT, &super::challenge(), &proof));
+        assert!(!verify("hunter2", AGENT, &challenge, &proof[1..]));
+        assert!(!verify("hunter2", AGENT, &challenge, ""));
+    }
+
+    #[test]
+    fn challenges_dont_repeat() {
+        assert_eq!(challenge().len(), 32);
+        assert_ne!(challenge(), challenge());
+    }
+}
@@ -0,0 +1,247 @@
+use crate::collectors::Sample;
+use crate::helpers::pop_16u8;
+use crate::protocol::HostHeader;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+pub mod agent;
+pub mod hub;
+pub mod mdns;
+pub mod ssh;
+
+/*
+Agent to hub protocol, newline delimited JSON over TCP. The agent opens with
+
+{"host": "server", "token": "..."}
+
+and then sends one line per collected sample:
+
+{"sample": {...}, "disk_bytes": [...]}
+
+The hub never answers, it just hangs up on agents with the wrong token. The token travels
+as plain text over plain TCP, so it keeps out strays on a trusted network and nothing more.
+
+Agents without a hub address turn this around: they listen on `agent.listen`, advertise
+themselves over mDNS (see mdns.rs) and hubs with `discover` dial them. Anything can advertise
+itself, so there the token is never sent, both ends prove they know it instead:
+
+hub   {"host": "desktop", "token": null, "challenge": "<hex>"}
+agent {"host": "server", "token": null, "challenge": "<hex>", "proof": "<hex>"}
+hub   {"proof": "<hex>"}
+
+proof = HMAC-SHA256(token, "agent" or "hub" || the other side's challenge), hex encoded. Each
+side hangs up on a wrong or missing proof when it has a token set, the agent only sends its
+challenge then, and the hub only answers one. After that the agent goes on with its samples.
+*/
+
+pub const DEFAULT_PORT: u16 = 8701;
+pub const AGENT_PORT: u16 = 8702;
+// An agent that stopped sending this long ago is treated as gone
+const STALE_AFTER: Duration = Duration::from_secs(15);
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct AgentConfig {
+    // host:port of the hub, empty to wait for hubs that find this agent over mDNS
+    pub hub: String,
+    // Where hubs dial in when `hub` is empty
+    pub listen: String,
+    // Shown on the hub, the hostname by default
+    pub name: Option<String>,
+    // Has to match the hub's, sent in plain text to `hub`, only proven to hubs dialing in
+    pub token: Option<String>,
+}
+
+impl Default for AgentConfig {
+    fn default() -> Self {
+        AgentConfig {
+            hub: String::new(),
+            listen: format!("0.0.0.0:{AGENT_PORT}"),
+            name: None,
+            token: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct HubConfig {
+    pub listen: String,
+    pub token: Option<String>,
+    // Agent whose stats go to the Flipper until a button press, the first one sending when unset
+    pub show: Option<String>,
+    // Seconds each host (this one included) stays on the Flipper before the next, 0 only
+    // switches on button presses
+    pub cycle: u64,
+    // Connect to agents advertising themselves over mDNS
+    pub discover: bool,
+}
+
+impl Default for HubConfig {
+    fn default() -> Self {
+        HubConfig {
+            listen: format!("0.0.0.0:{DEFAULT_PORT}"),
+            token: None,
+            show: None,
+            cycle: 0,
+            discover: true,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Hello {
+    host: String,
+    token: Option<String>,
+    // Only on connections a hub dialed, see mdns.rs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    challenge: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    proof: Option<String>,
+}
+
+// The hub's answer to an agent's challenge
+#[derive(Serialize, Deserialize, Debug)]
+struct Proof {
+    proof: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Update {
+    sample: Sample,
+    // Left out of Sample's JSON, but the v2 wide fields need them
+    disk_bytes: Vec<u64>,
+}
+
+impl Update {
+    fn new(sample: Sample) -> Self {
+        Update {
+            disk_bytes: sample
+                .disks
+                .iter()
+                .flatten()
+                .map(|disk| disk.disk_bytes)
+                .collect(),
+            sample,
+        }
+    }
+
+    fn into_sample(self) -> Sample {
+        let mut sample = self.sample;
+        for (disk, bytes) in sample.disks.iter_mut().flatten().zip(self.disk_bytes) {
+            disk.disk_bytes = bytes;
+        }
+        sample
+    }
+}
+
+// Latest sample of every agent and SSH host, read by the collector
+#[derive(Clone)]
+pub struct Hub {
+    hosts: Arc<RwLock<BTreeMap<String, (Sample, Instant)>>>,
+    // Page 0, shown with the collector's own sample
+    local: String,
+    show: Option<String>,
+    cycle: u64,
+    paging: Arc<Mutex<Paging>>,
+}
+
+struct Paging {
+    // None shows `show` or the first agent, until a button press (or `cycle`) picks a page
+    page: Option<usize>,
+    since: Instant,
+}
+
+// Host the Flipper shows right now, `sample` is None for this machine
+pub struct Page {
+    pub header: HostHeader,
+    pub sample: Option<Sample>,
+}
+
+impl Hub {
+    pub fn new(config: &HubConfig) -> Self {
+        Hub {
+            hosts: Arc::new(RwLock::new(BTreeMap::new())),
+            local: sysinfo::System::host_name().unwrap_or_else(|| "localhost".to_owned()),
+            show: config.show.clone(),
+            cycle: config.cycle,
+            paging: Arc::new(Mutex::new(Paging {
+                page: (config.show.is_none() && config.cycle > 0).then_some(0),
+                since: Instant::now(),
+            })),
+        }
+    }
+
+    fn update(&self, host: &str, sample: Sample) {
+        self.hosts
+            .write()
+            .unwrap()
+            .insert(host.to_owned(), (sample, Instant::now()));
+    }
+
+    // Agents still sending, in name order
+    fn fresh(&self) -> Vec<(String, Sample)> {
+        let hosts = self.hosts.read().unwrap();
+        hosts
+            .iter()
+            .filter(|(_, (_, updated))| updated.elapsed() < STALE_AFTER)
+            .map(|(host, (sample, _))| (host.clone(), sample.clone()))
+            .collect()
+    }
+
+    // Button press on the Flipper, pages wrap around and `cycle` waits a full period again
+    pub fn switch(&self, step: i8) {
+        let fresh = self.fresh();
+        let pages = fresh.len() as i64 + 1;
+        let mut paging = self.paging.lock().unwrap();
+        let current = paging.page.unwrap_or_else(|| self.default_page(&fresh));
+        paging.page = Some((current as i64 + step as i64).rem_euclid(pages) as usize);
+        paging.since = Instant::now();
+        tracing::info!(page = paging.page, pages, "Switched host");
+    }
+
+    pub fn page(&self) -> Page {
+        let mut fresh = self.fresh();
+        let mut paging = self.paging.lock().unwrap();
+        if let Some(page) = &mut paging.page {
+            if self.cycle > 0 && paging.since.elapsed().as_secs() >= self.cycle {
+                *page += 1;
+                paging.since = Instant::now();
+            }
+        }
+
+        // Agents going stale shift the pages after them, so an index past the end wraps
+        let page_count = fresh.len() + 1;
+        let page = paging
+            .page
+            .map_or_else(|| self.default_page(&fresh), |page| page % page_count);
+        let (name, sample) = match page.checked_sub(1) {
+            Some(index) => {
+                let (name, sample) = fresh.swap_remove(index);
+                (name, Some(sample))
+            }
+            None => (self.local.clone(), None),
+        };
+        Page {
+            header: HostHeader {
+                page: page.min(u8::MAX as usize) as u8,
+                page_count: page_count.min(u8::MAX as usize) as u8,
+                name: pop_16u8(name.as_bytes()),
+            },
+            sample,
+        }
+    }
+
+    // `show` while it's sending, otherwise the first agent, otherwise this machine
+    fn default_page(&self, fresh: &[(String, Sample)]) -> usize {
+        match &self.show {
+            Some(show) => fresh
+                .iter()
+                .position(|(host, _)| host == show)
+                .map_or(0, |i| i + 1),
+            None => usize::from(!fresh.is_empty()),
+        }
+    }
+}
@@ -0,0 +1,410 @@
+use super::Hub;
+use crate::collectors::disk_info::DiskInfo;
+use crate::collectors::network_info::NetworkInfo;
+use crate::collectors::system_info::SystemInfo;
+use crate::collectors::Sample;
+use crate::helpers::{percent, pop_4u8, pop_8u8};
+use crate::transport::backoff::Backoff;
+use crate::units::Units;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::process::Stdio;
+use std::time::Instant;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct SshConfig {
+    // Destination as passed to ssh, e.g. "admin@nas" or a Host from ~/.ssh/config
+    pub host: String,
+    // Shown for this host, `host` by default
+    pub name: Option<String>,
+    // Extra ssh arguments, e.g. ["-p", "2222", "-i", "~/.ssh/monitor"]
+    pub options: Vec<String>,
+    // Seconds between samples
+    pub interval: u64,
+    pub disks: Vec<String>,
+}
+
+impl Default for SshConfig {
+    fn default() -> Self {
+        SshConfig {
+            host: String::new(),
+            name: None,
+            options: Vec::new(),
+            interval: 5,
+            disks: vec!["/".to_owned()],
+        }
+    }
+}
+
+// Single quoted for the remote shell
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+// Runs on the remote host for as long as the connection lasts, one block of procfs
+// snapshots per interval. Only needs a POSIX shell and df
+fn script(config: &SshConfig) -> String {
+    let disks: Vec<String> = config.disks.iter().map(|disk| quote(disk)).collect();
+    format!(
+        "while :; do \
+        echo @stat; grep '^cpu' /proc/stat; \
+        echo @meminfo; cat /proc/meminfo; \
+        echo @loadavg; cat /proc/loadavg; \
+        echo @uptime; cat /proc/uptime; \
+        echo @net; cat /proc/net/dev; \
+        echo @df; df -Pk {} 2>/dev/null; \
+        echo @end; sleep {}; done",
+        disks.join(" "),
+        config.interval.max(1)
+    )
+}
+
+// Keeps one ssh session per host open, reconnecting whenever it drops
+pub async fn run(config: SshConfig, units: Units, hub: Hub) {
+    let name = config.name.clone().unwrap_or_else(|| config.host.clone());
+    tracing::info!(host = %config.host, %name, "Collecting over SSH");
+
+    let mut backoff = Backoff::new();
+    loop {
+        if let Err(e) = poll(&config, &name, units, &hub, &mut backoff).await {
+            tracing::warn!(host = %config.host, "SSH collection stopped: {e}");
+        }
+        backoff.wait().await;
+    }
+}
+
+async fn poll(
+    config: &SshConfig,
+    name: &str,
+    units: Units,
+    hub: &Hub,
+    backoff: &mut Backoff,
+) -> Result<(), String> {
+    let mut child = tokio::process::Command::new("ssh")
+        // Never wait for a password prompt nobody sees
+        .args(["-o", "BatchMode=yes", "-o", "ServerAliveInterval=15"])
+        .args(&config.options)
+        .arg(&config.host)
+        .arg(script(config))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("failed to run ssh: {e}"))?;
+
+    // Drained alongside stdout, a chatty ssh could otherwise fill the pipe and stall both
+    let stderr = child.stderr.take().map(|mut pipe| {
+        tokio::spawn(async move {
+            let mut stderr = String::new();
+            let _ = pipe.read_to_string(&mut stderr).await;
+            stderr
+        })
+    });
+
+    let mut lines = BufReader::new(child.stdout.take().unwrap()).lines();
+    let mut remote = Remote::default();
+    let mut section = String::new();
+    let mut block: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    while let Ok(Some(line)) = lines.next_line().await {
+        match line.strip_prefix('@') {
+            Some("end") => {
+                hub.update(name, remote.sample(&block, units));
+                block.clear();
+                backoff.reset();
+            }
+            Some(next) => section = next.to_owned(),
+            None => block.entry(section.clone()).or_default().push(line),
+        }
+    }
+
+    let stderr = match stderr {
+        Some(task) => task.await.unwrap_or_default(),
+        None => String::new(),
+    };
+    let status = child.wait().await.map_err(|e| e.to_string())?;
+    Err(format!("ssh exited with {status}: {}", stderr.trim()))
+}
+
+// Counters from the previous block, rates and CPU usage need two of them
+#[derive(Default)]
+struct Remote {
+    // (busy, total) jiffies of the "cpu" line followed by every core
+    cpu: Vec<(u64, u64)>,
+    // Received and transmitted bytes over all interfaces but loopback
+    net: Option<(u64, u64, Instant)>,
+}
+
+impl Remote {
+    fn sample(&mut self, block: &BTreeMap<String, Vec<String>>, units: Units) -> Sample {
+        let section = |name: &str| block.get(name).map(Vec::as_slice).unwrap_or_default();
+        let numbers = |line: &str| -> Vec<u64> {
+            line.split_whitespace()
+                .filter_map(|value| value.parse().ok())
+                .collect()
+        };
+
+        // user nice system idle iowait irq softirq steal, idle and iowait count as idle
+        let cpu: Vec<(u64, u64)> = section("stat")
+            .iter()
+            .map(|line| {
+                let jiffies = numbers(line);
+                let total: u64 = jiffies.iter().take(8).sum();
+                let idle = jiffies.get(3).unwrap_or(&0) + jiffies.get(4).unwrap_or(&0);
+                (total.saturating_sub(idle), total)
+            })
+            .collect();
+        let usage: Vec<u8> = match cpu.len() == self.cpu.len() {
+            true => cpu
+                .iter()
+                .zip(&self.cpu)
+                .map(|((busy, total), (last_busy, last_total))| {
+                    let busy = busy.saturating_sub(*last_busy) as f64;
+                    let total = total.saturating_sub(*last_total).max(1) as f64;
+                    percent("cpu_usage", busy / total * 100.0)
+                })
+                .collect(),
+            false => Vec::new(),
+        };
+        self.cpu = cpu;
+
+        let meminfo = |key: &str| {
+            section("meminfo")
+                .iter()
+                .find(|line| line.starts_with(key))
+                .and_then(|line| numbers(line).first().copied())
+                .map(|kib| kib * 1024)
+        };
+        let ram_total = meminfo("MemTotal:").unwrap_or(0);
+        let ram_used = ram_total.saturating_sub(meminfo("MemAvailable:").unwrap_or(0));
+        let ram_scaled = units.scale(ram_total);
+
+        let load_average = section("loadavg").first().and_then(|line| {
+            let load: Vec<u16> = line
+                .split_whitespace()
+                .take(3)
+                .filter_map(|value| value.parse::<f64>().ok())
+                .map(|load| (load * 100.0).round() as u16)
+                .collect();
+            load.try_into().ok()
+        });
+        let uptime = section("uptime")
+            .first()
+            .and_then(|line| line.split_whitespace().next()?.parse::<f64>().ok())
+            .unwrap_or(0.0);
+
+        let valid = [
+            (SystemInfo::VALID_CPU, !usage.is_empty()),
+            (SystemInfo::VALID_RAM, ram_total > 0),
+            (SystemInfo::VALID_LOAD, load_average.is_some()),
+        ]
+        .into_iter()
+        .filter(|(_, known)| *known)
+        .fold(0, |valid, (flag, _)| valid | flag);
+
+        let mib = 1024 * 1024;
+        let system = SystemInfo {
+            cpu_usage: usage.first().copied().unwrap_or(0),
+            ram_max: ram_scaled.tenths,
+            ram_usage: match ram_total {
+                0 => 0,
+                _ => percent("ram_usage", ram_used as f64 / ram_total as f64 * 100.0),
+            },
+            ram_unit: pop_4u8(ram_scaled.unit.as_bytes()),
+            gpu_usage: u8::MAX,
+            vram_max: 0,
+            vram_usage: u8::MAX,
+            vram_unit: pop_4u8(units.scale(0).unit.as_bytes()),
+            core_usage: usage.iter().skip(1).copied().collect(),
+            ram_used_mib: (ram_used / mib).min(u32::MAX as u64) as u32,
+            ram_total_mib: (ram_total / mib).min(u32::MAX as u64) as u32,
+            vram_used_mib: u32::MAX,
+            vram_total_mib: u32::MAX,
+            gpu_temp: u8::MAX,
+            gpu_power: u16::MAX,
+            gpu_clock: u16::MAX,
+            vram_clock: u16::MAX,
+            gpu_fan: u8::MAX,
+            gpu_encoder: u8::MAX,
+            gpu_decoder: u8::MAX,
+            load_average,
+            cpu_freq_avg: 0,
+            cpu_freq_max: 0,
+            uptime: uptime.min(u32::MAX as f64) as u32,
+            valid,
+        };
+
+        // "eth0: rx_bytes packets errs drop fifo frame compressed multicast tx_bytes ..."
+        let (rx, tx) = section("net")
+            .iter()
+            .filter_map(|line| line.split_once(':'))
+            .filter(|(interface, _)| !interface.trim().starts_with("lo"))
+            .map(|(_, counters)| numbers(counters))
+            .filter(|counters| counters.len() > 8)
+            .fold((0, 0), |(rx, tx), counters| {
+                (rx + counters[0], tx + counters[8])
+            });
+        let now = Instant::now();
+        let network = self.net.map(|(last_rx, last_tx, last)| {
+            let elapsed = now.duration_since(last).as_secs_f64().max(f64::EPSILON);
+            NetworkInfo::from_rates(
+                (rx.saturating_sub(last_rx) as f64 / elapsed) as u64,
+                (tx.saturating_sub(last_tx) as f64 / elapsed) as u64,
+                units,
+            )
+        });
+        self.net = Some((rx, tx, now));
+
+        // "Filesystem 1024-blocks Used Available Capacity Mounted on", header skipped
+        let disks: Vec<DiskInfo> = section("df")
+            .iter()
+            .skip(1)
+            .filter_map(|line| {
+                let columns: Vec<&str> = line.split_whitespace().collect();
+                let total = columns.get(1)?.parse::<u64>().ok()? * 1024;
+                let used = columns.get(2)?.parse::<u64>().ok()? * 1024;
+                let mount_point = columns.get(5..)?.join(" ");
+                let scaled = units.scale(total);
+                Some(DiskInfo {
+                    label: pop_8u8(mount_point.as_bytes()),
+                    disk_max: scaled.tenths,
+                    disk_usage: match total {
+                        0 => 0,
+                        _ => percent("disk_usage", used as f64 / total as f64 * 100.0),
+                    },
+                    disk_unit: pop_4u8(scaled.unit.as_bytes()),
+                    disk_bytes: total,
+                })
+            })
+            .collect();
+
+        Sample {
+            system: Some(system),
+            disks: (!disks.is_empty()).then_some(disks),
+            network,
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    // Captured from a Debian box, trimmed
+    fn block(stat: &[&str]) -> BTreeMap<String, Vec<String>> {
+        let sections: [(&str, &[&str]); 6] = [
+            ("stat", stat),
+            (
+                "meminfo",
+                &[
+                    "MemTotal:       16384000 kB",
+                    "MemFree:         1024000 kB",
+                    "MemAvailable:    4096000 kB",
+                ],
+            ),
+            ("loadavg", &["0.52 0.58 0.59 1/467 12345"]),
+            ("uptime", &["12345.67 98765.43"]),
+            (
+                "net",
+                &[
+                    "Inter-|   Receive                                                |  Transmit",
+                    " face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs drop fifo colls carrier compressed",
+                    "    lo:    5000      50    0    0    0     0          0         0     5000      50    0    0    0     0       0          0",
+                    "  eth0:    3000      20    0    0    0     0          0         0     1500      10    0    0    0     0       0          0",
+                    " wlan0:    1000      10    0    0    0     0          0         0      500       5    0    0    0     0       0          0",
+                ],
+            ),
+            (
+                "df",
+                &[
+                    "Filesystem     1024-blocks    Used Available Capacity Mounted on",
+                    "/dev/sda1          1000000  250000    750000      25% /",
+                    "/dev/sdb1          2000000 1000000   1000000      50% /mnt/my data",
+                ],
+            ),
+        ];
+        sections
+            .iter()
+            .map(|(name, lines)| {
+                let lines = lines.iter().map(|line| line.to_string()).collect();
+                (name.to_string(), lines)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn cpu_usage_needs_two_blocks() {
+        let mut remote = Remote::default();
+        let first = remote.sample(
+            &block(&[
+                "cpu  100 0 100 800 0 0 0 0 0 0",
+                "cpu0 50 0 50 400 0 0 0 0 0 0",
+                "cpu1 50 0 50 400 0 0 0 0 0 0",
+            ]),
+            Units::Binary,
+        );
+        let system = first.system.unwrap();
+        assert_eq!(system.valid & SystemInfo::VALID_CPU, 0);
+
+        // cpu0 busy for all of it, cpu1 idle, iowait counts as idle
+        let second = remote.sample(
+            &block(&[
+                "cpu  200 0 200 1000 0 0 0 0 0 0",
+                "cpu0 150 0 150 400 0 0 0 0 0 0",
+                "cpu1 50 0 50 500 100 0 0 0 0 0",
+            ]),
+            Units::Binary,
+        );
+        let system = second.system.unwrap();
+        assert_ne!(system.valid & SystemInfo::VALID_CPU, 0);
+        assert_eq!(system.cpu_usage, 50);
+        assert_eq!(system.core_usage, [100, 0]);
+    }
+
+    #[test]
+    fn memory_load_and_uptime() {
+        let sample = Remote::default().sample(&block(&[]), Units::Binary);
+        let system = sample.system.unwrap();
+        assert_eq!(system.ram_usage, 75);
+        assert_eq!(system.ram_total_mib, 16000);
+        assert_eq!(system.ram_used_mib, 12000);
+        assert_eq!(system.load_average, Some([52, 58, 59]));
+        assert_eq!(system.uptime, 12345);
+        assert_ne!(system.valid & SystemInfo::VALID_RAM, 0);
+        assert_ne!(system.valid & SystemInfo::VALID_LOAD, 0);
+    }
+
+    #[test]
+    fn network_rates_leave_out_loopback() {
+        let mut remote = Remote {
+            net: Some((2000, 1000, Instant::now() - Duration::from_secs(1))),
+            ..Default::default()
+        };
+        let network = remote.sample(&block(&[]), Units::Binary).network.unwrap();
+        // 2000 and 1000 bytes over a little more than the second the test took
+        assert!((1900..=2000).contains(&network.rx_bytes));
+        assert!((950..=1000).contains(&network.tx_bytes));
+        assert!(matches!(remote.net, Some((4000, 2000, _))));
+
+        let first = Remote::default().sample(&block(&[]), Units::Binary);
+        assert!(first.network.is_none());
+    }
+
+    #[test]
+    fn df_mounts_with_spaces() {
+        let disks = Remote::default()
+            .sample(&block(&[]), Units::Binary)
+            .disks
+            .unwrap();
+        assert_eq!(disks.len(), 2);
+        assert_eq!(disks[0].label, *b"/\0\0\0\0\0\0\0");
+        assert_eq!(disks[0].disk_usage, 25);
+        assert_eq!(disks[0].disk_bytes, 1000000 * 1024);
+        assert_eq!(disks[1].label, *b"/mnt/my ");
+        assert_eq!(disks[1].disk_usage, 50);
+    }
+}
@@ -0,0 +1,98 @@
+use crate::config::Config;
+use crate::transport::ble;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const LABEL: &str = "com.pxseu.flipper-pc-monitor";
+
+fn plist_path() -> Result<PathBuf, Box<dyn Error>> {
+    Ok(dirs::home_dir()
+        .ok_or("Failed to find the home directory")?
+        .join("Library")
+        .join("LaunchAgents")
+        .join(format!("{LABEL}.plist")))
+}
+
+fn plist(exe: &Path, config: Option<&Path>, log: &Path) -> String {
+    let mut arguments = vec![exe.display().to_string(), "run".to_owned()];
+    if let Some(config) = config {
+        arguments.push("--config".to_owned());
+        arguments.push(config.display().to_string());
+    }
+    let arguments: String = arguments
+        .iter()
+        .map(|argument| format!("        <string>{}</string>\n", escape(argument)))
+        .collect();
+    let log = escape(&log.display().to_string());
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{LABEL}</string>
+    <key>ProgramArguments</key>
+    <array>
+{arguments}    </array>
+    <key>RunAtLoad</key>
+    <true/>
+    <key>KeepAlive</key>
+    <true/>
+    <key>StandardOutPath</key>
+    <string>{log}</string>
+    <key>StandardErrorPath</key>
+    <string>{log}</string>
+</dict>
+</plist>
+"#
+    )
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+// Writes a LaunchAgent, loading it is left to launchctl so nothing starts behind the user's back
+pub async fn install(config: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    // macOS only asks for Bluetooth access while a process is in the foreground.
+    // Agents started by launchd never get the prompt, so trigger it now for this binary
+    println!("Requesting Bluetooth access, allow it if macOS asks");
+    let ble_config = Config::load(config)?.ble;
+    ble::scan(Duration::from_secs(3), &ble_config).await?;
+
+    let exe = std::env::current_exe()?;
+    let config = config.map(std::fs::canonicalize).transpose()?;
+    let log = dirs::home_dir()
+        .ok_or("Failed to find the home directory")?
+        .join("Library")
+        .join("Logs")
+        .join("flipper-pc-monitor.log");
+
+    let path = plist_path()?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(&path, plist(&exe, config.as_deref(), &log))?;
+
+    println!("Wrote {}", path.display());
+    println!("Load it with: launchctl load -w \"{}\"", path.display());
+    println!("Logs go to {}", log.display());
+    Ok(())
+}
+
+pub fn uninstall() -> Result<(), Box<dyn Error>> {
+    let path = plist_path()?;
+    println!(
+        "Stop a running instance first with: launchctl unload -w \"{}\"",
+        path.display()
+    );
+    std::fs::remove_file(&path).map_err(|e| format!("Failed to remove {}: {e}", path.display()))?;
+
+    println!("Removed {}", path.display());
+    Ok(())
+}
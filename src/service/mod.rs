@@ -0,0 +1,6 @@
+#[cfg(target_os = "macos")]
+pub mod launchd;
+#[cfg(target_os = "linux")]
+pub mod systemd;
+#[cfg(windows)]
+pub mod windows;
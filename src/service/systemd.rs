@@ -0,0 +1,116 @@
+use crate::transport::Link;
+use std::error::Error;
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::path::Path;
+use std::time::Duration;
+
+const UNIT_NAME: &str = "flipper-pc-monitor.service";
+
+// sd_notify without libsystemd, does nothing when not started by systemd
+pub fn notify(message: &str) {
+    let Some(path) = std::env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+    let Ok(socket) = UnixDatagram::unbound() else {
+        return;
+    };
+
+    // A leading @ means an abstract socket
+    let result = match path.as_bytes().strip_prefix(b"@") {
+        Some(name) => SocketAddr::from_abstract_name(name)
+            .and_then(|addr| socket.send_to_addr(message.as_bytes(), &addr)),
+        None => socket.send_to(message.as_bytes(), &path),
+    };
+    if let Err(e) = result {
+        tracing::warn!("Failed to notify systemd: {e}");
+    }
+}
+
+fn watchdog_interval() -> Option<Duration> {
+    let usec = std::env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    // Ping twice per timeout so a single late tick doesn't get us killed
+    Some(Duration::from_micros(usec) / 2)
+}
+
+// Reports readiness, keeps the watchdog fed and mirrors the connection state into `systemctl status`
+pub fn spawn(link: &Link) {
+    notify("READY=1");
+
+    if let Some(interval) = watchdog_interval() {
+        tokio::spawn(async move {
+            loop {
+                notify("WATCHDOG=1");
+                tokio::time::sleep(interval).await;
+            }
+        });
+    }
+
+    let mut state = link.state();
+    tokio::spawn(async move {
+        loop {
+            notify(&format!("STATUS={:?}", *state.borrow_and_update()));
+            if state.changed().await.is_err() {
+                return;
+            }
+        }
+    });
+}
+
+fn unit(exe: &Path, config: Option<&Path>) -> String {
+    let mut exec_start = format!("\"{}\" run --daemon", exe.display());
+    if let Some(config) = config {
+        exec_start += &format!(" --config \"{}\"", config.display());
+    }
+
+    format!(
+        "[Unit]
+Description=Flipper PC Monitor backend
+After=bluetooth.target
+
+[Service]
+Type=notify
+ExecStart={exec_start}
+WatchdogSec=30
+Restart=on-failure
+RestartSec=5
+
+[Install]
+WantedBy=default.target
+"
+    )
+}
+
+fn unit_path() -> Result<std::path::PathBuf, Box<dyn Error>> {
+    Ok(dirs::config_dir()
+        .ok_or("Failed to find the config directory")?
+        .join("systemd")
+        .join("user")
+        .join(UNIT_NAME))
+}
+
+// Writes a user unit, enabling it is left to systemctl so nothing starts behind the user's back
+pub fn install(config: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    let exe = std::env::current_exe()?;
+    let config = config.map(std::fs::canonicalize).transpose()?;
+
+    let path = unit_path()?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(&path, unit(&exe, config.as_deref()))?;
+
+    println!("Wrote {}", path.display());
+    println!("Enable it with: systemctl --user daemon-reload && systemctl --user enable --now {UNIT_NAME}");
+    Ok(())
+}
+
+pub fn uninstall() -> Result<(), Box<dyn Error>> {
+    let path = unit_path()?;
+    std::fs::remove_file(&path).map_err(|e| format!("Failed to remove {}: {e}", path.display()))?;
+
+    println!("Removed {}", path.display());
+    println!("Stop a running instance with: systemctl --user disable --now {UNIT_NAME}");
+    Ok(())
+}
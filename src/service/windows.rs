@@ -0,0 +1,133 @@
+use crate::config::Config;
+use std::error::Error;
+use std::ffi::OsString;
+use std::path::Path;
+use std::sync::OnceLock;
+use std::time::Duration;
+use windows_service::service::{
+    ServiceAccess, ServiceControl, ServiceControlAccept, ServiceErrorControl, ServiceExitCode,
+    ServiceInfo, ServiceStartType, ServiceState, ServiceStatus, ServiceType,
+};
+use windows_service::service_control_handler::{self, ServiceControlHandlerResult};
+use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+use windows_service::{define_windows_service, service_dispatcher};
+
+const SERVICE_NAME: &str = "FlipperPcMonitor";
+
+// The SCM calls service_main without any way to pass our own state
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+define_windows_service!(ffi_service_main, service_main);
+
+fn status(state: ServiceState, controls_accepted: ServiceControlAccept) -> ServiceStatus {
+    ServiceStatus {
+        service_type: ServiceType::OWN_PROCESS,
+        current_state: state,
+        controls_accepted,
+        exit_code: ServiceExitCode::Win32(0),
+        checkpoint: 0,
+        wait_hint: Duration::default(),
+        process_id: None,
+    }
+}
+
+fn service_main(_arguments: Vec<OsString>) {
+    if let Err(e) = run_service() {
+        tracing::error!("Service failed: {e}");
+    }
+}
+
+fn run_service() -> Result<(), Box<dyn Error>> {
+    let (shutdown, mut stopped) = tokio::sync::watch::channel(false);
+    let status_handle =
+        service_control_handler::register(SERVICE_NAME, move |control| match control {
+            ServiceControl::Stop | ServiceControl::Shutdown => {
+                let _ = shutdown.send(true);
+                ServiceControlHandlerResult::NoError
+            }
+            ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+            _ => ServiceControlHandlerResult::NotImplemented,
+        })?;
+
+    status_handle.set_service_status(status(
+        ServiceState::Running,
+        ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN,
+    ))?;
+
+    // service_main runs on a thread of its own, outside the runtime main() started
+    let config = CONFIG.get().cloned().unwrap_or_default();
+    let result = tokio::runtime::Runtime::new()?.block_on(async {
+        tokio::select! {
+            result = crate::run(config, false) => result,
+            _ = stopped.changed() => Ok(()),
+        }
+    });
+
+    status_handle
+        .set_service_status(status(ServiceState::Stopped, ServiceControlAccept::empty()))?;
+    result
+}
+
+// Entry point when started by the SCM, blocks until the service is stopped
+pub fn dispatch(config: Config) -> Result<(), Box<dyn Error>> {
+    let _ = CONFIG.set(config);
+    service_dispatcher::start(SERVICE_NAME, ffi_service_main)?;
+    Ok(())
+}
+
+pub fn install(config: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    let manager = ServiceManager::local_computer(
+        None::<&str>,
+        ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE,
+    )?;
+
+    // The service runs as LocalSystem, which has a config dir of its own
+    let config = match config {
+        Some(config) => Some(std::fs::canonicalize(config)?),
+        None => Config::default_path().filter(|path| path.exists()),
+    };
+
+    let mut launch_arguments = vec![OsString::from("service")];
+    if let Some(config) = config {
+        launch_arguments.push("--config".into());
+        launch_arguments.push(config.into_os_string());
+    }
+
+    let info = ServiceInfo {
+        name: SERVICE_NAME.into(),
+        display_name: "Flipper PC Monitor".into(),
+        service_type: ServiceType::OWN_PROCESS,
+        start_type: ServiceStartType::AutoStart,
+        error_control: ServiceErrorControl::Normal,
+        executable_path: std::env::current_exe()?,
+        launch_arguments,
+        dependencies: vec![],
+        account_name: None,
+        account_password: None,
+    };
+
+    let service =
+        manager.create_service(&info, ServiceAccess::CHANGE_CONFIG | ServiceAccess::START)?;
+    service.set_description("Streams system info to the PC Monitor app on a Flipper Zero")?;
+    service.start::<&str>(&[])?;
+
+    println!("Installed and started the {SERVICE_NAME} service");
+    Ok(())
+}
+
+pub fn uninstall() -> Result<(), Box<dyn Error>> {
+    let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)?;
+    let service = manager.open_service(
+        SERVICE_NAME,
+        ServiceAccess::QUERY_STATUS | ServiceAccess::STOP | ServiceAccess::DELETE,
+    )?;
+
+    if service.query_status()?.current_state != ServiceState::Stopped {
+        service.stop()?;
+    }
+    // Removal finishes once the SCM lets go of the last handle
+    service.delete()?;
+
+    println!("Removed the {SERVICE_NAME} service");
+    Ok(())
+}
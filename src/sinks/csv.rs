@@ -0,0 +1,271 @@
+use super::points::{flatten, Point};
+use super::timestamp_ms;
+use crate::collectors::Sample;
+use serde::Deserialize;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use tokio::sync::broadcast;
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct CsvConfig {
+    pub path: PathBuf,
+    // Megabytes before the file is moved to <path>.1, 0 never rotates
+    pub max_size: u64,
+    // Rotated files kept next to the current one
+    pub keep: usize,
+}
+
+impl CsvConfig {
+    // None never rotates, sizes past what a u64 holds neither
+    fn max_bytes(&self) -> Option<u64> {
+        (self.max_size > 0).then(|| self.max_size.saturating_mul(1 << 20))
+    }
+}
+
+impl Default for CsvConfig {
+    fn default() -> Self {
+        CsvConfig {
+            path: PathBuf::from("flipper-pc-monitor.csv"),
+            max_size: 10,
+            keep: 5,
+        }
+    }
+}
+
+pub async fn run(config: CsvConfig, mut samples: broadcast::Receiver<Sample>) {
+    tracing::info!(path = %config.path.display(), "Logging samples to CSV");
+
+    let mut writer = CsvWriter {
+        config,
+        file: None,
+        columns: Vec::new(),
+        size: 0,
+    };
+    loop {
+        let sample = match samples.recv().await {
+            Ok(sample) => sample,
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+
+        if let Err(e) = writer.write(&sample) {
+            tracing::warn!(path = %writer.config.path.display(), "Failed to write CSV: {e}");
+        }
+    }
+}
+
+struct CsvWriter {
+    config: CsvConfig,
+    file: Option<File>,
+    // Header of the open file, a sample with different columns starts a new one
+    columns: Vec<String>,
+    size: u64,
+}
+
+impl CsvWriter {
+    fn write(&mut self, sample: &Sample) -> io::Result<()> {
+        let points: Vec<Point> = flatten(sample)
+            .into_iter()
+            .filter(|point| !point.is_volatile())
+            .collect();
+        let columns: Vec<String> = points
+            .iter()
+            .flat_map(|point| point.fields.iter().map(|(field, _)| point.column(field)))
+            .collect();
+
+        let full = self.config.max_bytes().is_some_and(|max| self.size >= max);
+        // Taken out and only put back once written, a failed write reopens the file next time
+        let mut file = match self.file.take() {
+            Some(file) if !full && columns == self.columns => file,
+            previous => {
+                if previous.is_some() && !full {
+                    tracing::info!("Collected metrics changed, starting a new CSV file");
+                }
+                self.open(columns)?
+            }
+        };
+
+        let timestamp = timestamp_ms();
+        let values = points.iter().flat_map(|point| {
+            point
+                .fields
+                .iter()
+                .map(|(_, value)| value.map(|value| value.to_string()).unwrap_or_default())
+        });
+        let line = std::iter::once(timestamp.to_string())
+            .chain(values)
+            .collect::<Vec<_>>()
+            .join(",")
+            + "\n";
+
+        file.write_all(line.as_bytes())?;
+        self.size += line.len() as u64;
+        self.file = Some(file);
+        Ok(())
+    }
+
+    // Appends to an existing file with the same header, anything else is rotated away first
+    fn open(&mut self, columns: Vec<String>) -> io::Result<File> {
+        let path = &self.config.path;
+        let header = std::iter::once("timestamp_ms".to_owned())
+            .chain(columns.iter().map(|column| quote(column)))
+            .collect::<Vec<_>>()
+            .join(",");
+
+        let existing = File::open(path)
+            .ok()
+            .and_then(|file| BufReader::new(file).lines().next()?.ok());
+        let size = std::fs::metadata(path).map_or(0, |metadata| metadata.len());
+        let full = self.config.max_bytes().is_some_and(|max| size >= max);
+        let append = existing.as_deref() == Some(header.as_str()) && !full;
+        if existing.is_some() && !append {
+            rotate(path, self.config.keep)?;
+        }
+
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        self.size = match append {
+            true => size,
+            false => {
+                writeln!(file, "{header}")?;
+                header.len() as u64 + 1
+            }
+        };
+        self.columns = columns;
+        Ok(file)
+    }
+}
+
+fn quote(value: &str) -> String {
+    match value.contains([',', '"', '\n']) {
+        true => format!("\"{}\"", value.replace('"', "\"\"")),
+        false => value.to_owned(),
+    }
+}
+
+// <path> becomes <path>.1, <path>.1 becomes <path>.2 and so on, dropping the oldest
+fn rotate(path: &Path, keep: usize) -> io::Result<()> {
+    let numbered = |n: usize| {
+        let mut name = path.as_os_str().to_owned();
+        name.push(format!(".{n}"));
+        PathBuf::from(name)
+    };
+
+    if keep == 0 {
+        return std::fs::remove_file(path);
+    }
+    for n in (1..keep).rev() {
+        if numbered(n).exists() {
+            std::fs::rename(numbered(n), numbered(n + 1))?;
+        }
+    }
+    std::fs::rename(path, numbered(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Fresh for every test, the system's temp dir is shared between runs
+    fn temp_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "flipper-pc-monitor-csv-{}-{name}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join("samples.csv")
+    }
+
+    fn numbered(path: &Path, n: usize) -> PathBuf {
+        let mut name = path.as_os_str().to_owned();
+        name.push(format!(".{n}"));
+        PathBuf::from(name)
+    }
+
+    #[test]
+    fn quotes_only_when_needed() {
+        assert_eq!(quote("system.cpu_usage"), "system.cpu_usage");
+        assert_eq!(quote("disk[C:, D:].usage"), "\"disk[C:, D:].usage\"");
+        assert_eq!(quote("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(quote("a\nb"), "\"a\nb\"");
+    }
+
+    #[test]
+    fn rotate_shifts_and_drops_the_oldest() {
+        let path = temp_path("rotate");
+        for (n, content) in ["current", "first", "second"].iter().enumerate() {
+            let file = if n == 0 {
+                path.clone()
+            } else {
+                numbered(&path, n)
+            };
+            std::fs::write(file, content).unwrap();
+        }
+
+        rotate(&path, 2).unwrap();
+        assert!(!path.exists());
+        assert_eq!(
+            std::fs::read_to_string(numbered(&path, 1)).unwrap(),
+            "current"
+        );
+        assert_eq!(
+            std::fs::read_to_string(numbered(&path, 2)).unwrap(),
+            "first"
+        );
+
+        std::fs::write(&path, "new").unwrap();
+        rotate(&path, 0).unwrap();
+        assert!(!path.exists());
+        assert_eq!(
+            std::fs::read_to_string(numbered(&path, 1)).unwrap(),
+            "current"
+        );
+    }
+
+    #[test]
+    fn reopens_matching_headers_and_rotates_others() {
+        let path = temp_path("reopen");
+        std::fs::write(&path, "timestamp_ms,a,b\n1,2,3\n").unwrap();
+        let mut writer = CsvWriter {
+            config: CsvConfig {
+                path: path.clone(),
+                ..Default::default()
+            },
+            file: None,
+            columns: Vec::new(),
+            size: 0,
+        };
+
+        writer.open(vec!["a".to_owned(), "b".to_owned()]).unwrap();
+        assert_eq!(writer.size, 23);
+        assert!(!numbered(&path, 1).exists());
+
+        writer.open(vec!["a".to_owned(), "c".to_owned()]).unwrap();
+        assert_eq!(writer.size, 17);
+        assert_eq!(
+            std::fs::read_to_string(numbered(&path, 1)).unwrap(),
+            "timestamp_ms,a,b\n1,2,3\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "timestamp_ms,a,c\n"
+        );
+    }
+
+    #[test]
+    fn huge_sizes_never_rotate() {
+        let config = CsvConfig {
+            max_size: u64::MAX,
+            ..Default::default()
+        };
+        assert_eq!(config.max_bytes(), Some(u64::MAX));
+        assert_eq!(CsvConfig::default().max_bytes(), Some(10 << 20));
+        let never = CsvConfig {
+            max_size: 0,
+            ..Default::default()
+        };
+        assert_eq!(never.max_bytes(), None);
+    }
+}
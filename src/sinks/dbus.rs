@@ -0,0 +1,136 @@
+use super::points::flatten;
+use crate::collectors::Sample;
+use crate::commands::Command;
+use crate::transport::Link;
+use std::collections::HashMap;
+use tokio::sync::broadcast;
+use zbus::object_server::SignalContext;
+use zbus::{interface, Connection};
+
+const BUS_NAME: &str = "org.flipper.PcMonitor";
+const PATH: &str = "/org/flipper/PcMonitor";
+
+struct PcMonitor {
+    link: Link,
+    // Latest sample keyed like "system.cpu_usage" or "disk[/home].usage", unknown values left out
+    metrics: HashMap<String, f64>,
+}
+
+#[interface(name = "org.flipper.PcMonitor")]
+impl PcMonitor {
+    #[zbus(property)]
+    fn metrics(&self) -> HashMap<String, f64> {
+        self.metrics.clone()
+    }
+
+    // "scanning", "connecting" or "connected"
+    #[zbus(property)]
+    fn connection(&self) -> String {
+        format!("{:?}", *self.link.state().borrow()).to_lowercase()
+    }
+
+    #[zbus(property)]
+    fn paused(&self) -> bool {
+        self.link.is_paused()
+    }
+
+    async fn pause(
+        &self,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+    ) -> zbus::fdo::Result<()> {
+        self.link.set_paused(true);
+        self.paused_changed(&ctxt).await?;
+        Ok(())
+    }
+
+    async fn resume(
+        &self,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+    ) -> zbus::fdo::Result<()> {
+        self.link.set_paused(false);
+        self.paused_changed(&ctxt).await?;
+        Ok(())
+    }
+
+    fn set_interval(&self, interval_ms: u16) {
+        self.link.commands().send(Command::SetInterval(interval_ms));
+    }
+
+    // Every new sample, same contents as the Metrics property
+    #[zbus(signal)]
+    async fn refreshed(ctxt: &SignalContext<'_>, metrics: HashMap<String, f64>)
+        -> zbus::Result<()>;
+}
+
+async fn connect(link: Link) -> zbus::Result<Connection> {
+    let monitor = PcMonitor {
+        link,
+        metrics: HashMap::new(),
+    };
+    zbus::connection::Builder::session()?
+        .name(BUS_NAME)?
+        .serve_at(PATH, monitor)?
+        .build()
+        .await
+}
+
+// org.flipper.PcMonitor on the session bus, for desktop extensions and scripts
+pub async fn run(link: Link, mut samples: broadcast::Receiver<Sample>) {
+    let mut state = link.state();
+    let connection = match connect(link).await {
+        Ok(connection) => connection,
+        Err(e) => return tracing::error!("Failed to register on D-Bus: {e}"),
+    };
+    let iface = match connection
+        .object_server()
+        .interface::<_, PcMonitor>(PATH)
+        .await
+    {
+        Ok(iface) => iface,
+        Err(e) => return tracing::error!("Failed to look up D-Bus interface: {e}"),
+    };
+    tracing::info!(name = BUS_NAME, "Serving on D-Bus");
+
+    loop {
+        tokio::select! {
+            sample = samples.recv() => {
+                let sample = match sample {
+                    Ok(sample) => sample,
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return,
+                };
+
+                let metrics: HashMap<String, f64> = flatten(&sample)
+                    .iter()
+                    .filter(|point| !point.is_volatile())
+                    .flat_map(|point| {
+                        point
+                            .fields
+                            .iter()
+                            .filter_map(|(field, value)| Some((point.column(field), (*value)?)))
+                    })
+                    .collect();
+
+                let mut monitor = iface.get_mut().await;
+                monitor.metrics = metrics.clone();
+                let ctxt = iface.signal_context();
+                let emitted = match monitor.metrics_changed(ctxt).await {
+                    Ok(()) => PcMonitor::refreshed(ctxt, metrics).await,
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = emitted {
+                    tracing::debug!("Failed to emit D-Bus signal: {e}");
+                }
+            }
+            changed = state.changed() => {
+                if changed.is_err() {
+                    return;
+                }
+                let monitor = iface.get().await;
+                if let Err(e) = monitor.connection_changed(iface.signal_context()).await {
+                    tracing::debug!("Failed to emit D-Bus signal: {e}");
+                }
+            }
+        }
+    }
+}
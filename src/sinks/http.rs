@@ -0,0 +1,145 @@
+use crate::collectors::Sample;
+use crate::transport::Link;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::Response;
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+
+// /health reports unhealthy when collection stalled for this long
+const STALE_AFTER: Duration = Duration::from_secs(30);
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct HttpConfig {
+    // Only reachable from this machine unless changed to e.g. "0.0.0.0"
+    pub address: String,
+    pub port: u16,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        HttpConfig {
+            address: "127.0.0.1".to_owned(),
+            port: 8700,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Shared {
+    latest: Arc<RwLock<Option<(Sample, Instant)>>>,
+    link: Link,
+    samples: broadcast::Sender<Sample>,
+}
+
+#[derive(Serialize)]
+struct Health {
+    connection: String,
+    paused: bool,
+    // Milliseconds since the last sample, null before the first one
+    sample_age_ms: Option<u64>,
+}
+
+pub async fn run(config: HttpConfig, link: Link, samples: broadcast::Sender<Sample>) {
+    let latest = Arc::new(RwLock::new(None));
+    let mut receiver = samples.subscribe();
+    let app = Router::new()
+        .route("/metrics.json", get(metrics))
+        .route("/health", get(health))
+        .route("/ws", get(stream))
+        .with_state(Shared {
+            latest: latest.clone(),
+            link,
+            samples,
+        });
+
+    let listener = match tokio::net::TcpListener::bind((config.address.as_str(), config.port)).await
+    {
+        Ok(listener) => listener,
+        Err(e) => {
+            return tracing::error!(
+                address = %config.address,
+                port = config.port,
+                "Failed to start HTTP API: {e}"
+            );
+        }
+    };
+    tracing::info!(address = %config.address, port = config.port, "Serving HTTP API");
+
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, app).await {
+            tracing::error!("HTTP API stopped: {e}");
+        }
+    });
+
+    loop {
+        let sample = match receiver.recv().await {
+            Ok(sample) => sample,
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+        *latest.write().unwrap() = Some((sample, Instant::now()));
+    }
+}
+
+async fn metrics(State(shared): State<Shared>) -> Result<Json<Sample>, StatusCode> {
+    let latest = shared.latest.read().unwrap();
+    match &*latest {
+        Some((sample, _)) => Ok(Json(sample.clone())),
+        None => Err(StatusCode::SERVICE_UNAVAILABLE),
+    }
+}
+
+async fn health(State(shared): State<Shared>) -> (StatusCode, Json<Health>) {
+    let age = shared
+        .latest
+        .read()
+        .unwrap()
+        .as_ref()
+        .map(|(_, collected)| collected.elapsed());
+    let status = match age {
+        Some(age) if age < STALE_AFTER => StatusCode::OK,
+        _ => StatusCode::SERVICE_UNAVAILABLE,
+    };
+
+    let health = Health {
+        connection: format!("{:?}", *shared.link.state().borrow()).to_lowercase(),
+        paused: shared.link.is_paused(),
+        sample_age_ms: age.map(|age| age.as_millis() as u64),
+    };
+    (status, Json(health))
+}
+
+// Every sample as JSON, for browser dashboards and overlays
+async fn stream(ws: WebSocketUpgrade, State(shared): State<Shared>) -> Response {
+    let samples = shared.samples.subscribe();
+    ws.on_upgrade(move |socket| push_samples(socket, samples))
+}
+
+async fn push_samples(mut socket: WebSocket, mut samples: broadcast::Receiver<Sample>) {
+    loop {
+        let sample = tokio::select! {
+            sample = samples.recv() => match sample {
+                Ok(sample) => sample,
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return,
+            },
+            // Clients aren't expected to send anything, this notices them going away
+            message = socket.recv() => match message {
+                Some(Ok(_)) => continue,
+                _ => return,
+            },
+        };
+
+        let json = serde_json::to_string(&sample).unwrap();
+        if socket.send(Message::Text(json)).await.is_err() {
+            return;
+        }
+    }
+}
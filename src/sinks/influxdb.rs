@@ -0,0 +1,164 @@
+use super::points::{flatten, Point};
+use super::timestamp_ms;
+use crate::collectors::Sample;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+// Lines kept while the server is unreachable, the oldest are dropped first
+const MAX_BUFFERED: usize = 10_000;
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct InfluxConfig {
+    pub url: String,
+    pub org: String,
+    pub bucket: String,
+    pub token: Option<String>,
+    // Added to every point, e.g. host = "desktop"
+    pub tags: BTreeMap<String, String>,
+    // Seconds between writes, samples are batched in between
+    pub flush_interval: u64,
+}
+
+impl Default for InfluxConfig {
+    fn default() -> Self {
+        InfluxConfig {
+            url: "http://localhost:8086".to_owned(),
+            org: String::new(),
+            bucket: "flipper-pc-monitor".to_owned(),
+            token: None,
+            tags: BTreeMap::new(),
+            flush_interval: 10,
+        }
+    }
+}
+
+pub async fn run(config: InfluxConfig, mut samples: broadcast::Receiver<Sample>) {
+    let client = reqwest::Client::new();
+    let url = format!("{}/api/v2/write", config.url.trim_end_matches('/'));
+    let tags: String = config
+        .tags
+        .iter()
+        .map(|(key, value)| format!(",{}={}", escape(key), escape(value)))
+        .collect();
+
+    tracing::info!(url = %config.url, bucket = %config.bucket, "Writing to InfluxDB");
+
+    let mut lines: Vec<String> = Vec::new();
+    let mut flush = tokio::time::interval(Duration::from_secs(config.flush_interval.max(1)));
+    loop {
+        tokio::select! {
+            sample = samples.recv() => match sample {
+                Ok(sample) => {
+                    let timestamp = timestamp_ms();
+                    lines.extend(
+                        flatten(&sample)
+                            .iter()
+                            .filter_map(|point| line(point, &tags, timestamp)),
+                    );
+                    if lines.len() > MAX_BUFFERED {
+                        lines.drain(..lines.len() - MAX_BUFFERED);
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return,
+            },
+            _ = flush.tick() => {
+                if lines.is_empty() {
+                    continue;
+                }
+
+                let mut request = client
+                    .post(&url)
+                    .query(&[
+                        ("org", config.org.as_str()),
+                        ("bucket", config.bucket.as_str()),
+                        ("precision", "ms"),
+                    ])
+                    .body(lines.join("\n"));
+                if let Some(token) = &config.token {
+                    request = request.header("Authorization", format!("Token {token}"));
+                }
+
+                match request.send().await.and_then(|r| r.error_for_status()) {
+                    Ok(_) => lines.clear(),
+                    // Retrying won't fix a wrong token, bucket or rejected points
+                    Err(e) if e.status().is_some_and(|s| s.is_client_error()) => {
+                        tracing::warn!("InfluxDB rejected the write, dropping it: {e}");
+                        lines.clear();
+                    }
+                    Err(e) => tracing::warn!("Failed to write to InfluxDB: {e}"),
+                }
+            }
+        }
+    }
+}
+
+// Tag keys and values, and field keys, can't contain unescaped commas, equals signs or spaces
+fn escape(value: &str) -> String {
+    escape_measurement(value).replace('=', "\\=")
+}
+
+// Measurements only need commas and spaces escaped
+fn escape_measurement(value: &str) -> String {
+    value.replace(',', "\\,").replace(' ', "\\ ")
+}
+
+// None when every field was unknown, a line needs at least one
+fn line(point: &Point, tags: &str, timestamp: u128) -> Option<String> {
+    let mut line = format!("{}{tags}", escape_measurement(point.measurement));
+    // Empty tag values aren't allowed, those are left out
+    for (key, value) in point.tags.iter().filter(|(_, value)| !value.is_empty()) {
+        let _ = write!(line, ",{}={}", escape(key), escape(value));
+    }
+
+    let fields: Vec<String> = point
+        .fields
+        .iter()
+        .filter_map(|(key, value)| Some(format!("{}={}", escape(key), (*value)?)))
+        .collect();
+    if fields.is_empty() {
+        return None;
+    }
+
+    Some(format!("{line} {} {timestamp}", fields.join(",")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_spaces_commas_and_equals() {
+        assert_eq!(escape("a b,c=d"), "a\\ b\\,c\\=d");
+        assert_eq!(escape_measurement("a b,c=d"), "a\\ b\\,c=d");
+        assert_eq!(escape("plain"), "plain");
+    }
+
+    #[test]
+    fn line_protocol() {
+        let point = Point {
+            measurement: "disk usage",
+            tags: vec![("label", "C: Data".to_owned()), ("unit", String::new())],
+            fields: vec![
+                ("used", Some(40.0)),
+                ("max,total", Some(5000.5)),
+                ("free", None),
+            ],
+        };
+        assert_eq!(
+            line(&point, ",host=my\\ pc", 1234).as_deref(),
+            Some("disk\\ usage,host=my\\ pc,label=C:\\ Data used=40,max\\,total=5000.5 1234")
+        );
+
+        let unknown = Point {
+            measurement: "gpu",
+            tags: Vec::new(),
+            fields: vec![("usage", None)],
+        };
+        assert_eq!(line(&unknown, "", 1234), None);
+    }
+}
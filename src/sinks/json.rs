@@ -0,0 +1,87 @@
+use crate::collectors::Sample;
+use crate::transport::backoff::Backoff;
+use serde::Deserialize;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::sync::broadcast;
+use tokio_serial::SerialPortBuilderExt;
+
+/*
+Every sample as one line of JSON, the same object GET /metrics.json serves, for displays
+that would rather parse text than the binary packets (ESP32 desk screens and the like).
+Written to a serial port, or to stdout without one (logs go to stderr).
+*/
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct JsonConfig {
+    // Serial port of the display, stdout when unset
+    pub port: Option<String>,
+    pub baud_rate: u32,
+}
+
+impl Default for JsonConfig {
+    fn default() -> Self {
+        JsonConfig {
+            port: None,
+            baud_rate: 115200,
+        }
+    }
+}
+
+type Output = Box<dyn AsyncWrite + Send + Unpin>;
+
+fn open(config: &JsonConfig) -> tokio_serial::Result<Output> {
+    match &config.port {
+        Some(port) => Ok(Box::new(
+            tokio_serial::new(port, config.baud_rate).open_native_async()?,
+        )),
+        None => Ok(Box::new(tokio::io::stdout())),
+    }
+}
+
+pub async fn run(config: JsonConfig, mut samples: broadcast::Receiver<Sample>) {
+    match &config.port {
+        Some(port) => tracing::info!(
+            port,
+            baud_rate = config.baud_rate,
+            "Writing samples as JSON lines"
+        ),
+        None => tracing::info!("Writing samples as JSON lines to stdout"),
+    }
+
+    let mut backoff = Backoff::new();
+    loop {
+        match open(&config) {
+            Ok(mut output) => {
+                backoff.reset();
+                // Whatever queued up while the port was gone is stale by now
+                samples = samples.resubscribe();
+                match write(&mut output, &mut samples).await {
+                    Ok(()) => return,
+                    Err(e) => tracing::warn!("Failed to write JSON: {e}"),
+                }
+            }
+            Err(e) => tracing::warn!("Failed to open serial port for JSON: {e}"),
+        }
+        backoff.wait().await;
+    }
+}
+
+// Ok once there are no more samples to write
+async fn write(
+    output: &mut Output,
+    samples: &mut broadcast::Receiver<Sample>,
+) -> std::io::Result<()> {
+    loop {
+        let sample = match samples.recv().await {
+            Ok(sample) => sample,
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => return Ok(()),
+        };
+
+        let mut line = serde_json::to_vec(&sample).unwrap();
+        line.push(b'\n');
+        output.write_all(&line).await?;
+        output.flush().await?;
+    }
+}
@@ -0,0 +1,22 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub mod csv;
+#[cfg(target_os = "linux")]
+pub mod dbus;
+pub mod http;
+pub mod influxdb;
+pub mod json;
+pub mod mqtt;
+pub mod otlp;
+pub mod points;
+pub mod sqlite;
+pub mod statsd;
+pub mod webhook;
+
+// Wall clock time samples are recorded with
+pub fn timestamp_ms() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis()
+}
@@ -0,0 +1,252 @@
+use crate::collectors::system_info::SystemInfo;
+use crate::collectors::Sample;
+use rumqttc::{AsyncClient, Event, LastWill, MqttOptions, Packet, QoS};
+use serde::Deserialize;
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct MqttConfig {
+    pub host: String,
+    pub port: u16,
+    pub topic: String,
+    pub client_id: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    // Home Assistant MQTT discovery, the values show up as sensors of one device
+    pub discovery: bool,
+    pub discovery_prefix: String,
+    // Device name in Home Assistant, the hostname by default
+    pub device_name: Option<String>,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        MqttConfig {
+            host: "localhost".to_owned(),
+            port: 1883,
+            topic: "flipper-pc-monitor/state".to_owned(),
+            client_id: "flipper-pc-monitor".to_owned(),
+            username: None,
+            password: None,
+            discovery: true,
+            discovery_prefix: "homeassistant".to_owned(),
+            device_name: None,
+        }
+    }
+}
+
+pub async fn run(config: MqttConfig, mut samples: broadcast::Receiver<Sample>) {
+    let mut options = MqttOptions::new(&config.client_id, &config.host, config.port);
+    options.set_keep_alive(Duration::from_secs(30));
+    if let (Some(username), Some(password)) = (&config.username, &config.password) {
+        options.set_credentials(username, password);
+    }
+
+    // Home Assistant marks the sensors unavailable once the broker notices we're gone
+    let availability = format!("{}/availability", config.topic);
+    if config.discovery {
+        options.set_last_will(LastWill::new(
+            &availability,
+            "offline",
+            QoS::AtLeastOnce,
+            true,
+        ));
+    }
+
+    let (client, mut eventloop) = AsyncClient::new(options, 10);
+
+    // rumqttc only makes progress (and reconnects) while the event loop is polled
+    let discovery = config.discovery.then(|| (client.clone(), config.clone()));
+    tokio::spawn(async move {
+        loop {
+            match eventloop.poll().await {
+                // Again on every reconnect, the broker may have lost the retained messages
+                Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                    if let Some((client, config)) = &discovery {
+                        tokio::spawn(announce(client.clone(), config.clone()));
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    tracing::warn!("MQTT connection error: {e}");
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                }
+            }
+        }
+    });
+
+    tracing::info!(
+        host = %config.host,
+        port = config.port,
+        topic = %config.topic,
+        "Publishing to MQTT"
+    );
+
+    loop {
+        let sample = match samples.recv().await {
+            Ok(sample) => sample,
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+
+        let Some(system) = &sample.system else {
+            continue;
+        };
+
+        let payload = serde_json::to_vec(system).unwrap();
+        if let Err(e) = client
+            .publish(&config.topic, QoS::AtMostOnce, false, payload)
+            .await
+        {
+            tracing::warn!("Failed to publish to MQTT: {e}");
+        }
+    }
+}
+
+struct Sensor {
+    key: &'static str,
+    name: &'static str,
+    unit: Option<&'static str>,
+    device_class: Option<&'static str>,
+    // VALID_* bit the value depends on, 0 if it's always known
+    valid: u16,
+    // Jinja expression for the value, `value_json.<key>` when None
+    value: Option<&'static str>,
+}
+
+const fn sensor(key: &'static str, name: &'static str, unit: &'static str, valid: u16) -> Sensor {
+    Sensor {
+        key,
+        name,
+        unit: Some(unit),
+        device_class: None,
+        valid,
+        value: None,
+    }
+}
+
+const SENSORS: [Sensor; 12] = [
+    sensor("cpu_usage", "CPU usage", "%", SystemInfo::VALID_CPU),
+    Sensor {
+        device_class: Some("frequency"),
+        ..sensor("cpu_freq_avg", "CPU frequency", "MHz", 0)
+    },
+    Sensor {
+        unit: None,
+        value: Some("value_json.load_average[0] / 100"),
+        ..sensor("load_average", "Load average", "", SystemInfo::VALID_LOAD)
+    },
+    sensor("ram_usage", "RAM usage", "%", SystemInfo::VALID_RAM),
+    Sensor {
+        device_class: Some("data_size"),
+        ..sensor("ram_used_mib", "RAM used", "MiB", SystemInfo::VALID_RAM)
+    },
+    sensor("gpu_usage", "GPU usage", "%", SystemInfo::VALID_GPU),
+    sensor("vram_usage", "VRAM usage", "%", SystemInfo::VALID_VRAM),
+    Sensor {
+        device_class: Some("temperature"),
+        ..sensor(
+            "gpu_temp",
+            "GPU temperature",
+            "°C",
+            SystemInfo::VALID_GPU_TEMP,
+        )
+    },
+    Sensor {
+        device_class: Some("power"),
+        value: Some("value_json.gpu_power / 10"),
+        ..sensor("gpu_power", "GPU power", "W", SystemInfo::VALID_GPU_POWER)
+    },
+    Sensor {
+        device_class: Some("frequency"),
+        ..sensor("gpu_clock", "GPU clock", "MHz", SystemInfo::VALID_GPU_CLOCK)
+    },
+    sensor("gpu_fan", "GPU fan", "%", SystemInfo::VALID_GPU_FAN),
+    Sensor {
+        device_class: Some("duration"),
+        ..sensor("uptime", "Uptime", "s", 0)
+    },
+];
+
+// Retained config messages under <discovery_prefix>/sensor/<client_id>/<key>/config, all
+// reading from the state topic
+async fn announce(client: AsyncClient, config: MqttConfig) {
+    let node: String = config
+        .client_id
+        .chars()
+        .map(
+            |c| match c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                true => c,
+                false => '_',
+            },
+        )
+        .collect();
+    let name = config
+        .device_name
+        .clone()
+        .or_else(sysinfo::System::host_name)
+        .unwrap_or_else(|| "PC".to_owned());
+    let availability = format!("{}/availability", config.topic);
+
+    let device = serde_json::json!({
+        "identifiers": [node],
+        "name": name,
+        "manufacturer": "Flipper PC Monitor",
+        "model": "PC",
+        "sw_version": env!("CARGO_PKG_VERSION"),
+    });
+
+    for sensor in &SENSORS {
+        let value = match sensor.value {
+            Some(value) => value.to_owned(),
+            None => format!("value_json.{}", sensor.key),
+        };
+        // Values the collectors couldn't read go to "unknown" instead of 255
+        let template = match sensor.valid {
+            0 => format!("{{{{ {value} }}}}"),
+            bit => {
+                format!("{{{{ ({value}) if value_json.valid | bitwise_and({bit}) else None }}}}")
+            }
+        };
+
+        let mut payload = serde_json::json!({
+            "name": sensor.name,
+            "unique_id": format!("{node}_{}", sensor.key),
+            "state_topic": config.topic,
+            "value_template": template,
+            "state_class": "measurement",
+            "availability_topic": availability,
+            "device": device,
+        });
+        if let Some(unit) = sensor.unit {
+            payload["unit_of_measurement"] = unit.into();
+        }
+        if let Some(device_class) = sensor.device_class {
+            payload["device_class"] = device_class.into();
+        }
+
+        let topic = format!(
+            "{}/sensor/{node}/{}/config",
+            config.discovery_prefix, sensor.key
+        );
+        if let Err(e) = client
+            .publish(topic, QoS::AtLeastOnce, true, payload.to_string())
+            .await
+        {
+            return tracing::warn!("Failed to publish Home Assistant discovery: {e}");
+        }
+    }
+
+    if let Err(e) = client
+        .publish(availability, QoS::AtLeastOnce, true, "online")
+        .await
+    {
+        tracing::warn!("Failed to publish MQTT availability: {e}");
+    }
+    tracing::debug!(
+        sensors = SENSORS.len(),
+        "Published Home Assistant discovery"
+    );
+}
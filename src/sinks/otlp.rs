@@ -0,0 +1,117 @@
+use super::points::{flatten, Point};
+use crate::collectors::Sample;
+use opentelemetry::metrics::{MeterProvider as _, ObservableGauge};
+use opentelemetry::KeyValue;
+use opentelemetry_sdk::metrics::SdkMeterProvider;
+use opentelemetry_sdk::Resource;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct OtlpConfig {
+    // OTLP/gRPC endpoint of the collector
+    pub endpoint: String,
+    // Seconds between exports
+    pub interval: u64,
+    // Resource attributes, e.g. host.name = "desktop"
+    pub resource: BTreeMap<String, String>,
+}
+
+impl Default for OtlpConfig {
+    fn default() -> Self {
+        OtlpConfig {
+            endpoint: "http://localhost:4317".to_owned(),
+            interval: 10,
+            resource: BTreeMap::new(),
+        }
+    }
+}
+
+fn provider(config: &OtlpConfig) -> opentelemetry::metrics::Result<SdkMeterProvider> {
+    // Configured attributes come later and win over the default service.name
+    let resource = Resource::new(
+        std::iter::once(KeyValue::new("service.name", "flipper-pc-monitor")).chain(
+            config
+                .resource
+                .iter()
+                .map(|(key, value)| KeyValue::new(key.clone(), value.clone())),
+        ),
+    );
+
+    opentelemetry_otlp::new_pipeline()
+        .metrics(opentelemetry_sdk::runtime::Tokio)
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(&config.endpoint),
+        )
+        .with_period(Duration::from_secs(config.interval.max(1)))
+        .with_resource(resource)
+        .build()
+}
+
+// Every field becomes a gauge named pc_monitor.<measurement>.<field>, tags become attributes.
+// Gauges are observed at export time and report the latest sample
+pub async fn run(config: OtlpConfig, mut samples: broadcast::Receiver<Sample>) {
+    let provider = match provider(&config) {
+        Ok(provider) => provider,
+        Err(e) => return tracing::error!("Failed to set up OTLP export: {e}"),
+    };
+    let meter = provider.meter("flipper-pc-monitor");
+    tracing::info!(endpoint = %config.endpoint, "Exporting metrics over OTLP");
+
+    let latest: Arc<Mutex<Vec<Point>>> = Arc::default();
+    // Created as new fields show up, e.g. a disk that got mounted
+    let mut gauges: HashMap<String, ObservableGauge<f64>> = HashMap::new();
+    loop {
+        let sample = match samples.recv().await {
+            Ok(sample) => sample,
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => break,
+        };
+
+        let points: Vec<Point> = flatten(&sample)
+            .into_iter()
+            .filter(|point| !point.is_volatile())
+            .collect();
+        for point in &points {
+            for (field, _) in &point.fields {
+                let name = format!("pc_monitor.{}.{field}", point.measurement);
+                if gauges.contains_key(&name) {
+                    continue;
+                }
+
+                let (latest, measurement, field) = (latest.clone(), point.measurement, *field);
+                let gauge = meter
+                    .f64_observable_gauge(name.clone())
+                    .with_callback(move |observer| {
+                        let points = latest.lock().unwrap();
+                        for point in points.iter().filter(|p| p.measurement == measurement) {
+                            let attributes: Vec<KeyValue> = point
+                                .tags
+                                .iter()
+                                .map(|(key, value)| KeyValue::new(*key, value.clone()))
+                                .collect();
+                            for (_, value) in point.fields.iter().filter(|(f, _)| *f == field) {
+                                if let Some(value) = value {
+                                    observer.observe(*value, &attributes);
+                                }
+                            }
+                        }
+                    })
+                    .init();
+                gauges.insert(name, gauge);
+            }
+        }
+
+        *latest.lock().unwrap() = points;
+    }
+
+    if let Err(e) = provider.shutdown() {
+        tracing::warn!("Failed to flush OTLP metrics: {e}");
+    }
+}
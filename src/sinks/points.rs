@@ -0,0 +1,264 @@
+use crate::collectors::system_info::SystemInfo;
+use crate::collectors::Sample;
+use crate::helpers::c_str;
+
+// A sample flattened into plain numbers, for sinks that don't want the packet shaped structs
+pub struct Point {
+    pub measurement: &'static str,
+    pub tags: Vec<(&'static str, String)>,
+    // None when unknown, kept so the same fields show up on every sample
+    pub fields: Vec<(&'static str, Option<f64>)>,
+}
+
+// The busiest processes change from tick to tick, sinks keyed by metric name skip them
+const VOLATILE: [&str; 2] = ["process", "gpu_process"];
+
+impl Point {
+    fn new(measurement: &'static str) -> Self {
+        Point {
+            measurement,
+            tags: Vec::new(),
+            fields: Vec::new(),
+        }
+    }
+
+    fn tag(mut self, key: &'static str, value: String) -> Self {
+        self.tags.push((key, value));
+        self
+    }
+
+    fn field(mut self, key: &'static str, value: Option<f64>) -> Self {
+        self.fields
+            .push((key, value.filter(|value| value.is_finite())));
+        self
+    }
+
+    pub fn is_volatile(&self) -> bool {
+        VOLATILE.contains(&self.measurement)
+    }
+
+    // Name of one field, e.g. "system.cpu_usage" or "disk[/home].usage"
+    pub fn column(&self, field: &str) -> String {
+        let tags: String = self
+            .tags
+            .iter()
+            .map(|(_, value)| format!("[{value}]"))
+            .collect();
+        format!("{}{tags}.{field}", self.measurement)
+    }
+}
+
+// u8::MAX/u16::MAX/u32::MAX stand for unknown throughout a sample
+fn known<T: Into<f64> + PartialEq>(value: T, unknown: T) -> Option<f64> {
+    (value != unknown).then(|| value.into())
+}
+
+pub fn flatten(sample: &Sample) -> Vec<Point> {
+    let mut points = Vec::new();
+
+    if let Some(system) = &sample.system {
+        let load = |i: usize| {
+            system
+                .load_average
+                .filter(|_| system.valid & SystemInfo::VALID_LOAD != 0)
+                .map(|load| load[i] as f64 / 100.0)
+        };
+
+        // Unknown unless the matching VALID_* bit is set
+        let checked = [
+            ("cpu_usage", SystemInfo::VALID_CPU, system.cpu_usage as f64),
+            ("ram_usage", SystemInfo::VALID_RAM, system.ram_usage as f64),
+            (
+                "ram_used_mib",
+                SystemInfo::VALID_RAM,
+                system.ram_used_mib as f64,
+            ),
+            (
+                "ram_total_mib",
+                SystemInfo::VALID_RAM,
+                system.ram_total_mib as f64,
+            ),
+            ("gpu_usage", SystemInfo::VALID_GPU, system.gpu_usage as f64),
+            (
+                "vram_usage",
+                SystemInfo::VALID_VRAM,
+                system.vram_usage as f64,
+            ),
+            (
+                "vram_used_mib",
+                SystemInfo::VALID_VRAM,
+                system.vram_used_mib as f64,
+            ),
+            (
+                "vram_total_mib",
+                SystemInfo::VALID_VRAM,
+                system.vram_total_mib as f64,
+            ),
+            (
+                "gpu_temp",
+                SystemInfo::VALID_GPU_TEMP,
+                system.gpu_temp as f64,
+            ),
+            (
+                "gpu_power",
+                SystemInfo::VALID_GPU_POWER,
+                system.gpu_power as f64 / 10.0,
+            ),
+            (
+                "gpu_clock",
+                SystemInfo::VALID_GPU_CLOCK,
+                system.gpu_clock as f64,
+            ),
+            (
+                "vram_clock",
+                SystemInfo::VALID_GPU_CLOCK,
+                system.vram_clock as f64,
+            ),
+            ("gpu_fan", SystemInfo::VALID_GPU_FAN, system.gpu_fan as f64),
+        ];
+        let mut system_point = Point::new("system");
+        for (key, flag, value) in checked {
+            system_point = system_point.field(key, (system.valid & flag != 0).then_some(value));
+        }
+
+        points.push(
+            system_point
+                .field("gpu_encoder", known(system.gpu_encoder, u8::MAX))
+                .field("gpu_decoder", known(system.gpu_decoder, u8::MAX))
+                .field("load1", load(0))
+                .field("load5", load(1))
+                .field("load15", load(2))
+                .field("cpu_freq_avg", Some(system.cpu_freq_avg.into()))
+                .field("cpu_freq_max", Some(system.cpu_freq_max.into()))
+                .field("uptime", Some(system.uptime.into())),
+        );
+
+        for (core, usage) in system.core_usage.iter().enumerate() {
+            points.push(
+                Point::new("cpu_core")
+                    .tag("core", core.to_string())
+                    .field("usage", Some((*usage).into())),
+            );
+        }
+    }
+
+    for disk in sample.disks.iter().flatten() {
+        points.push(
+            Point::new("disk")
+                .tag("label", c_str(&disk.label))
+                .field("usage", Some(disk.disk_usage.into()))
+                .field("total_bytes", Some(disk.disk_bytes as f64)),
+        );
+    }
+
+    if let Some(network) = &sample.network {
+        points.push(
+            Point::new("network")
+                .field("rx_bytes", Some(network.rx_bytes as f64))
+                .field("tx_bytes", Some(network.tx_bytes as f64)),
+        );
+    }
+
+    if let Some(battery) = &sample.battery {
+        points.push(
+            Point::new("battery")
+                .field("level", Some(battery.level.into()))
+                .field("charging", Some(battery.charging.into()))
+                .field("time_remaining", known(battery.time_remaining, u16::MAX)),
+        );
+    }
+
+    for process in sample.processes.iter().flatten() {
+        points.push(
+            Point::new("process")
+                .tag("name", c_str(&process.name))
+                .field("cpu_usage", Some(process.cpu_usage.into()))
+                .field("ram_usage", Some(process.ram_usage.into())),
+        );
+    }
+
+    for process in sample.gpu_processes.iter().flatten() {
+        points.push(
+            Point::new("gpu_process")
+                .tag("name", c_str(&process.name))
+                .field("vram_used", Some(process.vram_used.into())),
+        );
+    }
+
+    for fan in sample.fans.iter().flatten() {
+        points.push(
+            Point::new("fan")
+                .tag("label", c_str(&fan.label))
+                .field("rpm", Some(fan.rpm.into())),
+        );
+    }
+
+    for temp in sample.temps.iter().flatten() {
+        points.push(
+            Point::new("temp")
+                .tag("label", c_str(&temp.label))
+                .field("temp", known(temp.temp, u8::MAX)),
+        );
+    }
+
+    for drive in sample.smart.iter().flatten() {
+        points.push(
+            Point::new("smart")
+                .tag("label", c_str(&drive.label))
+                .field("temp", known(drive.temp, u8::MAX))
+                .field("status", Some(drive.status.into())),
+        );
+    }
+
+    if let Some(ups) = &sample.ups {
+        points.push(
+            Point::new("ups")
+                .field("charge", known(ups.charge, u8::MAX))
+                .field("load", known(ups.load, u8::MAX))
+                .field("on_battery", known(ups.on_battery, u8::MAX)),
+        );
+    }
+
+    if let Some(power) = &sample.power {
+        points.push(
+            Point::new("power")
+                .field(
+                    "cpu_power",
+                    known(power.cpu_power, u16::MAX).map(|w| w / 10.0),
+                )
+                .field(
+                    "gpu_power",
+                    known(power.gpu_power, u16::MAX).map(|w| w / 10.0),
+                ),
+        );
+        for cluster in &power.clusters {
+            points.push(
+                Point::new("cpu_cluster")
+                    .tag("name", c_str(&cluster.name))
+                    .field("usage", Some(cluster.usage.into())),
+            );
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    if let Some(rpi) = &sample.rpi {
+        points.push(
+            Point::new("rpi")
+                .field("temp", known(rpi.temp, u8::MAX))
+                .field("throttled", Some(rpi.throttled.into()))
+                .field("core_voltage", Some(rpi.core_voltage.into()))
+                .field("gpu_mem", Some(rpi.gpu_mem.into())),
+        );
+    }
+
+    for sensor in sample.sensors.iter().flatten() {
+        points.push(
+            Point::new("sensor")
+                .tag("label", c_str(&sensor.label))
+                .tag("unit", c_str(&sensor.unit))
+                .field("value", Some(sensor.value.into())),
+        );
+    }
+
+    points
+}
@@ -0,0 +1,211 @@
+use super::points::flatten;
+use super::timestamp_ms;
+use crate::collectors::Sample;
+use rusqlite::{params, Connection, OpenFlags};
+use serde::Deserialize;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+
+const PRUNE_EVERY: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct SqliteConfig {
+    // <data dir>/flipper-pc-monitor/history.db when unset
+    pub path: Option<PathBuf>,
+    // Seconds between recorded samples
+    pub every: u64,
+    // Days samples are kept for
+    pub retention_days: u64,
+}
+
+impl Default for SqliteConfig {
+    fn default() -> Self {
+        SqliteConfig {
+            path: None,
+            every: 10,
+            retention_days: 7,
+        }
+    }
+}
+
+impl SqliteConfig {
+    pub fn path(&self) -> Option<PathBuf> {
+        match &self.path {
+            Some(path) => Some(path.clone()),
+            None => Some(
+                dirs::data_dir()?
+                    .join("flipper-pc-monitor")
+                    .join("history.db"),
+            ),
+        }
+    }
+}
+
+fn open(path: &Path) -> rusqlite::Result<Connection> {
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+
+    let db = Connection::open(path)?;
+    db.execute_batch(
+        "CREATE TABLE IF NOT EXISTS samples (
+            timestamp INTEGER NOT NULL,  -- milliseconds since the Unix epoch
+            metric TEXT NOT NULL,        -- e.g. system.cpu_usage or disk[/home].usage
+            value REAL NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS samples_metric_timestamp ON samples (metric, timestamp);",
+    )?;
+    Ok(db)
+}
+
+pub async fn run(config: SqliteConfig, mut samples: broadcast::Receiver<Sample>) {
+    let Some(path) = config.path() else {
+        return tracing::error!("No data directory for the history database, set sqlite.path");
+    };
+
+    // rusqlite blocks, so the database gets a thread of its own
+    let (sender, receiver) = mpsc::channel();
+    // Absurd retentions just mean forever
+    let retention = Duration::from_secs(config.retention_days.saturating_mul(24 * 60 * 60));
+    std::thread::spawn(move || record(&path, retention, receiver));
+
+    let every = Duration::from_secs(config.every);
+    let mut recorded: Option<Instant> = None;
+    loop {
+        let sample = match samples.recv().await {
+            Ok(sample) => sample,
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+
+        if recorded.is_some_and(|recorded| recorded.elapsed() < every) {
+            continue;
+        }
+        recorded = Some(Instant::now());
+
+        // The thread only stops when the database couldn't be opened
+        if sender.send(sample).is_err() {
+            return;
+        }
+    }
+}
+
+fn record(path: &Path, retention: Duration, samples: mpsc::Receiver<Sample>) {
+    let mut db = match open(path) {
+        Ok(db) => db,
+        Err(e) => {
+            return tracing::error!(path = %path.display(), "Failed to open history database: {e}");
+        }
+    };
+    tracing::info!(path = %path.display(), "Recording history");
+
+    let mut pruned: Option<Instant> = None;
+    for sample in samples {
+        let timestamp = timestamp_ms() as i64;
+        if let Err(e) = insert(&mut db, &sample, timestamp) {
+            tracing::warn!("Failed to record sample: {e}");
+        }
+
+        if pruned.is_some_and(|pruned| pruned.elapsed() < PRUNE_EVERY) {
+            continue;
+        }
+        pruned = Some(Instant::now());
+        let retention = i64::try_from(retention.as_millis()).unwrap_or(i64::MAX);
+        let cutoff = timestamp.saturating_sub(retention);
+        match db.execute("DELETE FROM samples WHERE timestamp < ?1", params![cutoff]) {
+            Ok(0) => {}
+            Ok(deleted) => tracing::debug!(deleted, "Pruned old history"),
+            Err(e) => tracing::warn!("Failed to prune history: {e}"),
+        }
+    }
+}
+
+fn insert(db: &mut Connection, sample: &Sample, timestamp: i64) -> rusqlite::Result<()> {
+    let transaction = db.transaction()?;
+    {
+        let mut insert = transaction
+            .prepare_cached("INSERT INTO samples (timestamp, metric, value) VALUES (?1, ?2, ?3)")?;
+        for point in flatten(sample).iter().filter(|point| !point.is_volatile()) {
+            for (field, value) in &point.fields {
+                if let Some(value) = value {
+                    insert.execute(params![timestamp, point.column(field), value])?;
+                }
+            }
+        }
+    }
+    transaction.commit()
+}
+
+// `history` subcommand, metrics whose name contains `metric` over the last `last`
+pub fn query(
+    config: &SqliteConfig,
+    metric: &str,
+    last: Duration,
+    raw: bool,
+) -> Result<(), Box<dyn Error>> {
+    let path = config.path().ok_or("No data directory, set sqlite.path")?;
+    if !path.exists() {
+        return Err(format!(
+            "No history at {}, enable [sqlite] in the config first",
+            path.display()
+        )
+        .into());
+    }
+
+    let db = Connection::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let since = timestamp_ms() as i64 - last.as_millis() as i64;
+
+    if raw {
+        let mut statement = db.prepare(
+            "SELECT timestamp, metric, value FROM samples
+            WHERE instr(metric, ?1) > 0 AND timestamp >= ?2 ORDER BY metric, timestamp",
+        )?;
+        let rows = statement.query_map(params![metric, since], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, f64>(2)?,
+            ))
+        })?;
+
+        println!("timestamp_ms,metric,value");
+        for row in rows {
+            let (timestamp, metric, value) = row?;
+            println!("{timestamp},{metric},{value}");
+        }
+        return Ok(());
+    }
+
+    let mut statement = db.prepare(
+        "SELECT metric, COUNT(*), MIN(value), AVG(value), MAX(value) FROM samples
+        WHERE instr(metric, ?1) > 0 AND timestamp >= ?2 GROUP BY metric ORDER BY metric",
+    )?;
+    let rows = statement
+        .query_map(params![metric, since], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, u64>(1)?,
+                row.get::<_, f64>(2)?,
+                row.get::<_, f64>(3)?,
+                row.get::<_, f64>(4)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if rows.is_empty() {
+        return Err(format!("No samples matching \"{metric}\" in that time").into());
+    }
+
+    println!(
+        "{:<32} {:>8} {:>10} {:>10} {:>10}",
+        "metric", "samples", "min", "avg", "max"
+    );
+    for (metric, count, min, avg, max) in rows {
+        println!("{metric:<32} {count:>8} {min:>10.1} {avg:>10.1} {max:>10.1}");
+    }
+    Ok(())
+}
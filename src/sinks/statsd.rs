@@ -0,0 +1,218 @@
+use super::points::{flatten, Point};
+use crate::collectors::Sample;
+use serde::Deserialize;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use tokio::net::UdpSocket;
+use tokio::sync::broadcast;
+
+// Keeps datagrams under a typical Ethernet MTU so they aren't fragmented
+const MAX_DATAGRAM: usize = 1432;
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct StatsdConfig {
+    // host:port of the StatsD server or agent
+    pub address: String,
+    pub prefix: String,
+    // DogStatsD style |#key:value tags instead of tag values in the metric name
+    pub tags: bool,
+}
+
+impl Default for StatsdConfig {
+    fn default() -> Self {
+        StatsdConfig {
+            address: "127.0.0.1:8125".to_owned(),
+            prefix: "pc_monitor".to_owned(),
+            tags: false,
+        }
+    }
+}
+
+pub async fn run(config: StatsdConfig, mut samples: broadcast::Receiver<Sample>) {
+    let target = match tokio::net::lookup_host(&config.address).await {
+        Ok(mut addresses) => addresses.next(),
+        Err(e) => {
+            let address = &config.address;
+            return tracing::error!(%address, "Failed to resolve StatsD server: {e}");
+        }
+    };
+    let Some(target) = target else {
+        return tracing::error!(address = %config.address, "StatsD server has no addresses");
+    };
+
+    let local: SocketAddr = match target {
+        SocketAddr::V4(_) => ([0, 0, 0, 0], 0).into(),
+        SocketAddr::V6(_) => ([0u16; 8], 0).into(),
+    };
+    let socket = match UdpSocket::bind(local).await {
+        Ok(socket) => socket,
+        Err(e) => return tracing::error!("Failed to open StatsD socket: {e}"),
+    };
+    if let Err(e) = socket.connect(target).await {
+        return tracing::error!(%target, "Failed to connect StatsD socket: {e}");
+    }
+
+    tracing::info!(%target, prefix = %config.prefix, "Sending gauges to StatsD");
+
+    loop {
+        let sample = match samples.recv().await {
+            Ok(sample) => sample,
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+
+        let lines = flatten(&sample)
+            .iter()
+            // Process names change every tick and would leave behind a gauge for each one
+            .filter(|point| !point.is_volatile())
+            .flat_map(|point| gauges(point, &config))
+            .collect::<Vec<_>>();
+
+        for datagram in datagrams(&lines) {
+            // Nothing listening just means ICMP errors, which aren't worth more than a debug line
+            if let Err(e) = socket.send(datagram.as_bytes()).await {
+                tracing::debug!("Failed to send to StatsD: {e}");
+            }
+        }
+    }
+}
+
+// Graphite and most StatsD backends split names on dots and dislike anything but
+// alphanumerics, dashes and underscores
+fn sanitize(value: &str) -> String {
+    let value: String = value
+        .chars()
+        .map(|c| match c.is_ascii_alphanumeric() || c == '-' {
+            true => c,
+            false => '_',
+        })
+        .collect();
+    match value.trim_matches('_') {
+        // e.g. the "/" mount point
+        "" => "root".to_owned(),
+        value => value.to_owned(),
+    }
+}
+
+fn gauges(point: &Point, config: &StatsdConfig) -> Vec<String> {
+    let mut name = format!("{}.{}", config.prefix, point.measurement);
+    let mut tags = String::new();
+    for (key, value) in &point.tags {
+        match config.tags {
+            true => {
+                let separator = if tags.is_empty() { "|#" } else { "," };
+                let _ = write!(tags, "{separator}{key}:{}", sanitize(value));
+            }
+            false => {
+                let _ = write!(name, ".{}", sanitize(value));
+            }
+        }
+    }
+
+    let mut gauges = Vec::new();
+    for (field, value) in &point.fields {
+        let Some(value) = value else {
+            continue;
+        };
+        // A leading sign means "change by", a negative gauge has to be set from zero. Both
+        // go out as one line so they can't end up in different datagrams
+        gauges.push(match *value < 0.0 {
+            true => format!("{name}.{field}:0|g{tags}\n{name}.{field}:{value}|g{tags}"),
+            false => format!("{name}.{field}:{value}|g{tags}"),
+        });
+    }
+    gauges
+}
+
+// Newline separated lines packed into as few datagrams as fit
+fn datagrams(lines: &[String]) -> Vec<String> {
+    let mut datagrams = Vec::new();
+    let mut current = String::new();
+    for line in lines {
+        if !current.is_empty() && current.len() + 1 + line.len() > MAX_DATAGRAM {
+            datagrams.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push('\n');
+        }
+        current.push_str(line);
+    }
+    if !current.is_empty() {
+        datagrams.push(current);
+    }
+    datagrams
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point() -> Point {
+        Point {
+            measurement: "disk",
+            tags: vec![("label", "/".to_owned()), ("name", "My Disk.1".to_owned())],
+            fields: vec![("usage", Some(40.0)), ("delta", Some(-2.5)), ("free", None)],
+        }
+    }
+
+    #[test]
+    fn sanitizes_names() {
+        assert_eq!(sanitize("sda1"), "sda1");
+        assert_eq!(sanitize("My Disk.1"), "My_Disk_1");
+        assert_eq!(sanitize("/home/"), "home");
+        assert_eq!(sanitize("/"), "root");
+        assert_eq!(sanitize("nvme-0"), "nvme-0");
+    }
+
+    #[test]
+    fn gauges_in_the_name_or_as_tags() {
+        let config = StatsdConfig::default();
+        assert_eq!(
+            gauges(&point(), &config),
+            [
+                "pc_monitor.disk.root.My_Disk_1.usage:40|g",
+                "pc_monitor.disk.root.My_Disk_1.delta:0|g\npc_monitor.disk.root.My_Disk_1.delta:-2.5|g",
+            ]
+        );
+
+        let config = StatsdConfig {
+            tags: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            gauges(&point(), &config)[0],
+            "pc_monitor.disk.usage:40|g|#label:root,name:My_Disk_1"
+        );
+    }
+
+    #[test]
+    fn datagrams_split_at_the_mtu() {
+        assert!(datagrams(&[]).is_empty());
+
+        let line = "x".repeat(700);
+        let lines = vec![line.clone(), line.clone(), line.clone()];
+        let packed = datagrams(&lines);
+        assert_eq!(packed, [format!("{line}\n{line}"), line.clone()]);
+        assert!(packed.iter().all(|datagram| datagram.len() <= MAX_DATAGRAM));
+
+        // Exactly full still fits, one more byte doesn't
+        let first = "a".repeat(MAX_DATAGRAM - 2);
+        assert_eq!(datagrams(&[first.clone(), "b".to_owned()]).len(), 1);
+        let first = "a".repeat(MAX_DATAGRAM - 1);
+        assert_eq!(datagrams(&[first, "b".to_owned()]).len(), 2);
+    }
+
+    #[test]
+    fn negative_gauges_stay_in_one_datagram() {
+        let filler = "x".repeat(MAX_DATAGRAM - 20);
+        let lines = [
+            filler,
+            gauges(&point(), &StatsdConfig::default())[1].clone(),
+        ];
+        let packed = datagrams(&lines);
+        assert_eq!(packed.len(), 2);
+        assert!(packed[1].starts_with("pc_monitor.disk.root.My_Disk_1.delta:0|g\n"));
+        assert!(packed[1].ends_with(":-2.5|g"));
+    }
+}
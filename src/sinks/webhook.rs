@@ -0,0 +1,262 @@
+use crate::alerts::{Alert, AlertConfig, Alerts};
+use crate::collectors::Sample;
+use crate::transport::backoff::Backoff;
+use crate::transport::{ConnectionState, Link};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+// BLE drops out for a few seconds now and then, only a longer gap counts as unreachable
+const UNREACHABLE_AFTER: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookFormat {
+    // Plain JSON, see `payload`
+    Json,
+    // Discord channel webhook, an embed per event
+    Discord,
+    // Slack incoming webhook
+    Slack,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct WebhookConfig {
+    pub url: String,
+    pub format: WebhookFormat,
+    // Extra request headers, e.g. an Authorization or X-Gotify-Key token
+    pub headers: BTreeMap<String, String>,
+    // Further attempts after a failed POST, with backoff in between
+    pub retries: u32,
+    // Also post when the Flipper hasn't been reachable for a while and when it's back
+    pub connection: bool,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        WebhookConfig {
+            url: String::new(),
+            format: WebhookFormat::Json,
+            headers: BTreeMap::new(),
+            retries: 3,
+            connection: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Event {
+    Alert(Alert),
+    Unreachable,
+    Reachable,
+}
+
+impl Event {
+    fn is_connection(&self) -> bool {
+        matches!(self, Event::Unreachable | Event::Reachable)
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Event::Alert(_) => "alert",
+            Event::Unreachable => "unreachable",
+            Event::Reachable => "reachable",
+        }
+    }
+
+    fn is_problem(&self) -> bool {
+        match self {
+            Event::Alert(alert) => alert.active,
+            Event::Unreachable => true,
+            Event::Reachable => false,
+        }
+    }
+
+    fn describe(&self, host: &str) -> (String, String) {
+        match self {
+            Event::Alert(alert) => {
+                let label = alert.kind.label();
+                let unit = alert.kind.unit();
+                match alert.active {
+                    true => (
+                        format!("{label} high on {host}"),
+                        format!(
+                            "{label} is {}{unit}, above the {}{unit} threshold",
+                            alert.value, alert.threshold
+                        ),
+                    ),
+                    false => (
+                        format!("{label} back to normal on {host}"),
+                        format!("{label} is down to {}{unit}", alert.value),
+                    ),
+                }
+            }
+            Event::Unreachable => (
+                format!("Flipper lost {host}"),
+                format!(
+                    "No Flipper has been connected to {host} for {} seconds",
+                    UNREACHABLE_AFTER.as_secs()
+                ),
+            ),
+            Event::Reachable => (
+                format!("Flipper reconnected to {host}"),
+                format!("{host} is sending updates to the Flipper again"),
+            ),
+        }
+    }
+}
+
+// Checks the [alerts] thresholds on its own, so webhooks fire whether or not a Flipper
+// is connected
+pub async fn run(
+    webhooks: Vec<WebhookConfig>,
+    thresholds: AlertConfig,
+    link: Link,
+    mut samples: broadcast::Receiver<Sample>,
+) {
+    let client = match reqwest::Client::builder().timeout(TIMEOUT).build() {
+        Ok(client) => client,
+        Err(e) => return tracing::error!("Failed to create webhook client: {e}"),
+    };
+    let host = sysinfo::System::host_name().unwrap_or_default();
+    let mut alerts = Alerts::new(thresholds);
+
+    for webhook in &webhooks {
+        tracing::info!(url = %webhook.url, format = ?webhook.format, "Sending alerts to webhook");
+    }
+
+    let send = |event: Event| {
+        // Retries shouldn't hold up the next event or the other webhooks
+        for webhook in webhooks
+            .iter()
+            .filter(|webhook| webhook.connection || !event.is_connection())
+        {
+            let body = payload(webhook.format, &event, &host);
+            tokio::spawn(post(client.clone(), webhook.clone(), body));
+        }
+    };
+
+    let mut state = link.state();
+    let mut connected = *state.borrow_and_update() == ConnectionState::Connected;
+    // Only a Flipper that was there before can lose the host
+    let mut lost_at: Option<tokio::time::Instant> = None;
+    let mut unreachable = false;
+    loop {
+        let deadline = lost_at
+            .filter(|_| !unreachable)
+            .map(|at| at + UNREACHABLE_AFTER);
+        tokio::select! {
+            sample = samples.recv() => match sample {
+                Ok(sample) => {
+                    for alert in alerts.check(&sample) {
+                        send(Event::Alert(alert));
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return,
+            },
+            changed = state.changed() => {
+                if changed.is_err() {
+                    return;
+                }
+                let now_connected = *state.borrow_and_update() == ConnectionState::Connected;
+                match (connected, now_connected) {
+                    // Disconnecting on purpose during shutdown isn't worth a message
+                    (true, false) if !link.is_closed() => {
+                        lost_at = Some(tokio::time::Instant::now());
+                    }
+                    (false, true) => {
+                        if unreachable {
+                            send(Event::Reachable);
+                        }
+                        lost_at = None;
+                        unreachable = false;
+                    }
+                    _ => {}
+                }
+                connected = now_connected;
+            },
+            _ = sleep_until(deadline) => {
+                unreachable = true;
+                send(Event::Unreachable);
+            }
+        }
+    }
+}
+
+async fn sleep_until(deadline: Option<tokio::time::Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+fn payload(format: WebhookFormat, event: &Event, host: &str) -> Vec<u8> {
+    let (title, message) = event.describe(host);
+    let body = match format {
+        // title and message are what Gotify expects, the rest is for scripts
+        WebhookFormat::Json => {
+            let mut body = serde_json::json!({
+                "title": title,
+                "message": message,
+                "host": host,
+                "event": event.name(),
+                "timestamp_ms": super::timestamp_ms() as u64,
+            });
+            if let Event::Alert(alert) = event {
+                body["alert"] = alert.kind.name().into();
+                body["active"] = alert.active.into();
+                body["value"] = alert.value.into();
+                body["threshold"] = alert.threshold.into();
+            }
+            body
+        }
+        WebhookFormat::Discord => serde_json::json!({
+            "username": "Flipper PC Monitor",
+            "embeds": [{
+                "title": title,
+                "description": message,
+                // Red for problems, green once they're gone
+                "color": match event.is_problem() {
+                    true => 0xd9534f,
+                    false => 0x5cb85c,
+                },
+            }],
+        }),
+        WebhookFormat::Slack => serde_json::json!({
+            "text": format!("*{title}*\n{message}"),
+        }),
+    };
+    serde_json::to_vec(&body).unwrap()
+}
+
+async fn post(client: reqwest::Client, webhook: WebhookConfig, body: Vec<u8>) {
+    let mut backoff = Backoff::new();
+    for attempt in 0..=webhook.retries {
+        if attempt > 0 {
+            backoff.wait().await;
+        }
+
+        let mut request = client
+            .post(&webhook.url)
+            .header("Content-Type", "application/json")
+            .body(body.clone());
+        for (name, value) in &webhook.headers {
+            request = request.header(name, value);
+        }
+
+        match request.send().await.and_then(|r| r.error_for_status()) {
+            Ok(_) => return,
+            // Sending the same payload again won't change the answer
+            Err(e) if e.status().is_some_and(|s| s.is_client_error()) => {
+                return tracing::warn!(url = %webhook.url, "Webhook rejected the message: {e}");
+            }
+            Err(e) => tracing::debug!(url = %webhook.url, attempt, "Webhook failed: {e}"),
+        }
+    }
+    let attempts = webhook.retries + 1;
+    tracing::warn!(url = %webhook.url, "Giving up on webhook after {attempts} attempts");
+}
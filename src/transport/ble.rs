@@ -0,0 +1,473 @@
+use super::backoff::Backoff;
+use super::{BleConfig, ConnectionState, Link};
+use crate::helpers::glob_match;
+use crate::protocol::chunking::{Chunker, Reassembler};
+use btleplug::api::{
+    Central, CentralEvent, Manager as _, Peripheral as _, PeripheralProperties, ScanFilter,
+};
+use btleplug::platform::{Adapter, Manager, Peripheral, PeripheralId};
+use futures::stream::StreamExt;
+use std::collections::HashMap;
+use std::error::Error;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use tracing::Instrument;
+use uuid::Uuid;
+
+// How long `nearest` keeps scanning for other Flippers after finding the first
+const SELECT_WINDOW: Duration = Duration::from_secs(3);
+
+pub async fn get_central(manager: &Manager) -> Result<Adapter, Box<dyn Error>> {
+    manager
+        .adapters()
+        .await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| "No Bluetooth adapter found".into())
+}
+
+fn is_flipper(props: &PeripheralProperties, service: Uuid) -> bool {
+    // Older firmwares don't put the serial service into the advertisement,
+    // so the app name is still accepted as a fallback
+    props.services.contains(&service) || props.local_name.iter().any(|name| name.contains("PC Mon"))
+}
+
+fn is_allowed(p: &Peripheral, props: &PeripheralProperties, devices: &[String]) -> bool {
+    // macOS hides MAC addresses, so the peripheral id is accepted as well. Names may be
+    // patterns like "PC Mon*"
+    devices.is_empty()
+        || devices.iter().any(|device| {
+            props.address.to_string().eq_ignore_ascii_case(device)
+                || p.id().to_string().eq_ignore_ascii_case(device)
+                || props
+                    .local_name
+                    .as_ref()
+                    .is_some_and(|name| glob_match(device, name))
+        })
+}
+
+pub async fn get_flipper(
+    central: &Adapter,
+    id: &PeripheralId,
+    devices: &[String],
+    config: &BleConfig,
+) -> Option<Peripheral> {
+    let peripherals = match central.peripherals().await {
+        Ok(peripherals) => peripherals,
+        Err(e) => {
+            tracing::debug!(%id, "Failed to list peripherals: {e}");
+            return None;
+        }
+    };
+    for p in peripherals.iter().filter(|p| p.id() == *id) {
+        let Ok(Some(props)) = p.properties().await else {
+            continue;
+        };
+
+        if is_flipper(&props, config.service_uuid) && is_allowed(p, &props, devices) {
+            return Some(p.clone());
+        }
+    }
+    None
+}
+
+// The system negotiates the MTU on connect, btleplug just has no way to read it
+async fn mtu(flipper: &Peripheral, config: &BleConfig) -> u16 {
+    #[cfg(target_os = "linux")]
+    if let Ok(Some(props)) = flipper.properties().await {
+        let address = props.address.to_string();
+        if let Some(mtu) = super::bluez::mtu(&address, config.characteristic_uuid).await {
+            return mtu;
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = flipper;
+    config.mtu
+}
+
+async fn data_sender(
+    flipper: Peripheral,
+    mut packets: broadcast::Receiver<Vec<u8>>,
+    link: Link,
+    config: BleConfig,
+) {
+    let chars = flipper.characteristics();
+    let cmd_char = match chars.iter().find(|c| c.uuid == config.characteristic_uuid) {
+        Some(c) => c,
+        None => {
+            return tracing::error!("Failed to find characteristic");
+        }
+    };
+    let mut chunker = match config.chunking {
+        true => {
+            let mtu = mtu(&flipper, &config).await;
+            tracing::info!(mtu, "Chunking packets that don't fit into a single write");
+            Some(Chunker::new(mtu))
+        }
+        false => None,
+    };
+    tracing::info!("Sending data...");
+
+    loop {
+        let packet = tokio::select! {
+            // Queued packets first, the goodbye is queued right before closing
+            biased;
+            packet = packets.recv() => match packet {
+                Ok(packet) => packet,
+                // Only the latest data matters, skip whatever we fell behind on
+                Err(broadcast::error::RecvError::Lagged(_)) => {
+                    link.quality().record_lagged();
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => return,
+            },
+            _ = link.closed() => {
+                if let Err(e) = flipper.disconnect().await {
+                    tracing::warn!("Failed to disconnect: {e}");
+                }
+                return;
+            }
+        };
+
+        let writes = match &mut chunker {
+            Some(chunker) => chunker.split(packet),
+            None => vec![packet],
+        };
+        let started = Instant::now();
+        let mut written = Ok(());
+        for write in writes {
+            written = flipper
+                .write(cmd_char, &write, btleplug::api::WriteType::WithoutResponse)
+                .await;
+            if written.is_err() {
+                break;
+            }
+        }
+        link.quality()
+            .record_write(started.elapsed(), written.is_ok());
+        if let Err(e) = written {
+            tracing::warn!("Failed to write: {e}");
+        };
+    }
+}
+
+async fn command_reader(flipper: Peripheral, link: Link, config: BleConfig) {
+    let Some(tx_char) = flipper
+        .characteristics()
+        .into_iter()
+        .find(|c| c.uuid == config.tx_characteristic_uuid)
+    else {
+        return tracing::warn!("Flipper doesn't expose a command characteristic");
+    };
+
+    if let Err(e) = flipper.subscribe(&tx_char).await {
+        return tracing::warn!("Failed to subscribe to commands: {e}");
+    }
+
+    let mut notifications = match flipper.notifications().await {
+        Ok(notifications) => notifications,
+        Err(e) => return tracing::warn!("Failed to read commands: {e}"),
+    };
+
+    let mut reassembler = config.chunking.then(Reassembler::default);
+    loop {
+        let notification = tokio::select! {
+            notification = notifications.next() => match notification {
+                Some(notification) => notification,
+                None => return,
+            },
+            _ = link.closed() => return,
+        };
+        if notification.uuid != tx_char.uuid {
+            continue;
+        }
+        match &mut reassembler {
+            Some(reassembler) => {
+                if let Some(command) = reassembler.push(&notification.value) {
+                    link.commands().dispatch(&command);
+                }
+            }
+            None => link.commands().dispatch(&notification.value),
+        }
+    }
+}
+
+// Runs until the DeviceConnected event aborts it
+async fn reconnect_thread(
+    central: Adapter,
+    id: PeripheralId,
+    devices: Vec<String>,
+    config: BleConfig,
+) {
+    let mut backoff = Backoff::new();
+    loop {
+        backoff.wait().await;
+
+        // The adapter may have stopped scanning while we were connected
+        if let Err(e) = central.start_scan(ScanFilter::default()).await {
+            tracing::debug!(%id, "Failed to restart scanning: {e}");
+        }
+
+        if let Some(flipper) = get_flipper(&central, &id, &devices, &config).await {
+            if let Err(e) = flipper.connect().await {
+                tracing::debug!(%id, "Reconnection failed: {e}");
+            }
+        };
+    }
+}
+
+pub async fn scan(duration: Duration, config: &BleConfig) -> Result<(), Box<dyn Error>> {
+    let manager = Manager::new().await?;
+    let central = get_central(&manager).await?;
+
+    println!(
+        "Scanning for {}s... Launch PC Monitor app on Flipper",
+        duration.as_secs()
+    );
+    central.start_scan(ScanFilter::default()).await?;
+    tokio::time::sleep(duration).await;
+    central.stop_scan().await?;
+
+    for p in central.peripherals().await? {
+        let Ok(Some(props)) = p.properties().await else {
+            continue;
+        };
+
+        if !is_flipper(&props, config.service_uuid) {
+            continue;
+        }
+
+        println!(
+            "[{}] {} address={} rssi={}",
+            p.id(),
+            props.local_name.as_deref().unwrap_or("?"),
+            props.address,
+            props.rssi.map_or("?".to_owned(), |rssi| rssi.to_string()),
+        );
+    }
+
+    Ok(())
+}
+
+// Address of the first allowed Flipper seen within `timeout`, for `pair`
+pub async fn find(
+    devices: &[String],
+    timeout: Duration,
+    config: &BleConfig,
+) -> Result<Option<String>, Box<dyn Error>> {
+    let manager = Manager::new().await?;
+    let central = get_central(&manager).await?;
+    let mut events = central.events().await?;
+
+    println!("Scanning... Launch PC Monitor app on Flipper");
+    central.start_scan(ScanFilter::default()).await?;
+    let found = tokio::time::timeout(timeout, async {
+        while let Some(event) = events.next().await {
+            let CentralEvent::DeviceDiscovered(id) = event else {
+                continue;
+            };
+            let Some(flipper) = get_flipper(&central, &id, devices, config).await else {
+                continue;
+            };
+            if let Ok(Some(props)) = flipper.properties().await {
+                return Some(props.address.to_string());
+            }
+        }
+        None
+    })
+    .await;
+    central.stop_scan().await?;
+
+    Ok(found.ok().flatten())
+}
+
+// Results arrive as DeviceConnected events, failures keep retrying in the background
+async fn connect(
+    central: &Adapter,
+    flp: Peripheral,
+    link: &Link,
+    devices: &[String],
+    config: &BleConfig,
+    reconnect_workers: &mut HashMap<PeripheralId, tokio::task::JoinHandle<()>>,
+) {
+    let id = flp.id();
+    tracing::info!(%id, "Connecting to Flipper");
+    link.set_state(ConnectionState::Connecting);
+    if let Err(e) = flp.connect().await {
+        tracing::warn!(%id, "Failed to connect to Flipper: {e}");
+        link.set_state(ConnectionState::Scanning);
+        keep_retrying(central, id, devices, config, reconnect_workers);
+    }
+}
+
+// Don't rely on the Flipper being rediscovered, retry in the background until it's back
+fn keep_retrying(
+    central: &Adapter,
+    id: PeripheralId,
+    devices: &[String],
+    config: &BleConfig,
+    reconnect_workers: &mut HashMap<PeripheralId, tokio::task::JoinHandle<()>>,
+) {
+    reconnect_workers.entry(id.clone()).or_insert_with(|| {
+        tokio::spawn(reconnect_thread(
+            central.clone(),
+            id,
+            devices.to_vec(),
+            config.clone(),
+        ))
+    });
+}
+
+// Every allowed Flipper (only the nearest with `nearest`) gets its own worker, all of them
+// receive the same packets
+pub async fn run(
+    link: Link,
+    devices: Vec<String>,
+    config: BleConfig,
+) -> Result<(), Box<dyn Error>> {
+    let manager = Manager::new().await?;
+
+    let central = get_central(&manager).await?;
+    tracing::info!("Found {:?} adapter", central.adapter_info().await?);
+
+    let mut events = central.events().await?;
+
+    tracing::info!("Scanning... Launch PC Monitor app on Flipper");
+    central.start_scan(ScanFilter::default()).await?;
+    link.set_state(ConnectionState::Scanning);
+
+    let mut data_workers: HashMap<PeripheralId, tokio::task::JoinHandle<()>> = HashMap::new();
+    let mut reconnect_workers: HashMap<PeripheralId, tokio::task::JoinHandle<()>> = HashMap::new();
+    // With `nearest`, Flippers found within SELECT_WINDOW of the first one compete on RSSI and
+    // the winner is the only one connected from then on
+    let mut candidates: Vec<PeripheralId> = Vec::new();
+    let mut chosen: Option<PeripheralId> = None;
+    let selection = tokio::time::sleep(SELECT_WINDOW);
+    tokio::pin!(selection);
+
+    loop {
+        let event = tokio::select! {
+            event = events.next() => match event {
+                Some(event) => event,
+                None => break,
+            },
+            _ = &mut selection, if !candidates.is_empty() => {
+                let mut nearest: Option<(Peripheral, i16)> = None;
+                for id in std::mem::take(&mut candidates) {
+                    let Some(flp) = get_flipper(&central, &id, &devices, &config).await else {
+                        continue;
+                    };
+                    let rssi = match flp.properties().await {
+                        Ok(Some(props)) => props.rssi.unwrap_or(i16::MIN),
+                        _ => i16::MIN,
+                    };
+                    tracing::debug!(%id, rssi, "Found Flipper");
+                    match &nearest {
+                        Some((_, nearest_rssi)) if *nearest_rssi >= rssi => {}
+                        _ => nearest = Some((flp, rssi)),
+                    }
+                }
+                if let Some((flp, rssi)) = nearest {
+                    tracing::info!(id = %flp.id(), rssi, "Picked the nearest Flipper");
+                    chosen = Some(flp.id());
+                    connect(&central, flp, &link, &devices, &config, &mut reconnect_workers).await;
+                }
+                continue;
+            }
+            _ = link.closed() => {
+                for worker in reconnect_workers.values() {
+                    worker.abort();
+                }
+                if let Err(e) = central.stop_scan().await {
+                    tracing::debug!("Failed to stop scanning: {e}");
+                }
+                // Each worker writes the goodbye and disconnects its Flipper
+                futures::future::join_all(data_workers.into_values()).await;
+                return Ok(());
+            }
+        };
+        match event {
+            CentralEvent::DeviceDiscovered(id) if !data_workers.contains_key(&id) => {
+                let Some(flp) = get_flipper(&central, &id, &devices, &config).await else {
+                    continue;
+                };
+                if !config.nearest {
+                    connect(
+                        &central,
+                        flp,
+                        &link,
+                        &devices,
+                        &config,
+                        &mut reconnect_workers,
+                    )
+                    .await;
+                } else if chosen.is_none() && !candidates.contains(&id) {
+                    if candidates.is_empty() {
+                        selection
+                            .as_mut()
+                            .reset(tokio::time::Instant::now() + SELECT_WINDOW);
+                    }
+                    candidates.push(id);
+                }
+            }
+            CentralEvent::DeviceConnected(id) => {
+                if let Some(worker) = reconnect_workers.remove(&id) {
+                    worker.abort();
+                }
+
+                if let Some(flp) = get_flipper(&central, &id, &devices, &config).await {
+                    if let Err(e) = flp.discover_services().await {
+                        tracing::warn!(%id, "Failed to discover Flipper services: {e}");
+                        if let Err(e) = flp.disconnect().await {
+                            tracing::debug!(%id, "Failed to disconnect: {e}");
+                        }
+                        keep_retrying(&central, id, &devices, &config, &mut reconnect_workers);
+                        continue;
+                    }
+                    tracing::info!(%id, "Connected to Flipper");
+                    #[cfg(target_os = "linux")]
+                    if let Ok(Some(props)) = flp.properties().await {
+                        tokio::spawn(super::bluez::warn_unpaired(props.address.to_string()));
+                    }
+
+                    let packets = link.subscribe();
+                    let worker_link = link.clone();
+                    let worker_config = config.clone();
+                    let span = tracing::info_span!("ble", %id);
+                    let previous = data_workers.insert(
+                        id.clone(),
+                        tokio::spawn(
+                            async move {
+                                tokio::join!(
+                                    data_sender(
+                                        flp.clone(),
+                                        packets,
+                                        worker_link.clone(),
+                                        worker_config.clone(),
+                                    ),
+                                    command_reader(flp, worker_link, worker_config)
+                                );
+                            }
+                            .instrument(span),
+                        ),
+                    );
+                    match previous {
+                        Some(worker) => worker.abort(),
+                        None => link.connection_opened(),
+                    }
+                };
+            }
+            // Other peripherals coming and going are none of our business
+            CentralEvent::DeviceDisconnected(id) => {
+                if let Some(worker) = data_workers.remove(&id) {
+                    worker.abort();
+                    tracing::info!(%id, "Disconnected from Flipper. Waiting for reconnection");
+                    link.connection_closed();
+                    keep_retrying(&central, id, &devices, &config, &mut reconnect_workers);
+                };
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
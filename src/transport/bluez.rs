@@ -0,0 +1,277 @@
+use super::{ble, BleConfig};
+use crate::helpers::glob_match;
+use std::collections::HashMap;
+use std::error::Error;
+use std::time::Duration;
+use uuid::Uuid;
+use zbus::fdo::ObjectManagerProxy;
+use zbus::zvariant::{ObjectPath, OwnedObjectPath, OwnedValue};
+use zbus::{interface, proxy, Connection};
+
+/*
+Pairing through BlueZ, which btleplug has no API for. The Flipper shows a PIN while pairing
+and BlueZ asks whatever agent is registered for it, so `pair` registers one reading it from
+the terminal. The bond is kept by BlueZ, marking the device trusted lets it reconnect after
+a reboot without asking again.
+*/
+
+const AGENT_PATH: &str = "/org/flipper/PcMonitor/agent";
+
+#[proxy(
+    interface = "org.bluez.AgentManager1",
+    default_service = "org.bluez",
+    default_path = "/org/bluez"
+)]
+trait AgentManager {
+    fn register_agent(&self, agent: &ObjectPath<'_>, capability: &str) -> zbus::Result<()>;
+    fn request_default_agent(&self, agent: &ObjectPath<'_>) -> zbus::Result<()>;
+    fn unregister_agent(&self, agent: &ObjectPath<'_>) -> zbus::Result<()>;
+}
+
+#[proxy(interface = "org.bluez.Adapter1", default_service = "org.bluez")]
+trait Adapter {
+    fn remove_device(&self, device: &ObjectPath<'_>) -> zbus::Result<()>;
+}
+
+#[proxy(interface = "org.bluez.Device1", default_service = "org.bluez")]
+trait Device {
+    fn pair(&self) -> zbus::Result<()>;
+    #[zbus(property)]
+    fn set_trusted(&self, trusted: bool) -> zbus::Result<()>;
+}
+
+struct Agent;
+
+// Asks on the terminal, pairing only ever happens from the `pair` command
+async fn prompt(question: String) -> zbus::fdo::Result<String> {
+    tokio::task::spawn_blocking(move || {
+        println!("{question}");
+        let mut answer = String::new();
+        std::io::stdin()
+            .read_line(&mut answer)
+            .map(|_| answer.trim().to_owned())
+    })
+    .await
+    .map_err(|e| zbus::fdo::Error::Failed(e.to_string()))?
+    .map_err(|e| zbus::fdo::Error::Failed(e.to_string()))
+}
+
+#[interface(name = "org.bluez.Agent1")]
+impl Agent {
+    fn release(&self) {}
+
+    async fn request_pin_code(&self, _device: OwnedObjectPath) -> zbus::fdo::Result<String> {
+        prompt("Enter the PIN shown on the Flipper:".to_owned()).await
+    }
+
+    fn display_pin_code(&self, _device: OwnedObjectPath, pincode: String) {
+        println!("Enter {pincode} on the Flipper");
+    }
+
+    async fn request_passkey(&self, _device: OwnedObjectPath) -> zbus::fdo::Result<u32> {
+        let passkey = prompt("Enter the PIN shown on the Flipper:".to_owned()).await?;
+        passkey
+            .parse()
+            .map_err(|_| zbus::fdo::Error::InvalidArgs(format!("Not a PIN: {passkey}")))
+    }
+
+    fn display_passkey(&self, _device: OwnedObjectPath, passkey: u32, _entered: u16) {
+        println!("Enter {passkey:06} on the Flipper");
+    }
+
+    async fn request_confirmation(
+        &self,
+        _device: OwnedObjectPath,
+        passkey: u32,
+    ) -> zbus::fdo::Result<()> {
+        let answer = prompt(format!("Does the Flipper show {passkey:06}? [y/N]")).await?;
+        match answer.eq_ignore_ascii_case("y") {
+            true => Ok(()),
+            false => Err(zbus::fdo::Error::AccessDenied("PIN rejected".to_owned())),
+        }
+    }
+
+    fn request_authorization(&self, _device: OwnedObjectPath) {}
+
+    fn authorize_service(&self, _device: OwnedObjectPath, _uuid: String) {}
+
+    fn cancel(&self) {
+        println!("Pairing was cancelled");
+    }
+}
+
+struct BluezDevice {
+    path: OwnedObjectPath,
+    adapter: OwnedObjectPath,
+    address: String,
+    name: String,
+    paired: bool,
+    services: Vec<String>,
+}
+
+fn property<T: TryFrom<OwnedValue>>(
+    properties: &HashMap<String, OwnedValue>,
+    name: &str,
+) -> Option<T> {
+    let value = properties.get(name)?.try_clone().ok()?;
+    T::try_from(value).ok()
+}
+
+// Properties of every BlueZ object implementing `interface`
+async fn objects(
+    connection: &Connection,
+    interface: &str,
+) -> zbus::Result<Vec<(OwnedObjectPath, HashMap<String, OwnedValue>)>> {
+    let objects = ObjectManagerProxy::builder(connection)
+        .destination("org.bluez")?
+        .path("/")?
+        .build()
+        .await?
+        .get_managed_objects()
+        .await?;
+
+    Ok(objects
+        .into_iter()
+        .filter_map(|(path, interfaces)| {
+            let (_, properties) = interfaces
+                .into_iter()
+                .find(|(name, _)| name.as_str() == interface)?;
+            Some((path, properties))
+        })
+        .collect())
+}
+
+async fn list(connection: &Connection) -> zbus::Result<Vec<BluezDevice>> {
+    Ok(objects(connection, "org.bluez.Device1")
+        .await?
+        .into_iter()
+        .filter_map(|(path, properties)| {
+            Some(BluezDevice {
+                adapter: property(&properties, "Adapter")?,
+                address: property(&properties, "Address")?,
+                name: property(&properties, "Name").unwrap_or_default(),
+                paired: property(&properties, "Paired").unwrap_or(false),
+                services: property(&properties, "UUIDs").unwrap_or_default(),
+                path,
+            })
+        })
+        .collect())
+}
+
+impl BluezDevice {
+    fn is_flipper(&self, service: Uuid) -> bool {
+        let service = service.to_string();
+        self.services
+            .iter()
+            .any(|uuid| uuid.eq_ignore_ascii_case(&service))
+            || self.name.contains("PC Mon")
+            || self.name.starts_with("Flipper")
+    }
+
+    fn matches(&self, devices: &[String]) -> bool {
+        devices.is_empty()
+            || devices.iter().any(|device| {
+                self.address.eq_ignore_ascii_case(device) || glob_match(device, &self.name)
+            })
+    }
+}
+
+pub async fn pair(
+    devices: Vec<String>,
+    config: BleConfig,
+    timeout: Duration,
+) -> Result<(), Box<dyn Error>> {
+    let Some(address) = ble::find(&devices, timeout, &config).await? else {
+        return Err("No Flipper found, is the PC Monitor app open?".into());
+    };
+
+    let connection = Connection::system().await?;
+    let Some(device) = device_at(&connection, &address).await? else {
+        return Err(format!("BlueZ doesn't know {address}").into());
+    };
+    if device.paired {
+        println!("Already paired with {} ({address})", device.name);
+        return Ok(());
+    }
+
+    connection.object_server().at(AGENT_PATH, Agent).await?;
+    let agent = ObjectPath::try_from(AGENT_PATH)?;
+    let manager = AgentManagerProxy::new(&connection).await?;
+    manager.register_agent(&agent, "KeyboardDisplay").await?;
+    manager.request_default_agent(&agent).await?;
+
+    println!("Pairing with {} ({address})...", device.name);
+    let proxy = DeviceProxy::builder(&connection)
+        .path(device.path)?
+        .build()
+        .await?;
+    let paired = proxy.pair().await;
+    let _ = manager.unregister_agent(&agent).await;
+    paired?;
+
+    proxy.set_trusted(true).await?;
+    println!("Paired, the connection now survives reboots. Undo with `forget-device`");
+    Ok(())
+}
+
+async fn device_at(connection: &Connection, address: &str) -> zbus::Result<Option<BluezDevice>> {
+    Ok(list(connection)
+        .await?
+        .into_iter()
+        .find(|device| device.address.eq_ignore_ascii_case(address)))
+}
+
+// Removes the bond of every paired Flipper matching `devices`, all of them when empty
+pub async fn forget(devices: Vec<String>, service: Uuid) -> Result<(), Box<dyn Error>> {
+    let connection = Connection::system().await?;
+    let bonded: Vec<BluezDevice> = list(&connection)
+        .await?
+        .into_iter()
+        .filter(|device| device.paired && device.is_flipper(service) && device.matches(&devices))
+        .collect();
+    if bonded.is_empty() {
+        return Err("No paired Flipper found".into());
+    }
+
+    for device in bonded {
+        AdapterProxy::builder(&connection)
+            .path(device.adapter.clone())?
+            .build()
+            .await?
+            .remove_device(&device.path)
+            .await?;
+        println!("Forgot {} ({})", device.name, device.address);
+    }
+    Ok(())
+}
+
+// Unpaired Flippers can connect but may get their writes rejected, and won't come back by
+// themselves after a reboot
+pub async fn warn_unpaired(address: String) {
+    let Ok(connection) = Connection::system().await else {
+        return;
+    };
+    if let Ok(Some(device)) = device_at(&connection, &address).await {
+        if !device.paired {
+            tracing::warn!(%address, "Flipper isn't paired, run `pair` once to keep the bond");
+        }
+    }
+}
+
+// What BlueZ negotiated on connect for one of the Flipper's characteristics, BlueZ 5.62 and
+// newer report it
+pub async fn mtu(address: &str, characteristic: Uuid) -> Option<u16> {
+    let connection = Connection::system().await.ok()?;
+    let device = device_at(&connection, address).await.ok()??;
+    let characteristic = characteristic.to_string();
+    objects(&connection, "org.bluez.GattCharacteristic1")
+        .await
+        .ok()?
+        .into_iter()
+        .filter(|(path, _)| path.as_str().starts_with(device.path.as_str()))
+        .find(|(_, properties)| {
+            property::<String>(properties, "UUID")
+                .is_some_and(|uuid| uuid.eq_ignore_ascii_case(&characteristic))
+        })
+        .and_then(|(_, properties)| property(&properties, "MTU"))
+}
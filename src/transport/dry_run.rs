@@ -0,0 +1,39 @@
+use super::Link;
+use std::error::Error;
+use tokio::sync::broadcast;
+
+fn hex_dump(packet: &[u8]) -> String {
+    packet
+        .chunks(16)
+        .enumerate()
+        .map(|(line, chunk)| {
+            let hex: Vec<String> = chunk.iter().map(|b| format!("{b:02x}")).collect();
+            format!("  {:04x}: {}", line * 16, hex.join(" "))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Pretends a Flipper is connected and prints every packet exactly as it would be written
+pub async fn run(link: Link) -> Result<(), Box<dyn Error>> {
+    tracing::info!("Dry run, packets are printed instead of sent");
+    link.connection_opened();
+
+    let mut packets = link.subscribe();
+    loop {
+        let packet = tokio::select! {
+            biased;
+            packet = packets.recv() => match packet {
+                Ok(packet) => packet,
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    println!("(skipped {skipped} packets)");
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => return Ok(()),
+            },
+            _ = link.closed() => return Ok(()),
+        };
+
+        println!("{} bytes\n{}", packet.len(), hex_dump(&packet));
+    }
+}
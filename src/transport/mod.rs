@@ -0,0 +1,194 @@
+use crate::commands::Dispatcher;
+use quality::LinkQuality;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::{broadcast, watch};
+use uuid::Uuid;
+
+pub mod backoff;
+#[cfg(not(target_os = "freebsd"))]
+pub mod ble;
+#[cfg(target_os = "linux")]
+pub mod bluez;
+pub mod dry_run;
+pub mod net;
+pub mod quality;
+pub mod rpc;
+pub mod serial;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Ble,
+    Serial,
+    // Official Flipper RPC over USB, starts the app by itself (`rpc_app`)
+    Rpc,
+    // Wi-Fi dev board or bridge firmware, at the `host:port` addresses in `devices`
+    Tcp,
+    Udp,
+    // Newline delimited JSON samples instead of packets, to the [json] port or stdout
+    Json,
+    // Hex dumps packets to stdout, no Flipper needed
+    #[serde(rename = "dry-run")]
+    DryRun,
+}
+
+impl Kind {
+    // Byte streams where nothing marks where a packet ends, packets and commands are always
+    // framed (see framing.rs)
+    pub fn is_stream(self) -> bool {
+        matches!(self, Kind::Serial | Kind::Tcp)
+    }
+}
+
+// Stock firmware and PC Monitor app, the defaults in [ble]
+pub const FLIPPER_SERVICE_UUID: Uuid = Uuid::from_u128(0x8fe5b3d5_2e7f_4a98_2a48_7acc60fe0000);
+// Written by us
+pub const FLIPPER_CHARACTERISTIC_UUID: Uuid =
+    Uuid::from_u128(0x19ed82ae_ed21_4c9d_4145_228e62fe0000);
+// Indicated by the Flipper, carries commands back from the app
+pub const FLIPPER_TX_CHARACTERISTIC_UUID: Uuid =
+    Uuid::from_u128(0x19ed82ae_ed21_4c9d_4145_228e61fe0000);
+
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(default)]
+pub struct BleConfig {
+    // Split packets that don't fit into a single write into chunks (see chunking.rs), needs
+    // a newer app
+    pub chunking: bool,
+    // Only connect to the Flipper with the strongest signal of those matching `devices`,
+    // instead of sending to all of them
+    pub nearest: bool,
+    // ATT MTU to assume where the system doesn't report the negotiated one (BlueZ does)
+    pub mtu: u16,
+    // For custom firmwares and forked apps, the stock serial service by default
+    pub service_uuid: Uuid,
+    // Written by us
+    pub characteristic_uuid: Uuid,
+    // Carries commands back from the app
+    pub tx_characteristic_uuid: Uuid,
+}
+
+impl Default for BleConfig {
+    fn default() -> Self {
+        BleConfig {
+            chunking: false,
+            nearest: false,
+            mtu: crate::protocol::chunking::DEFAULT_MTU,
+            service_uuid: FLIPPER_SERVICE_UUID,
+            characteristic_uuid: FLIPPER_CHARACTERISTIC_UUID,
+            tx_characteristic_uuid: FLIPPER_TX_CHARACTERISTIC_UUID,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    Scanning,
+    Connecting,
+    Connected,
+}
+
+// Shared between the collector loop (producer) and the transports (consumers)
+#[derive(Clone)]
+pub struct Link {
+    packets: broadcast::Sender<Vec<u8>>,
+    state: Arc<watch::Sender<ConnectionState>>,
+    // Open connections, the link stays Connected while any Flipper is
+    connections: Arc<AtomicUsize>,
+    // Keeps connections open but stops sending, toggled from the tray
+    paused: Arc<AtomicBool>,
+    commands: Dispatcher,
+    quality: Arc<LinkQuality>,
+    // Set on shutdown, transports write what's queued and disconnect
+    closing: Arc<watch::Sender<bool>>,
+}
+
+impl Default for Link {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Link {
+    pub fn new() -> Self {
+        let (packets, _) = broadcast::channel(16);
+        let (state, _) = watch::channel(ConnectionState::Scanning);
+        let (closing, _) = watch::channel(false);
+
+        Link {
+            packets,
+            state: Arc::new(state),
+            connections: Arc::new(AtomicUsize::new(0)),
+            paused: Arc::new(AtomicBool::new(false)),
+            commands: Dispatcher::new(),
+            quality: Arc::new(LinkQuality::default()),
+            closing: Arc::new(closing),
+        }
+    }
+
+    pub fn send(&self, packet: Vec<u8>) {
+        // No subscribers just means no Flipper is connected right now
+        let _ = self.packets.send(packet);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Vec<u8>> {
+        self.packets.subscribe()
+    }
+
+    pub fn state(&self) -> watch::Receiver<ConnectionState> {
+        self.state.subscribe()
+    }
+
+    pub fn commands(&self) -> &Dispatcher {
+        &self.commands
+    }
+
+    pub fn quality(&self) -> &LinkQuality {
+        &self.quality
+    }
+
+    pub fn close(&self) {
+        self.closing.send_replace(true);
+    }
+
+    pub fn is_closed(&self) -> bool {
+        *self.closing.borrow()
+    }
+
+    pub async fn closed(&self) {
+        let _ = self.closing.subscribe().wait_for(|closing| *closing).await;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::SeqCst);
+    }
+
+    pub fn connection_opened(&self) {
+        self.connections.fetch_add(1, Ordering::SeqCst);
+        self.set_state(ConnectionState::Connected);
+    }
+
+    pub fn connection_closed(&self) {
+        if self.connections.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.set_state(ConnectionState::Scanning);
+        }
+    }
+
+    pub fn set_state(&self, state: ConnectionState) {
+        // Another Flipper searching doesn't matter while one is still connected
+        if state != ConnectionState::Connected && self.connections.load(Ordering::SeqCst) > 0 {
+            return;
+        }
+
+        self.state.send_if_modified(|current| {
+            let changed = *current != state;
+            *current = state;
+            changed
+        });
+    }
+}
@@ -0,0 +1,184 @@
+use super::backoff::Backoff;
+use super::{ConnectionState, Link};
+use crate::helpers::with_default_port;
+use crate::protocol::framing::Deframer;
+use std::error::Error;
+use std::time::Instant;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::sync::broadcast;
+use tracing::Instrument;
+
+// Used when a device is given without one, matches the bridge firmware's default
+const DEFAULT_PORT: u16 = 4242;
+
+fn addresses(devices: Vec<String>) -> Result<Vec<String>, Box<dyn Error>> {
+    // Unlike BLE and USB there is nothing to search for
+    if devices.is_empty() {
+        return Err("Set the Wi-Fi board's address in `devices`, e.g. 192.168.4.1:4242".into());
+    }
+    Ok(devices
+        .iter()
+        .map(|device| with_default_port(device, DEFAULT_PORT))
+        .collect())
+}
+
+// Wi-Fi dev board or another bridge, the packets go over the stream framed exactly as over USB
+pub async fn run_tcp(link: Link, devices: Vec<String>) -> Result<(), Box<dyn Error>> {
+    let loops = addresses(devices)?.into_iter().map(|address| {
+        let span = tracing::info_span!("tcp", %address);
+        run_address(link.clone(), address).instrument(span)
+    });
+    futures::future::join_all(loops).await;
+    Ok(())
+}
+
+async fn run_address(link: Link, address: String) {
+    let mut backoff = Backoff::new();
+    loop {
+        link.set_state(ConnectionState::Connecting);
+        if !stream(&link, &address, &mut backoff).await {
+            return;
+        }
+
+        tokio::select! {
+            _ = backoff.wait() => {}
+            _ = link.closed() => return,
+        }
+    }
+}
+
+// Streams until the board goes away, false once there is nothing left to send
+async fn stream(link: &Link, address: &str, backoff: &mut Backoff) -> bool {
+    tracing::info!("Connecting to Flipper");
+    let stream = tokio::select! {
+        stream = TcpStream::connect(address) => match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                tracing::warn!("Failed to connect to Flipper: {e}");
+                return true;
+            }
+        },
+        _ = link.closed() => return false,
+    };
+    // Packets are small and should go out right away
+    let _ = stream.set_nodelay(true);
+
+    tracing::info!("Connected to Flipper");
+    link.connection_opened();
+    backoff.reset();
+
+    let (mut reader, mut writer) = stream.into_split();
+    let mut packets = link.subscribe();
+    let mut commands = Deframer::default();
+    let mut buf = [0u8; 64];
+    loop {
+        let packet = tokio::select! {
+            // Queued packets first, the goodbye is queued right before closing
+            biased;
+            packet = packets.recv() => match packet {
+                Ok(packet) => packet,
+                Err(broadcast::error::RecvError::Lagged(_)) => {
+                    link.quality().record_lagged();
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => return false,
+            },
+            read = reader.read(&mut buf) => {
+                match read {
+                    Ok(n) if n > 0 => {
+                        for command in commands.push(&buf[..n]) {
+                            link.commands().dispatch(&command);
+                        }
+                    }
+                    Ok(_) => break,
+                    Err(e) => {
+                        tracing::warn!("Failed to read: {e}");
+                        break;
+                    }
+                }
+                continue;
+            }
+            _ = link.closed() => {
+                let _ = writer.shutdown().await;
+                link.connection_closed();
+                return false;
+            }
+        };
+
+        let started = Instant::now();
+        let written = writer.write_all(&packet).await;
+        link.quality()
+            .record_write(started.elapsed(), written.is_ok());
+        if let Err(e) = written {
+            tracing::warn!("Failed to write: {e}");
+            break;
+        }
+    }
+
+    tracing::info!("Disconnected from Flipper. Waiting for reconnection");
+    link.connection_closed();
+    true
+}
+
+// One datagram per packet. There is no connection to lose, so the link counts as
+// connected as soon as the socket is set up
+pub async fn run_udp(link: Link, devices: Vec<String>) -> Result<(), Box<dyn Error>> {
+    let mut sockets = Vec::new();
+    for address in addresses(devices)? {
+        let socket = UdpSocket::bind(match address.starts_with('[') {
+            true => "[::]:0",
+            false => "0.0.0.0:0",
+        })
+        .await?;
+        socket
+            .connect(&address)
+            .await
+            .map_err(|e| format!("Failed to resolve {address}: {e}"))?;
+        tracing::info!(%address, "Sending to Flipper over UDP");
+        sockets.push((socket, address));
+    }
+
+    let loops = sockets.into_iter().map(|(socket, address)| {
+        let span = tracing::info_span!("udp", %address);
+        send_datagrams(link.clone(), socket).instrument(span)
+    });
+    futures::future::join_all(loops).await;
+    Ok(())
+}
+
+async fn send_datagrams(link: Link, socket: UdpSocket) {
+    link.connection_opened();
+    let mut packets = link.subscribe();
+    let mut buf = [0u8; 64];
+    loop {
+        let packet = tokio::select! {
+            biased;
+            packet = packets.recv() => match packet {
+                Ok(packet) => packet,
+                Err(broadcast::error::RecvError::Lagged(_)) => {
+                    link.quality().record_lagged();
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            // The board can send commands back the same way
+            received = socket.recv(&mut buf) => {
+                if let Ok(n) = received {
+                    link.commands().dispatch(&buf[..n]);
+                }
+                continue;
+            }
+            _ = link.closed() => break,
+        };
+
+        let started = Instant::now();
+        let sent = socket.send(&packet).await;
+        link.quality().record_write(started.elapsed(), sent.is_ok());
+        // Usually an ICMP port unreachable from an earlier datagram, the board may be rebooting
+        if let Err(e) = sent {
+            tracing::debug!("Failed to send: {e}");
+        }
+    }
+    link.connection_closed();
+}
@@ -0,0 +1,82 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+// Writes normally return right away, one taking this long means the link's buffers are full
+const SLOW_WRITE: Duration = Duration::from_millis(100);
+
+// Write outcomes reported by the transports since the collector loop last looked
+#[derive(Debug, Default)]
+pub struct LinkQuality {
+    writes: AtomicU32,
+    congested: AtomicU32,
+}
+
+impl LinkQuality {
+    pub fn record_write(&self, elapsed: Duration, ok: bool) {
+        self.writes.fetch_add(1, Ordering::Relaxed);
+        if !ok || elapsed > SLOW_WRITE {
+            self.congested.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    // The sender fell behind and skipped packets
+    pub fn record_lagged(&self) {
+        self.congested.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // (writes, congested writes) since the previous call
+    fn take(&self) -> (u32, u32) {
+        (
+            self.writes.swap(0, Ordering::Relaxed),
+            self.congested.swap(0, Ordering::Relaxed),
+        )
+    }
+}
+
+// Doubles the update interval while the link is congested and eases back to the
+// configured one once writes go through again
+#[derive(Debug)]
+pub struct AdaptiveInterval {
+    base: Duration,
+    max: Duration,
+    current: Duration,
+}
+
+impl AdaptiveInterval {
+    // A `max` at or below `base` keeps the interval fixed
+    pub fn new(base: Duration, max: Duration) -> Self {
+        AdaptiveInterval {
+            base,
+            max,
+            current: base,
+        }
+    }
+
+    pub fn set_base(&mut self, base: Duration) {
+        self.base = base;
+        self.current = base;
+    }
+
+    pub fn update(&mut self, quality: &LinkQuality) -> Duration {
+        let (writes, congested) = quality.take();
+        let previous = self.current;
+
+        self.current = match (writes, congested) {
+            (_, 1..) => (self.current * 2).min(self.max),
+            // Nothing was sent, e.g. while disconnected, so there is nothing to judge
+            (0, _) => self.current,
+            _ => (self.current * 3 / 4).max(self.base),
+        }
+        .max(self.base);
+
+        if self.current != previous {
+            tracing::debug!(
+                writes,
+                congested,
+                interval_ms = self.current.as_millis() as u64,
+                "Adjusting update interval to the link"
+            );
+        }
+        self.current
+    }
+}
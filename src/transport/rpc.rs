@@ -0,0 +1,325 @@
+use super::backoff::Backoff;
+use super::serial::{find_flipper_port, BAUD_RATE};
+use super::{ConnectionState, Link};
+use std::error::Error;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::sync::broadcast;
+use tokio_serial::SerialPortBuilderExt;
+use tracing::Instrument;
+
+/*
+The official Flipper RPC over the USB serial port, the way qFlipper talks to it. The CLI
+switches into RPC mode with `start_rpc_session`, after which both sides exchange
+varint length prefixed PB.Main protobuf messages. The PC Monitor app is launched with
+`app_start_request` (args "RPC") and gets every packet through `app_data_exchange_request`,
+which it also uses to send commands back. Needs an app that registers for data exchange.
+
+Only the few PB.Main fields used here are encoded by hand, numbers as in flipper.proto
+from flipperzero-protobuf.
+*/
+
+const CLI_PROMPT: &[u8] = b">: ";
+const START_RPC: &[u8] = b"start_rpc_session\r";
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(3);
+// Far more than a command needs, anything longer is skipped
+const MAX_MESSAGE: usize = 4096;
+
+const MAIN_COMMAND_ID: u32 = 1;
+const MAIN_COMMAND_STATUS: u32 = 2;
+const MAIN_STOP_SESSION: u32 = 19;
+const MAIN_APP_START_REQUEST: u32 = 16;
+const MAIN_APP_DATA_EXCHANGE_REQUEST: u32 = 65;
+const APP_START_NAME: u32 = 1;
+const APP_START_ARGS: u32 = 2;
+const DATA_EXCHANGE_DATA: u32 = 1;
+
+mod pb {
+    const VARINT: u8 = 0;
+    const FIXED64: u8 = 1;
+    const LENGTH_DELIMITED: u8 = 2;
+    const FIXED32: u8 = 5;
+
+    pub enum Value<'a> {
+        Varint(u64),
+        Bytes(&'a [u8]),
+    }
+
+    pub fn varint(out: &mut Vec<u8>, mut value: u64) {
+        while value >= 0x80 {
+            out.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        out.push(value as u8);
+    }
+
+    pub fn uint(out: &mut Vec<u8>, field: u32, value: u64) {
+        varint(out, (field as u64) << 3 | VARINT as u64);
+        varint(out, value);
+    }
+
+    pub fn bytes(out: &mut Vec<u8>, field: u32, value: &[u8]) {
+        varint(out, (field as u64) << 3 | LENGTH_DELIMITED as u64);
+        varint(out, value.len() as u64);
+        out.extend_from_slice(value);
+    }
+
+    // Value and the bytes after it
+    pub fn read_varint(data: &[u8]) -> Option<(u64, &[u8])> {
+        let mut value = 0u64;
+        for (i, byte) in data.iter().enumerate().take(10) {
+            value |= ((byte & 0x7F) as u64) << (7 * i);
+            if byte & 0x80 == 0 {
+                return Some((value, &data[i + 1..]));
+            }
+        }
+        None
+    }
+
+    // Every field of a message, None if it's cut off or uses a wire type we can't skip
+    pub fn fields(mut data: &[u8]) -> Option<Vec<(u32, Value<'_>)>> {
+        let mut fields = Vec::new();
+        while !data.is_empty() {
+            let (key, rest) = read_varint(data)?;
+            let field = (key >> 3) as u32;
+            let (value, rest) = match (key & 0x07) as u8 {
+                VARINT => {
+                    let (value, rest) = read_varint(rest)?;
+                    (Value::Varint(value), rest)
+                }
+                LENGTH_DELIMITED => {
+                    let (length, rest) = read_varint(rest)?;
+                    let length = usize::try_from(length).ok().filter(|&l| l <= rest.len())?;
+                    (Value::Bytes(&rest[..length]), &rest[length..])
+                }
+                FIXED64 => (Value::Bytes(rest.get(..8)?), &rest[8..]),
+                FIXED32 => (Value::Bytes(rest.get(..4)?), &rest[4..]),
+                _ => return None,
+            };
+            fields.push((field, value));
+            data = rest;
+        }
+        Some(fields)
+    }
+}
+
+// A length prefixed PB.Main with `content` in its oneof
+fn main_message(command_id: u32, content_field: u32, content: &[u8]) -> Vec<u8> {
+    let mut message = Vec::new();
+    pb::uint(&mut message, MAIN_COMMAND_ID, command_id as u64);
+    pb::bytes(&mut message, content_field, content);
+
+    let mut framed = Vec::with_capacity(message.len() + 2);
+    pb::varint(&mut framed, message.len() as u64);
+    framed.extend(message);
+    framed
+}
+
+fn app_start(command_id: u32, app: &str) -> Vec<u8> {
+    let mut request = Vec::new();
+    pb::bytes(&mut request, APP_START_NAME, app.as_bytes());
+    pb::bytes(&mut request, APP_START_ARGS, b"RPC");
+    main_message(command_id, MAIN_APP_START_REQUEST, &request)
+}
+
+fn data_exchange(command_id: u32, data: &[u8]) -> Vec<u8> {
+    let mut request = Vec::new();
+    pb::bytes(&mut request, DATA_EXCHANGE_DATA, data);
+    main_message(command_id, MAIN_APP_DATA_EXCHANGE_REQUEST, &request)
+}
+
+enum Message {
+    Status { command_id: u64, status: u64 },
+    Data(Vec<u8>),
+}
+
+fn parse_main(message: &[u8]) -> Option<Message> {
+    let fields = pb::fields(message)?;
+    let uint = |wanted| {
+        fields.iter().find_map(|(field, value)| match value {
+            pb::Value::Varint(value) if *field == wanted => Some(*value),
+            _ => None,
+        })
+    };
+
+    for (field, value) in &fields {
+        if let (MAIN_APP_DATA_EXCHANGE_REQUEST, pb::Value::Bytes(request)) = (*field, value) {
+            let data = pb::fields(request)?
+                .into_iter()
+                .find_map(|(field, value)| match value {
+                    pb::Value::Bytes(data) if field == DATA_EXCHANGE_DATA => Some(data.to_vec()),
+                    _ => None,
+                })
+                .unwrap_or_default();
+            return Some(Message::Data(data));
+        }
+    }
+    Some(Message::Status {
+        command_id: uint(MAIN_COMMAND_ID).unwrap_or(0),
+        status: uint(MAIN_COMMAND_STATUS).unwrap_or(0),
+    })
+}
+
+// Pops the next complete message off `buffer`
+fn next_message(buffer: &mut Vec<u8>) -> Option<Vec<u8>> {
+    let (length, rest) = pb::read_varint(buffer)?;
+    let header = buffer.len() - rest.len();
+    let end = header + usize::try_from(length).ok()?;
+    if buffer.len() < end {
+        return None;
+    }
+    let message = buffer[header..end].to_vec();
+    buffer.drain(..end);
+    Some(message)
+}
+
+// Reads until `marker`, the CLI echoes and prints a banner before it
+async fn read_until<R: AsyncRead + Unpin>(reader: &mut R, marker: &[u8]) -> std::io::Result<()> {
+    let mut seen = Vec::new();
+    let mut buf = [0u8; 256];
+    while !seen.windows(marker.len()).any(|window| window == marker) {
+        let n = reader.read(&mut buf).await?;
+        if n == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        seen.extend_from_slice(&buf[..n]);
+    }
+    Ok(())
+}
+
+// One loop per configured port, or a single one following whichever Flipper is plugged in
+pub async fn run(link: Link, ports: Vec<String>, app: String) -> Result<(), Box<dyn Error>> {
+    tracing::info!("Looking for Flipper on USB, PC Monitor is started over RPC");
+
+    if ports.is_empty() {
+        return run_port(link, None, app).await;
+    }
+
+    let loops = ports
+        .into_iter()
+        .map(|port| run_port(link.clone(), Some(port), app.clone()));
+    for result in futures::future::join_all(loops).await {
+        result?;
+    }
+    Ok(())
+}
+
+async fn run_port(link: Link, port: Option<String>, app: String) -> Result<(), Box<dyn Error>> {
+    let mut backoff = Backoff::new();
+    loop {
+        link.set_state(ConnectionState::Scanning);
+
+        if let Some(port_name) = port.clone().or_else(find_flipper_port) {
+            let span = tracing::info_span!("rpc", port = %port_name);
+            if !session(&link, &port_name, &app, &mut backoff)
+                .instrument(span)
+                .await
+            {
+                return Ok(());
+            }
+        }
+
+        backoff.wait().await;
+    }
+}
+
+// Streams until the Flipper goes away, false once there is nothing left to send
+async fn session(link: &Link, port_name: &str, app: &str, backoff: &mut Backoff) -> bool {
+    tracing::info!("Connecting to Flipper");
+    link.set_state(ConnectionState::Connecting);
+
+    let port = match tokio_serial::new(port_name, BAUD_RATE).open_native_async() {
+        Ok(port) => port,
+        Err(e) => {
+            tracing::warn!("Failed to connect to Flipper: {e}");
+            return true;
+        }
+    };
+    let (mut reader, mut writer) = tokio::io::split(port);
+
+    let handshake = async {
+        writer.write_all(b"\r").await?;
+        read_until(&mut reader, CLI_PROMPT).await?;
+        writer.write_all(START_RPC).await?;
+        read_until(&mut reader, b"\n").await?;
+        writer.write_all(&app_start(1, app)).await
+    };
+    match tokio::time::timeout(HANDSHAKE_TIMEOUT, handshake).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => {
+            tracing::warn!("Failed to start RPC session: {e}");
+            return true;
+        }
+        Err(_) => {
+            tracing::warn!("Flipper CLI didn't answer, is qFlipper or another session open?");
+            return true;
+        }
+    }
+
+    tracing::info!(app, "Connected to Flipper, starting app");
+    link.connection_opened();
+    backoff.reset();
+
+    let mut packets = link.subscribe();
+    let mut command_id: u32 = 1;
+    let mut buffer = Vec::new();
+    let mut buf = [0u8; 256];
+    loop {
+        let packet = tokio::select! {
+            // Queued packets first, the goodbye is queued right before closing
+            biased;
+            packet = packets.recv() => match packet {
+                Ok(packet) => packet,
+                Err(broadcast::error::RecvError::Lagged(_)) => {
+                    link.quality().record_lagged();
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => return false,
+            },
+            read = reader.read(&mut buf) => {
+                match read {
+                    Ok(n) if n > 0 => buffer.extend_from_slice(&buf[..n]),
+                    Ok(_) => break,
+                    Err(e) => {
+                        tracing::warn!("Failed to read: {e}");
+                        break;
+                    }
+                }
+                while let Some(message) = next_message(&mut buffer) {
+                    match parse_main(&message) {
+                        Some(Message::Data(data)) => link.commands().dispatch(&data),
+                        Some(Message::Status { command_id, status }) if status != 0 => {
+                            tracing::warn!(command_id, status, "Flipper RPC request failed");
+                        }
+                        _ => {}
+                    }
+                }
+                if buffer.len() > MAX_MESSAGE {
+                    tracing::warn!("Dropping oversized RPC message");
+                    buffer.clear();
+                }
+                continue;
+            }
+            _ = link.closed() => {
+                let stop = main_message(command_id.wrapping_add(1), MAIN_STOP_SESSION, &[]);
+                let _ = writer.write_all(&stop).await;
+                link.connection_closed();
+                return false;
+            }
+        };
+
+        command_id = command_id.wrapping_add(1);
+        let started = Instant::now();
+        let written = writer.write_all(&data_exchange(command_id, &packet)).await;
+        link.quality()
+            .record_write(started.elapsed(), written.is_ok());
+        if let Err(e) = written {
+            tracing::warn!("Failed to write: {e}");
+            break;
+        }
+    }
+
+    tracing::info!("Disconnected from Flipper. Waiting for reconnection");
+    link.connection_closed();
+    true
+}
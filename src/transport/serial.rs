@@ -0,0 +1,132 @@
+use super::backoff::Backoff;
+use super::{ConnectionState, Link};
+use crate::protocol::framing::Deframer;
+use std::error::Error;
+use std::time::Instant;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::broadcast;
+use tokio_serial::{SerialPortBuilderExt, SerialPortType};
+use tracing::Instrument;
+
+// STMicroelectronics VCP ids the Flipper enumerates with
+const FLIPPER_USB_VID: u16 = 0x0483;
+const FLIPPER_USB_PID: u16 = 0x5740;
+pub(super) const BAUD_RATE: u32 = 230400;
+
+pub fn find_flipper_port() -> Option<String> {
+    tokio_serial::available_ports()
+        .ok()?
+        .into_iter()
+        .find(|p| match &p.port_type {
+            SerialPortType::UsbPort(usb) => {
+                usb.vid == FLIPPER_USB_VID && usb.pid == FLIPPER_USB_PID
+            }
+            _ => false,
+        })
+        .map(|p| p.port_name)
+}
+
+// One loop per configured port, or a single one following whichever Flipper is plugged in
+pub async fn run(link: Link, ports: Vec<String>) -> Result<(), Box<dyn Error>> {
+    tracing::info!("Looking for Flipper on USB... Launch PC Monitor app on Flipper");
+
+    if ports.is_empty() {
+        return run_port(link, None).await;
+    }
+
+    let loops = ports
+        .into_iter()
+        .map(|port| run_port(link.clone(), Some(port)));
+    for result in futures::future::join_all(loops).await {
+        result?;
+    }
+    Ok(())
+}
+
+async fn run_port(link: Link, port: Option<String>) -> Result<(), Box<dyn Error>> {
+    let mut backoff = Backoff::new();
+    loop {
+        link.set_state(ConnectionState::Scanning);
+
+        if let Some(port_name) = port.clone().or_else(find_flipper_port) {
+            let span = tracing::info_span!("serial", port = %port_name);
+            if !stream(&link, &port_name, &mut backoff)
+                .instrument(span)
+                .await
+            {
+                return Ok(());
+            }
+        }
+
+        backoff.wait().await;
+    }
+}
+
+// Streams until the Flipper goes away, false once there is nothing left to send
+async fn stream(link: &Link, port_name: &str, backoff: &mut Backoff) -> bool {
+    tracing::info!("Connecting to Flipper");
+    link.set_state(ConnectionState::Connecting);
+
+    let port = match tokio_serial::new(port_name, BAUD_RATE).open_native_async() {
+        Ok(port) => port,
+        Err(e) => {
+            tracing::warn!("Failed to connect to Flipper: {e}");
+            return true;
+        }
+    };
+
+    tracing::info!("Connected to Flipper");
+    link.connection_opened();
+    backoff.reset();
+
+    let (mut reader, mut writer) = tokio::io::split(port);
+    let mut packets = link.subscribe();
+    let mut commands = Deframer::default();
+    let mut buf = [0u8; 64];
+    loop {
+        let packet = tokio::select! {
+            // Queued packets first, the goodbye is queued right before closing
+            biased;
+            packet = packets.recv() => match packet {
+                Ok(packet) => packet,
+                Err(broadcast::error::RecvError::Lagged(_)) => {
+                    link.quality().record_lagged();
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => return false,
+            },
+            read = reader.read(&mut buf) => {
+                match read {
+                    Ok(n) if n > 0 => {
+                        for command in commands.push(&buf[..n]) {
+                            link.commands().dispatch(&command);
+                        }
+                    }
+                    Ok(_) => break,
+                    Err(e) => {
+                        tracing::warn!("Failed to read: {e}");
+                        break;
+                    }
+                }
+                continue;
+            }
+            _ = link.closed() => {
+                link.connection_closed();
+                return false;
+            }
+        };
+
+        let started = Instant::now();
+        let written = writer.write_all(&packet).await;
+        link.quality()
+            .record_write(started.elapsed(), written.is_ok());
+        if let Err(e) = written {
+            tracing::warn!("Failed to write: {e}");
+            break;
+        }
+    }
+
+    tracing::info!("Disconnected from Flipper. Waiting for reconnection");
+    link.connection_closed();
+    true
+}
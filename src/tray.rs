@@ -0,0 +1,139 @@
+use crate::collectors::Sample;
+use crate::config::Config;
+use crate::transport::{ConnectionState, Link};
+use std::error::Error;
+use std::time::{Duration, Instant};
+use tao::event::Event;
+use tao::event_loop::{ControlFlow, EventLoopBuilder};
+use tray_icon::menu::{Menu, MenuEvent, MenuItem, PredefinedMenuItem};
+use tray_icon::{Icon, TrayIconBuilder};
+
+const ICON_SIZE: u32 = 16;
+// Flipper orange
+const ICON_COLOR: [u8; 4] = [0xFF, 0x82, 0x00, 0xFF];
+
+enum TrayEvent {
+    State(ConnectionState),
+    Values(String),
+}
+
+fn describe(sample: &Sample) -> String {
+    match &sample.system {
+        Some(system) => format!(
+            "CPU {}%  RAM {}%  GPU {}%",
+            system.cpu_usage, system.ram_usage, system.gpu_usage
+        ),
+        None => "No system info".to_owned(),
+    }
+}
+
+// The tray needs the main thread on macOS, so the backend moves to a runtime of its own
+pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
+    let event_loop = EventLoopBuilder::<TrayEvent>::with_user_event().build();
+    let proxy = event_loop.create_proxy();
+
+    let link = Link::new();
+    let (samples, _) = tokio::sync::broadcast::channel(16);
+
+    let backend_link = link.clone();
+    let runtime = tokio::runtime::Runtime::new()?;
+    let (quit_sender, quit_receiver) = tokio::sync::oneshot::channel::<()>();
+    let mut quit_sender = Some(quit_sender);
+    let mut backend = Some(std::thread::spawn(move || {
+        runtime.block_on(async move {
+            let mut state = backend_link.state();
+            let state_proxy = proxy.clone();
+            tokio::spawn(async move {
+                loop {
+                    let current = *state.borrow_and_update();
+                    if state_proxy.send_event(TrayEvent::State(current)).is_err() {
+                        return;
+                    }
+                    if state.changed().await.is_err() {
+                        return;
+                    }
+                }
+            });
+
+            let mut values = samples.subscribe();
+            tokio::spawn(async move {
+                while let Ok(sample) = values.recv().await {
+                    if proxy
+                        .send_event(TrayEvent::Values(describe(&sample)))
+                        .is_err()
+                    {
+                        return;
+                    }
+                }
+            });
+
+            let shutdown = async {
+                tokio::select! {
+                    _ = crate::shutdown_signal() => {}
+                    _ = quit_receiver => {}
+                }
+            };
+            if let Err(e) = crate::serve(backend_link, samples, config, false, shutdown).await {
+                tracing::error!("Backend stopped: {e}");
+            }
+        });
+    }));
+
+    let status = MenuItem::new("Scanning", false, None);
+    let values = MenuItem::new("No data yet", false, None);
+    let pause = MenuItem::new("Pause", true, None);
+    let quit = MenuItem::new("Quit", true, None);
+
+    let menu = Menu::new();
+    menu.append_items(&[
+        &status,
+        &values,
+        &PredefinedMenuItem::separator(),
+        &pause,
+        &quit,
+    ])?;
+
+    let icon = Icon::from_rgba(
+        ICON_COLOR.repeat((ICON_SIZE * ICON_SIZE) as usize),
+        ICON_SIZE,
+        ICON_SIZE,
+    )?;
+    // Has to stay alive for as long as the icon should be shown
+    let tray = TrayIconBuilder::new()
+        .with_menu(Box::new(menu))
+        .with_tooltip("Flipper PC Monitor")
+        .with_icon(icon)
+        .build()?;
+
+    event_loop.run(move |event, _, control_flow| {
+        // Menu clicks don't wake the event loop, so poll for them
+        *control_flow = ControlFlow::WaitUntil(Instant::now() + Duration::from_millis(100));
+
+        match event {
+            Event::UserEvent(TrayEvent::State(state)) => {
+                let text = format!("{state:?}");
+                status.set_text(&text);
+                let _ = tray.set_tooltip(Some(format!("Flipper PC Monitor: {text}")));
+            }
+            Event::UserEvent(TrayEvent::Values(text)) => values.set_text(text),
+            _ => {}
+        }
+
+        while let Ok(event) = MenuEvent::receiver().try_recv() {
+            if event.id == pause.id() {
+                let paused = !link.is_paused();
+                link.set_paused(paused);
+                pause.set_text(if paused { "Resume" } else { "Pause" });
+            } else if event.id == quit.id() {
+                // The backend sends the goodbye packet and disconnects before the process goes
+                if let Some(quit) = quit_sender.take() {
+                    let _ = quit.send(());
+                }
+                if let Some(backend) = backend.take() {
+                    let _ = backend.join();
+                }
+                *control_flow = ControlFlow::Exit;
+            }
+        }
+    })
+}
@@ -0,0 +1,168 @@
+// Sizes and rates as the Flipper shows them, tenths of a unit plus the unit's name
+
+use serde::Deserialize;
+
+const BINARY_UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+const IEC_UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+const SI_UNITS: [&str; 5] = ["B", "kB", "MB", "GB", "TB"];
+const MAX_EXP: u32 = BINARY_UNITS.len() as u32 - 1;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Units {
+    // Steps of 1024 labelled "KB", "MB", "GB", what older versions always sent
+    #[default]
+    Binary,
+    // Steps of 1024 labelled "KiB", "MiB", "GiB"
+    Iec,
+    // Steps of 1000 labelled "kB", "MB", "GB"
+    Si,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Scaled {
+    // Saturates at u16::MAX past 6553.5 of the largest unit
+    pub tenths: u16,
+    pub exp: u32,
+    pub unit: &'static str,
+}
+
+impl Units {
+    pub fn base(self) -> u64 {
+        match self {
+            Units::Binary | Units::Iec => 1024,
+            Units::Si => 1000,
+        }
+    }
+
+    pub fn unit_name(self, exp: u32) -> &'static str {
+        let names = match self {
+            Units::Binary => BINARY_UNITS,
+            Units::Iec => IEC_UNITS,
+            Units::Si => SI_UNITS,
+        };
+        names.get(exp as usize).copied().unwrap_or("UB")
+    }
+
+    // Picks the largest unit that keeps the value at or above 1.0, checked after rounding
+    // so 1023.96 KB becomes 1.0 MB rather than 1024.0 KB
+    pub fn scale(self, value: u64) -> Scaled {
+        let base = self.base();
+        let mut exp = 0;
+        while exp < MAX_EXP && round_tenths(value, base, exp) >= base * 10 {
+            exp += 1;
+        }
+
+        Scaled {
+            tenths: round_tenths(value, base, exp).min(u16::MAX as u64) as u16,
+            exp,
+            unit: self.unit_name(exp),
+        }
+    }
+}
+
+// value / base^exp in tenths, rounded half up
+pub fn round_tenths(value: u64, base: u64, exp: u32) -> u64 {
+    let divisor = (base as u128).pow(exp);
+    ((value as u128 * 10 + divisor / 2) / divisor) as u64
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TempUnit {
+    #[default]
+    Celsius,
+    Fahrenheit,
+}
+
+impl TempUnit {
+    // What the Flipper prints after the degree sign
+    pub fn symbol(self) -> u8 {
+        match self {
+            TempUnit::Celsius => b'C',
+            TempUnit::Fahrenheit => b'F',
+        }
+    }
+
+    // Collectors always report whole degrees Celsius. u8::MAX stays unknown, so anything
+    // above 123 °C saturates at 254 °F
+    pub fn convert(self, celsius: u8) -> u8 {
+        match (self, celsius) {
+            (_, u8::MAX) | (TempUnit::Celsius, _) => celsius,
+            (TempUnit::Fahrenheit, _) => {
+                ((celsius as u32 * 18 + 5) / 10 + 32).min(u8::MAX as u32 - 1) as u8
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scale(value: u64) -> (u16, u32) {
+        let scaled = Units::Binary.scale(value);
+        (scaled.tenths, scaled.exp)
+    }
+
+    #[test]
+    fn exact_powers_use_the_larger_unit() {
+        assert_eq!(scale(1024), (10, 1));
+        assert_eq!(scale(1024 * 1024), (10, 2));
+        assert_eq!(scale(1023), (10230, 0));
+    }
+
+    #[test]
+    fn rounds_half_up() {
+        // 1.25 KB and 1.35 KB
+        assert_eq!(scale(1280).0, 13);
+        assert_eq!(scale(1382).0, 13);
+        assert_eq!(scale(1383).0, 14);
+        assert_eq!(round_tenths(15, 10, 1), 15);
+        assert_eq!(round_tenths(5, 100, 1), 1);
+        assert_eq!(round_tenths(4, 100, 1), 0);
+    }
+
+    #[test]
+    fn rounding_up_to_the_base_moves_to_the_next_unit() {
+        // 1023.96 KB
+        let value = 1023 * 1024 + 983;
+        assert_eq!(scale(value), (10, 2));
+        assert_eq!(Units::Binary.scale(value).unit, "MB");
+    }
+
+    #[test]
+    fn saturates_past_the_largest_unit() {
+        let huge = 7000 * 1024u64.pow(4);
+        assert_eq!(scale(huge), (u16::MAX, 4));
+        assert_eq!(scale(u64::MAX).1, 4);
+    }
+
+    #[test]
+    fn zero_and_unit_names() {
+        assert_eq!(scale(0), (0, 0));
+        assert_eq!(Units::Binary.unit_name(0), "B");
+        assert_eq!(Units::Binary.unit_name(3), "GB");
+        assert_eq!(Units::Binary.unit_name(9), "UB");
+    }
+
+    #[test]
+    fn si_and_iec() {
+        let si = Units::Si.scale(1_500_000_000);
+        assert_eq!((si.tenths, si.unit), (15, "GB"));
+        assert_eq!(Units::Si.scale(1000).unit, "kB");
+
+        let iec = Units::Iec.scale(16 * 1024u64.pow(3));
+        assert_eq!((iec.tenths, iec.unit), (160, "GiB"));
+    }
+
+    #[test]
+    fn fahrenheit_rounds_and_keeps_unknown() {
+        assert_eq!(TempUnit::Fahrenheit.convert(0), 32);
+        assert_eq!(TempUnit::Fahrenheit.convert(37), 99);
+        assert_eq!(TempUnit::Fahrenheit.convert(100), 212);
+        assert_eq!(TempUnit::Fahrenheit.convert(130), 254);
+        assert_eq!(TempUnit::Fahrenheit.convert(u8::MAX), u8::MAX);
+        assert_eq!(TempUnit::Celsius.convert(42), 42);
+    }
+}